@@ -21,20 +21,24 @@ Before any organizational rules can be applied, the system must first build a co
 - *Goal*: Determine the most accurate creation timestamp.
 - *Priority Order*:
   1. *EXIF Metadata*: Use `DateTimeOriginal` from EXIF data.
-  2. *Filesystem Fallback*: If EXIF is absent, use the oldest of `mtime` or `ctime`.
+     For camera RAW files it is read from the RAW structure when the EXIF parser fails.
+  2. *Takeout Sidecar*: `photoTakenTime` from the file's Google Photos Takeout JSON sidecar.
+  3. *Filename*: A date in the file name (videos).
+  4. *Filesystem Fallback*: If EXIF is absent, use the oldest of `mtime` or `ctime`.
 
 === Stage 3: Spatial Analysis (The "Where")
 - *Goal*: Identify geographic coordinates of capture.
 - *Priority Order*:
   1. *EXIF GPS Data*
-  2. *Google Maps History* (fallback using Stage 2 timestamp)
+  2. *Takeout Sidecar*: `geoData` from the Google Photos Takeout JSON sidecar
+  3. *Google Maps History* (fallback using Stage 2 timestamp)
 
 === Stage 4: Data Augmentation & Enrichment
 - *Goal*: Expand raw data into variables for rule engine.
 - *Actions*:
   - *Temporal*: Derive year, month, weekday, etc.
   - *Spatial*: Reverse-geocode coordinates.
-  - *Technical*: Extract dimensions, duration, MIME type, etc.
+  - *Technical*: Extract dimensions, duration, MIME type, etc. RAW files (CR2, CR3, NEF, ARW, RAF, DNG, ORF, RW2, PEF) are detected by their headers and report the full sensor size.
   - *EXIF Metadata*: Expose ALL EXIF tags with proper types (numbers remain numeric).
 
 == 4. Data Context & Template Variables
@@ -56,10 +60,13 @@ Before any organizational rules can be applied, the system must first build a co
   [], [{time.ss}], [Seconds (00-59)], [05],
   [], [{time.weekday}], [Day of week], [Monday],
   [], [{time.timestamp}], [Unix timestamp], [1719590400],
+  [], [{time.source}], [Where the time came from], [exif],
+  [], [{time.original}], [Camera time before clock offset correction], [2024-06-28T17:00:00+02:00],
 
   [space], [{space.country}], [Country name], [Spain],
   [], [{space.country_code}], [2-letter country code], [ES],
   [], [{space.state}], [State/Province/Region], [Community of Madrid],
+  [], [{space.county}], [County/Province (admin2)], [Provincia de Madrid],
   [], [{space.city}], [City or town], [Madrid],
   [], [{space.district}], [District], [Centro],
   [], [{space.road}], [Street name], [Calle de Atocha],
@@ -76,25 +83,84 @@ Before any organizational rules can be applied, the system must first build a co
   [], [{source.extension}], [Lowercase extension], [jpg],
   [], [{source.original}], [Full original filename], [IMG_1234.JPG],
   [], [{source.size}], [File size (bytes)], [4194304],
+  [], [{source.mime}], [Detected MIME type], [image/jpeg],
+
+  [event], [{event.id}], [Event number in the run], [3],
+  [], [{event.name}], [Start date and main city], [2024-07-12 Lisbon],
+  [], [{event.start}], [First day of the event], [2024-07-12],
+  [], [{event.end}], [Last day of the event], [2024-07-14],
+  [], [{event.size}], [Files in the event], [42],
+
+  [trip], [{trip.id}], [Trip number in the run], [2],
+  [], [{trip.name}], [Main city or country], [Portugal],
+  [], [{trip.start}], [First day of the trip], [2024-07-12],
+  [], [{trip.end}], [Last day of the trip], [2024-07-19],
+  [], [{trip.day}], [Day of the trip, from 1], [3],
+
+  [group], [{group.id}], [RAW+JPEG or Live Photo group], [4],
+  [], [{group.role}], [primary, jpeg, live, raw or still], [live],
+  [], [{group.size}], [Files in the group], [2],
+
+  [takeout], [{takeout.description}], [Google Photos description], [Sunset],
+  [], [{takeout.people}], [People tagged, comma-separated], [Ana, Rui],
+  [], [{takeout.title}], [Original title], [IMG_0001.jpg],
+  [], [{takeout.taken}], [Date taken per Takeout], [2024-07-12],
+  [], [{takeout.favorited}], [Starred in Google Photos], [true],
+  [], [{takeout.json}], [Path of the JSON sidecar], [/in/IMG_0001.jpg.json],
+
+  [xmp], [{xmp.rating}], [Star rating (-1 rejected)], [4],
+  [], [{xmp.label}], [Color label], [Red],
+  [], [{xmp.title}], [Title], [Sunset],
+  [], [{xmp.keywords}], [Keywords, comma-separated], [beach, Lisbon],
+  [], [{xmp.regions}], [Region (face) names], [Ana, Rui],
+  [], [{xmp.source}], [embedded or sidecar], [sidecar],
+  [iptc], [{iptc.title}], [Object name], [Sunset],
+  [], [{iptc.headline}], [Headline], [Storm hits coast],
+  [], [{iptc.caption}], [Caption/abstract], [Waves at Nazaré],
+  [], [{iptc.keywords}], [Keywords, comma-separated], [storm, coast],
+  [], [{iptc.byline}], [Photographers], [Ana Silva],
+  [], [{iptc.credit}], [Credit line], [Lusa],
+  [], [{iptc.source}], [Source], [Lusa],
+  [], [{iptc.copyright}], [Copyright notice], [© Ana Silva],
+  [], [{iptc.city}], [City], [Nazaré],
+  [], [{iptc.sublocation}], [Sublocation], [Praia do Norte],
+  [], [{iptc.state}], [Province/state], [Leiria],
+  [], [{iptc.country}], [Country], [Portugal],
+  [], [{iptc.country_code}], [Country code as written], [PRT],
+  [camera], [{camera.make}], [Canonical make], [Nikon],
+  [], [{camera.model}], [Model without the make], [D850],
+  [], [{camera.serial}], [Body serial number], [3012345],
+  [], [{camera.lens}], [Lens model], [RF24-105mm F4 L IS USM],
+  [], [{camera.kind}], [phone, dslr, action_cam or drone], [dslr],
+  [image], [{image.width}], [Displayed width (rotated)], [3024],
+  [], [{image.height}], [Displayed height (rotated)], [4032],
+  [], [{image.megapixels}], [Megapixels, one decimal], [12.2],
+  [], [{image.aspect_ratio}], [Width over height], [0.75],
+  [], [{image.orientation_class}], [portrait, landscape or square], [portrait],
+  [], [{image.is_panorama}], [Panorama (2.5:1 or tagged)], [false],
 
   [type], [type], [Media type (condition)], [image],
+  [subtype], [{subtype}], [Finer media type], [raw],
   
   [meta], [{meta.Make}], [Camera manufacturer], [Canon],
   [], [{meta.Model}], [Camera model], [EOS 5D Mark IV],
-  [], [{meta.FNumber}], [Aperture (numeric)], [2.8],
-  [], [{meta.ISO}], [ISO speed (numeric)], [1600],
+  [], [{meta.FNumber}], [Aperture (rational)], [2.8],
+  [], [{meta.ISO}], [ISO speed (numeric, ExifTool name)], [1600],
   [], [{meta.FocalLength}], [Focal length (numeric)], [85],
-  [], [{meta.ExposureTime}], [Exposure time], [1/125],
+  [], [{meta.ExposureTime}], [Exposure time (rational)], [1/125],
   [], [{meta.LensModel}], [Lens model], [EF 85mm f/1.8],
   [], [{meta.ImageWidth}], [Width (numeric)], [4000],
   [], [{meta.ImageHeight}], [Height (numeric)], [3000],
   [], [{meta.Orientation}], [Orientation (numeric)], [1],
-  [], [{meta.DateTimeOriginal}], [Original date/time], [2024:07:18 21:30:05],
+  [], [{meta.DateTimeOriginal}], [Original date/time], [2024-07-18T21:30:05+02:00],
   [], [{meta.duration}], [Video duration (numeric)], [183.5],
   [], [{meta.*}], [Any EXIF tag by name], [(varies)],
 
   [special], [{special.md5_short}], [First 8 of MD5], [a1b2c3d4],
   [], [{special.count}], [Filename collision suffix], [\_1],
+  [], [{special.phash}], [Perceptual hash (dHash)], [f0e1d2c3b4a59687],
+  [], [{special.dup_group}], [Short MD5 of the best copy], [a1b2c3d4],
+  [], [{special.is_best}], [Best copy among duplicates], [true],
 )
 
 == 5. The Ruleset Engine
@@ -112,25 +178,31 @@ This system's core logic is defined with *rulesets*, which are pipeline stages.
 == 6. Rule and Action Definitions
 
 A rule is:
-- *condition*: Boolean expression using dot notation (e.g., `type == "video"`, `meta.FNumber <= 2.8`, `space.country == "ES"`). Use `true` to always match.
-- *template*: Defines output path using variables.
+- *condition*: Boolean expression using dot notation (e.g., `type == "video"`, `meta.FNumber <= 2.8`, `space.country_code == "ES"`, `distance_from("Home") > 100`). Use `true` to always match. Capture times are available as `time.timestamp` (Unix seconds) and `time.datetime`, which compares against `date("2024-07-01")`, `date(2024, 7, 1)` and `now()` and supports `time.between(start, end)` and `time.days_since()`. When a `clock_offsets` entry corrected the camera clock, `time.original` holds the uncorrected EXIF time, also `{time.original}` in templates. Location functions: `in_place(name)`, `distance_from(name)`, `distance_km(lat, lon, lat2, lon2)`, `within_bbox([lat, lon,] south, west, north, east)` and `bearing(lat, lon, lat2, lon2)`.
+- *template*: Defines output path using variables. When `groups` is configured, a RAW and its JPEG or the halves of a Live Photo form one asset: its files share the best time and location among them, and all of them follow the rule matched by the primary file.
 - *action*: Either built-in or custom.
 
 === Built-in Actions
 - `move`, `copy`, `symlink`, `hardlink`
 
+Sidecar files matching the `sidecars` patterns (by default `{name}.xmp`, `{original}.xmp`, `{name}.aae`, `{name}.thm`, `{original}.supplemental-metadata.json` and `{original}.json`) get the same built-in action as their media file, into the destination directory and renamed along with it.
+
 === Custom Actions
 Commands that use template variables. Custom actions may access `{target.path}` for resolved output.
 
 === Condition Expressions
 Conditions use Rhai expression syntax with dot notation for accessing nested values:
-- Media type: `type == "image"` or `type == "video"`
-- Location: `space.city == "Madrid"`, `space.country == "ES"`
+- Media type: `type == "image"`, `"video"`, `"audio"` or `"document"`; `subtype == "raw"`, `"heic"`, `"gif"`, `"screenshot"`, `"scan"`, `"audio"` or `"document"`. Only images and videos are processed by default; audio and documents need `media_types: { include: [audio, document] }` or `include_non_media: true`
+- Location: `space.city == "Madrid"`, `space.country == "Spain"`, `space.country_code == "ES"`
 - Time: `time.yyyy == "2024"`, `time.weekday == "Saturday"`
 - EXIF metadata: `meta.Make == "Canon"`, `meta.ISO >= 3200`, `meta.FNumber <= 2.8`
+- XMP: `xmp.rating >= 4`, `xmp.label == "Red"`, `"beach" in xmp.keywords` (embedded XMP or `.xmp` sidecar, sidecar values first)
+- Camera: `camera.make == "Nikon"`, `camera.kind == "drone"` (normalized make and model; the `cameras:` section with `makes` and `models` entries extends the built-in table)
+- Image shape: `image.orientation_class == "portrait"`, `image.is_panorama`, `image.megapixels >= 20` (sizes after the EXIF Orientation; missing when unknown)
+- IPTC: `"storm" in iptc.keywords`, `iptc.city == "Nazaré"` (APP13 IIM; with `iptc_location: true` its location names replace reverse-geocoded `space` values)
 - Complex: `type == "image" && meta.Make != () && space.lat != 0`
 
-Note: Missing EXIF tags return empty values (safe to reference). Numeric EXIF values maintain their types for proper comparisons.
+Note: Missing EXIF tags return empty values (safe to reference). Numeric EXIF values maintain their types for proper comparisons. Rationals (`ExposureTime`, `FNumber`) compare with numbers by value or with `rational("1/60")`, have `.num`, `.den` and `.value`, and render as `1/125` or `2.8`; EXIF dates are datetimes; multi-value tags are arrays, comma-separated in templates. Tags carry ExifTool names (`ISO`, `LensModel`, `SerialNumber`, vendor tags such as `XPKeywords`) and are also available qualified by ExifTool group, e.g. `meta["GPS:GPSAltitude"]` or `meta["IFD1:ImageWidth"]` (thumbnail), `{meta.GPS:GPSAltitude}` in templates; thumbnail values never shadow the main image's plain names. Unknown tags appear as `Tag_<code>`. With an `exiftool:` section (`command`, default `exiftool`; `args`, default `-json -n -G`; `mode`, `fallback` or `always`), a persistent `-stay_open` exiftool process per worker adds its tags under their exiftool group-qualified keys and, when not already set, their plain names (EXIF over XMP over QuickTime over other groups; zoneless QuickTime dates are UTC), and supplies the capture time and GPS position files lacked.
 
 == 7. Command Line Interface
 
@@ -194,6 +266,7 @@ rulesets:
   Scanner-Daemon:
     input: "watch:/home/nil/scans/inbox"
     rules:
-      - action: move
+      - condition: 'type == "document" || subtype == "scan"'
+        action: move
         template: "/mnt/media/Documents/Scans/{time.yyyy}/{time.yyyy}-{time.mm}-{time.dd}_{source.name}.{source.extension}"
 ```
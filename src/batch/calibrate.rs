@@ -0,0 +1,624 @@
+// Calibrate Module
+// Estimates how far each camera's clock is off, using files whose time can be
+// trusted: GPS-tagged photos from phones (network-synced clocks) shot during the
+// same bursts of activity, or location history positions matching a camera's own
+// GPS tags or, for cameras without GPS, the time zone it was carried in. The
+// estimates feed the `clock_offsets:` configuration section.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, Offset, TimeZone};
+use chrono_tz::Tz;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use super::BatchEntry;
+use crate::metadata::clock::{camera_serial, format_offset, meta_text};
+use crate::metadata::geo::haversine_km;
+use crate::metadata::timezone::timezone_at;
+use crate::metadata::{Datetime, LocationHistory};
+
+/// Width of the histogram bins used to find the most common time difference.
+const BIN_SECS: i64 = 60;
+
+/// Farthest in time a location history point may be from a shot to tell the time
+/// zone it was taken in.
+const ZONE_MATCH_MS: i64 = 3600 * 1000;
+
+/// Identifies a camera body as well as its EXIF metadata allows.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CameraKey {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+}
+
+impl CameraKey {
+    pub fn from_meta(meta: &HashMap<String, rhai::Dynamic>) -> Option<Self> {
+        let key = Self {
+            make: meta_text(meta, "Make"),
+            model: meta_text(meta, "Model"),
+            serial: camera_serial(meta),
+        };
+        (key.make.is_some() || key.model.is_some()).then_some(key)
+    }
+}
+
+impl fmt::Display for CameraKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = [&self.make, &self.model]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match &self.serial {
+            Some(serial) => write!(f, "{name} (#{serial})"),
+            None => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Matched against GPS-tagged files of other cameras taken around the same time
+    Bursts,
+    /// Matched the camera's own GPS tags against location history positions, or
+    /// the time zone its clock was set to against the zone it was carried in
+    LocationHistory,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Method::Bursts => write!(f, "bursts"),
+            Method::LocationHistory => write!(f, "location history"),
+        }
+    }
+}
+
+/// The estimated correction for one camera: `offset` is added to its clock.
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub camera: CameraKey,
+    pub offset: Duration,
+    /// Number of matches supporting the estimate
+    pub matches: usize,
+    pub method: Method,
+}
+
+#[derive(Debug, Clone)]
+pub struct CalibrateOptions {
+    /// Largest clock error considered, in hours
+    pub max_offset_hours: i64,
+    /// Fewest matches needed to trust an estimate
+    pub min_matches: usize,
+    /// Farthest a location history position may be from a GPS tag to match it
+    pub max_distance_m: f64,
+}
+
+impl Default for CalibrateOptions {
+    fn default() -> Self {
+        Self {
+            max_offset_hours: 14,
+            min_matches: 3,
+            max_distance_m: 200.0,
+        }
+    }
+}
+
+struct Sample {
+    local: Datetime,
+    timestamp_ms: i64,
+    position: Option<(f64, f64)>,
+    trusted: bool,
+}
+
+impl Sample {
+    /// The wall-clock time, in seconds, as if it were UTC.
+    fn wall_secs(&self) -> i64 {
+        self.local.local().and_utc().timestamp()
+    }
+}
+
+/// Estimates the clock offset of every camera in the batch that has enough matches.
+pub fn estimate_offsets(
+    entries: &[BatchEntry],
+    location_history: Option<&LocationHistory>,
+    options: &CalibrateOptions,
+) -> Vec<Estimate> {
+    let mut cameras: BTreeMap<CameraKey, Vec<Sample>> = BTreeMap::new();
+    for entry in entries {
+        let context = &entry.context;
+        let (Some(camera), Some(local), Some(timestamp)) = (
+            CameraKey::from_meta(&context.meta),
+            Datetime::from_time(&context.time),
+            context.time.timestamp,
+        ) else {
+            continue;
+        };
+
+        let from_exif = context.space.source == "exif";
+        cameras.entry(camera).or_default().push(Sample {
+            local,
+            timestamp_ms: timestamp.timestamp_millis(),
+            position: context.space.position().filter(|_| from_exif),
+            trusted: from_exif,
+        });
+    }
+
+    let max_offset_ms = options.max_offset_hours * 3600 * 1000;
+    let max_offset_secs = max_offset_ms / 1000;
+
+    // Trusted files of every camera by wall-clock time, scanned through a window
+    // sliding along each camera's own files
+    let mut references: Vec<(i64, &CameraKey)> = cameras
+        .iter()
+        .flat_map(|(camera, samples)| {
+            samples
+                .iter()
+                .filter(|sample| sample.trusted)
+                .map(move |sample| (sample.wall_secs(), camera))
+        })
+        .collect();
+    references.sort_unstable();
+
+    let mut estimates = Vec::new();
+    for (camera, samples) in &cameras {
+        // Wall-clock differences to every trusted file of another camera nearby in time
+        let mut burst_deltas = Vec::new();
+        let mut wall_secs: Vec<i64> = samples.iter().map(Sample::wall_secs).collect();
+        wall_secs.sort_unstable();
+        let mut first = 0;
+        for secs in wall_secs {
+            while first < references.len() && references[first].0 < secs - max_offset_secs {
+                first += 1;
+            }
+            burst_deltas.extend(
+                references[first..]
+                    .iter()
+                    .take_while(|(reference, _)| *reference <= secs + max_offset_secs)
+                    .filter(|(_, other)| *other != camera)
+                    .map(|(reference, _)| reference - secs),
+            );
+        }
+
+        // Time differences to the location history point closest to each GPS tag
+        let mut history_deltas = Vec::new();
+        let has_gps = samples.iter().any(|sample| sample.position.is_some());
+        if let Some(history) = location_history
+            && !has_gps
+        {
+            history_deltas.extend(
+                samples
+                    .iter()
+                    .filter_map(|sample| zone_delta(history, sample)),
+            );
+        } else if let Some(history) = location_history {
+            for sample in samples {
+                let Some((lat, lon)) = sample.position else {
+                    continue;
+                };
+                let start = (sample.timestamp_ms - max_offset_ms).max(0) as u64;
+                let end = (sample.timestamp_ms + max_offset_ms).max(0) as u64;
+                let nearest = history
+                    .points_between(start, end)
+                    .iter()
+                    .map(|point| {
+                        let distance_m = 1000.0
+                            * haversine_km(
+                                lat,
+                                lon,
+                                point.latitude_e7 as f64 / 1e7,
+                                point.longitude_e7 as f64 / 1e7,
+                            );
+                        (distance_m, point.timestamp_ms)
+                    })
+                    .filter(|(distance_m, _)| *distance_m <= options.max_distance_m)
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                if let Some((_, timestamp_ms)) = nearest {
+                    history_deltas.push((timestamp_ms as i64 - sample.timestamp_ms) / 1000);
+                }
+            }
+        }
+
+        let best = [
+            (Method::LocationHistory, histogram_peak(&history_deltas)),
+            (Method::Bursts, histogram_peak(&burst_deltas)),
+        ]
+        .into_iter()
+        .filter_map(|(method, peak)| peak.map(|(offset, matches)| (method, offset, matches)))
+        .filter(|(_, _, matches)| *matches >= options.min_matches)
+        .max_by_key(|(_, _, matches)| *matches);
+
+        if let Some((method, offset, matches)) = best {
+            estimates.push(Estimate {
+                camera: camera.clone(),
+                offset: Duration::seconds(offset),
+                matches,
+                method,
+            });
+        }
+    }
+
+    estimates
+}
+
+/// For a camera without GPS that records its UTC offset, how far its wall clock is
+/// from local time where the location history placed it at the shot's instant, in
+/// seconds. Cameras left on their home time zone abroad are off by whole hours.
+fn zone_delta(history: &LocationHistory, sample: &Sample) -> Option<i64> {
+    let camera_offset = sample.local.offset()?;
+    let instant_ms = sample.timestamp_ms.max(0) as u64;
+    let (before, after) = history.find_closest_points(instant_ms);
+    let point = [before, after]
+        .into_iter()
+        .flatten()
+        .min_by_key(|point| point.timestamp_ms.abs_diff(instant_ms))
+        .filter(|point| point.timestamp_ms.abs_diff(instant_ms) <= ZONE_MATCH_MS as u64)?;
+
+    let tz: Tz = timezone_at(
+        point.latitude_e7 as f64 / 1e7,
+        point.longitude_e7 as f64 / 1e7,
+    )?
+    .parse()
+    .ok()?;
+    let instant = DateTime::from_timestamp_millis(sample.timestamp_ms)?;
+    let local_offset = tz.offset_from_utc_datetime(&instant.naive_utc()).fix();
+    Some(i64::from(local_offset.local_minus_utc() - camera_offset))
+}
+
+/// Finds the most common time difference, in seconds: the densest window of three
+/// adjacent bins, refined to the median of the differences inside it. Returns the
+/// difference and the number of differences supporting it.
+fn histogram_peak(deltas: &[i64]) -> Option<(i64, usize)> {
+    let mut bins: HashMap<i64, usize> = HashMap::new();
+    for delta in deltas {
+        *bins.entry(delta.div_euclid(BIN_SECS)).or_default() += 1;
+    }
+
+    let window = |bin: i64| {
+        (bin - 1..=bin + 1)
+            .filter_map(|b| bins.get(&b))
+            .sum::<usize>()
+    };
+    let peak = bins
+        .keys()
+        .map(|&bin| (window(bin), -bin.abs(), bin))
+        .max()
+        .map(|(_, _, bin)| bin)?;
+
+    let mut inside: Vec<i64> = deltas
+        .iter()
+        .copied()
+        .filter(|delta| (delta.div_euclid(BIN_SECS) - peak).abs() <= 1)
+        .collect();
+    inside.sort_unstable();
+    Some((inside[inside.len() / 2], inside.len()))
+}
+
+/// Adds or updates a `clock_offsets:` entry for every estimate in a YAML
+/// configuration. Entries restricted to a date range are left alone. Only the
+/// `clock_offsets:` section is edited in place, so comments and formatting of
+/// the rest of the file, and of its other entries, are kept.
+pub fn update_config(yaml: &str, estimates: &[Estimate]) -> Result<String> {
+    let config: Value =
+        serde_yaml::from_str(yaml).with_context(|| "Failed to parse configuration")?;
+    let root = config
+        .as_mapping()
+        .with_context(|| "Configuration is not a mapping")?;
+    let entries: Vec<Value> = match root.get("clock_offsets") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Sequence(entries)) => entries.clone(),
+        Some(_) => bail!("clock_offsets is not a list"),
+    };
+
+    let mut lines: Vec<String> = yaml.lines().map(str::to_string).collect();
+    let header = lines
+        .iter()
+        .position(|line| line.starts_with("clock_offsets:"));
+    let header = match header {
+        Some(header) if block_list(&lines[header]) => header,
+        // Written inline ("clock_offsets: []"): start a block list in its place
+        Some(header) => {
+            lines[header] = "clock_offsets:".to_string();
+            header
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("clock_offsets:".to_string());
+            lines.len() - 1
+        }
+    };
+
+    // The section runs until the next line at the left margin other than a list item
+    let end = lines[header + 1..]
+        .iter()
+        .position(|line| !line.is_empty() && !line.starts_with([' ', '\t', '-']))
+        .map_or(lines.len(), |position| header + 1 + position);
+    let indentation = |line: &str| line[..line.len() - line.trim_start().len()].to_string();
+    let indent = (header + 1..end)
+        .find(|&index| lines[index].trim_start().starts_with("- "))
+        .map_or("  ".to_string(), |index| indentation(&lines[index]));
+    // Entries start with a dash at the section's indentation; nested lists are deeper
+    let items: Vec<usize> = (header + 1..end)
+        .filter(|&index| {
+            lines[index].trim_start().starts_with("- ") && indentation(&lines[index]) == indent
+        })
+        .collect();
+
+    let mut updates: Vec<(usize, String)> = Vec::new();
+    let mut appended: Vec<String> = Vec::new();
+    for estimate in estimates {
+        let camera = &estimate.camera;
+        let fields = [
+            ("make", &camera.make),
+            ("model", &camera.model),
+            ("serial", &camera.serial),
+        ];
+        let offset = format!("\"{}\"", format_offset(estimate.offset));
+
+        let existing = entries.iter().position(|entry| {
+            entry.as_mapping().is_some_and(|entry| {
+                fields
+                    .iter()
+                    .all(|(key, value)| entry.get(*key).and_then(Value::as_str) == value.as_deref())
+                    && !entry.contains_key("from")
+                    && !entry.contains_key("until")
+            })
+        });
+
+        match existing.and_then(|index| items.get(index).map(|&start| (index, start))) {
+            Some((index, start)) => {
+                let stop = items.get(index + 1).copied().unwrap_or(end);
+                let line = (start..stop).find(|&line| {
+                    lines[line]
+                        .trim_start()
+                        .trim_start_matches("- ")
+                        .starts_with("offset:")
+                });
+                if let Some(line) = line {
+                    updates.push((line, offset));
+                }
+            }
+            None => {
+                let mut prefix = format!("{indent}- ");
+                for (key, value) in fields {
+                    if let Some(value) = value {
+                        appended.push(format!("{prefix}{key}: {}", yaml_scalar(value)?));
+                        prefix = format!("{indent}  ");
+                    }
+                }
+                appended.push(format!("{prefix}offset: {offset}"));
+            }
+        }
+    }
+
+    for (line, offset) in updates {
+        let text = &lines[line];
+        let key = text.find("offset:").unwrap_or(0) + "offset:".len();
+        let rest = &text[key..];
+        let comment = rest
+            .find(" #")
+            .map(|position| rest[rest[..position].trim_end().len()..].to_string())
+            .unwrap_or_default();
+        lines[line] = format!("{} {offset}{comment}", &text[..key]);
+    }
+
+    // New entries go after the last entry, before any blank lines ending the section
+    let last = (header..end)
+        .rev()
+        .find(|&index| !lines[index].trim().is_empty())
+        .unwrap_or(header);
+    lines.splice(last + 1..last + 1, appended);
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    Ok(updated)
+}
+
+/// Whether a `clock_offsets:` line leaves its value to the lines below.
+fn block_list(line: &str) -> bool {
+    let value = line["clock_offsets:".len()..].trim();
+    value.is_empty() || value.starts_with('#')
+}
+
+fn yaml_scalar(value: &str) -> Result<String> {
+    let text = serde_yaml::to_string(&Value::String(value.to_string()))
+        .with_context(|| "Failed to write configuration")?;
+    Ok(text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MediaContext;
+    use crate::metadata::location_history::LocationPoint;
+    use chrono::{DateTime, TimeZone, Utc};
+    use rhai::Dynamic;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 7, 12, h, m, s).unwrap()
+    }
+
+    fn entry(model: &str, time: DateTime<Utc>, gps: Option<(f64, f64)>) -> BatchEntry {
+        let mut context = MediaContext::default();
+        context
+            .meta
+            .insert("Model".into(), Dynamic::from(model.to_string()));
+        context.time.timestamp = Some(time);
+        if let Some((lat, lon)) = gps {
+            context.space.lat = lat;
+            context.space.lon = lon;
+            context.space.source = "exif".to_string();
+        }
+        BatchEntry {
+            path: model.into(),
+            context,
+        }
+    }
+
+    #[test]
+    fn test_histogram_peak() {
+        assert_eq!(histogram_peak(&[]), None);
+        assert_eq!(
+            histogram_peak(&[-3600, 200, 205, 210, 5000]),
+            Some((205, 3))
+        );
+    }
+
+    #[test]
+    fn test_estimate_from_bursts() {
+        // The DSLR clock is 3 minutes 20 seconds behind the phone
+        let mut entries = Vec::new();
+        for (h, m) in [(10, 0), (10, 30), (13, 15), (18, 40)] {
+            entries.push(entry("Pixel 8", at(h, m, 0), Some((38.72, -9.14))));
+            entries.push(entry("EOS R6", at(h, m, 0) - Duration::seconds(200), None));
+            entries.push(entry("EOS R6", at(h, m, 30) - Duration::seconds(200), None));
+        }
+
+        let estimates = estimate_offsets(&entries, None, &CalibrateOptions::default());
+        let dslr = estimates
+            .iter()
+            .find(|e| e.camera.model.as_deref() == Some("EOS R6"))
+            .unwrap();
+        assert_eq!(dslr.method, Method::Bursts);
+        assert!(
+            (dslr.offset.num_seconds() - 200).abs() <= 30,
+            "{:?}",
+            dslr.offset
+        );
+        assert!(dslr.matches >= 4);
+    }
+
+    #[test]
+    fn test_estimate_from_location_history() {
+        // A GPS camera whose clock runs one hour ahead, e.g. never switched from DST
+        let stops = [(40.41, -3.70), (40.42, -3.69), (40.45, -3.68)];
+        let mut points = Vec::new();
+        let mut entries = Vec::new();
+        for (i, (lat, lon)) in stops.iter().enumerate() {
+            let time = at(9 + 2 * i as u32, 0, 0);
+            points.push(LocationPoint {
+                timestamp_ms: time.timestamp_millis() as u64,
+                latitude_e7: (lat * 1e7) as i32,
+                longitude_e7: (lon * 1e7) as i32,
+            });
+            entries.push(entry(
+                "Camera GPS",
+                time + Duration::hours(1),
+                Some((*lat, *lon)),
+            ));
+        }
+        let history = LocationHistory::from_points(points);
+
+        let estimates = estimate_offsets(&entries, Some(&history), &CalibrateOptions::default());
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(estimates[0].method, Method::LocationHistory);
+        assert_eq!(estimates[0].offset, Duration::hours(-1));
+        assert_eq!(estimates[0].matches, 3);
+    }
+
+    #[test]
+    fn test_estimate_from_time_zones() {
+        // A camera without GPS still on Madrid summer time (+02:00) in Lisbon (+01:00)
+        let mut points = Vec::new();
+        let mut entries = Vec::new();
+        for hour in [9, 12, 15, 18] {
+            let time = at(hour, 0, 0);
+            points.push(LocationPoint {
+                timestamp_ms: time.timestamp_millis() as u64 - 600_000,
+                latitude_e7: 387_200_000,
+                longitude_e7: -91_400_000,
+            });
+            let mut camera = entry("EOS R6", time, None);
+            camera.context.time.utc_offset_secs = 7200;
+            entries.push(camera);
+        }
+        let history = LocationHistory::from_points(points);
+
+        let estimates = estimate_offsets(&entries, Some(&history), &CalibrateOptions::default());
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(estimates[0].method, Method::LocationHistory);
+        assert_eq!(estimates[0].offset, Duration::hours(-1));
+        assert_eq!(estimates[0].matches, 4);
+    }
+
+    #[test]
+    fn test_update_config() {
+        let camera = |model: &str| CameraKey {
+            make: Some("Canon".to_string()),
+            model: Some(model.to_string()),
+            serial: None,
+        };
+        let estimates = [
+            Estimate {
+                camera: camera("EOS R6"),
+                offset: Duration::seconds(200),
+                matches: 5,
+                method: Method::Bursts,
+            },
+            Estimate {
+                camera: camera("EOS R5"),
+                offset: Duration::hours(-1),
+                matches: 5,
+                method: Method::Bursts,
+            },
+        ];
+
+        let yaml = "\
+# Pipeline
+rulesets: []  # none yet
+
+# Camera clocks
+clock_offsets:
+  # The R6 drifts
+  - make: Canon
+    model: EOS R6
+    offset: \"+00:01:00\"  # measured in May
+  - make: Canon
+    model: EOS R6
+    offset: \"+01:00\"
+    from: \"2024-03-31\"
+
+# Custom actions
+actions: {}
+";
+        let text = update_config(yaml, &estimates).unwrap();
+        let updated: Value = serde_yaml::from_str(&text).unwrap();
+
+        let offsets = updated["clock_offsets"].as_sequence().unwrap();
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[0]["offset"], "+00:03:20");
+        assert_eq!(offsets[1]["offset"], "+01:00");
+        assert_eq!(offsets[2]["model"], "EOS R5");
+        assert_eq!(offsets[2]["offset"], "-01:00:00");
+        assert!(updated["actions"].is_mapping());
+
+        // Comments and the other sections are untouched
+        for comment in [
+            "# Pipeline",
+            "rulesets: []  # none yet",
+            "  # The R6 drifts",
+            "    offset: \"+00:03:20\"  # measured in May",
+            "# Custom actions",
+        ] {
+            assert!(text.lines().any(|line| line == comment), "{text}");
+        }
+        assert!(
+            text.contains(
+                "  - make: Canon\n    model: EOS R5\n    offset: \"-01:00:00\"\n\n# Custom"
+            )
+        );
+
+        // Configurations without the section get one
+        for yaml in ["rulesets: []\n", "rulesets: []\nclock_offsets: []\n"] {
+            let updated: Value =
+                serde_yaml::from_str(&update_config(yaml, &estimates).unwrap()).unwrap();
+            assert_eq!(updated["clock_offsets"].as_sequence().unwrap().len(), 2);
+            assert!(updated["rulesets"].is_sequence());
+        }
+    }
+}
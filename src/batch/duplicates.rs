@@ -0,0 +1,277 @@
+// Duplicates Module
+// Groups byte-identical files and near-duplicates (same perceptual hash within a
+// distance) and picks the best copy of each group, so rules can file the others
+// separately.
+
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use super::{BatchEntry, find, union};
+use crate::metadata::context::MediaContext;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateConfig {
+    /// Largest perceptual hash distance (bits out of 64) between near-duplicates
+    #[serde(default = "default_max_distance")]
+    pub max_distance: u32,
+}
+
+fn default_max_distance() -> u32 {
+    6
+}
+
+fn pixels(context: &MediaContext) -> i64 {
+    let dimension = |tags: [&str; 2]| {
+        tags.iter()
+            .find_map(|tag| context.meta.get(*tag).and_then(|v| v.as_int().ok()))
+            .unwrap_or(0)
+    };
+    dimension(["ExifImageWidth", "ImageWidth"]) * dimension(["ExifImageHeight", "ImageHeight"])
+}
+
+// Orders copies from worst to best
+fn quality(entry: &BatchEntry) -> (i64, u64, Reverse<&Utf8PathBuf>) {
+    let context = &entry.context;
+    (pixels(context), context.source.size, Reverse(&entry.path))
+}
+
+// Pairs of positions in `hashes` within `max_distance` bits of each other. Hashes
+// that close agree exactly on at least one of `max_distance + 1` bands, so only
+// hashes sharing a band are compared.
+fn near_pairs(hashes: &[(usize, u64)], max_distance: u32) -> Vec<(usize, usize)> {
+    let bands = (max_distance + 1).min(64);
+    let mut buckets: HashMap<(u32, u64), Vec<usize>> = HashMap::new();
+    for (position, &(_, hash)) in hashes.iter().enumerate() {
+        for band in 0..bands {
+            let start = band * 64 / bands;
+            let end = (band + 1) * 64 / bands;
+            let mask = if end - start == 64 {
+                u64::MAX
+            } else {
+                ((1u64 << (end - start)) - 1) << start
+            };
+            buckets
+                .entry((band, hash & mask))
+                .or_default()
+                .push(position);
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    for members in buckets.values() {
+        for (i, &a) in members.iter().enumerate() {
+            for &b in &members[i + 1..] {
+                if (hashes[a].1 ^ hashes[b].1).count_ones() <= max_distance {
+                    pairs.insert((a, b));
+                }
+            }
+        }
+    }
+    pairs.into_iter().collect()
+}
+
+/// Marks duplicate files: every member of a group gets the same `dup_group` (the
+/// short MD5 of the best copy) and only the best copy keeps `is_best`. The best copy
+/// has the most pixels, then the largest file, then the path sorting first. Near
+/// duplicates are all within `max_distance` of each other, so a chain of similar
+/// images does not collapse into one group. Returns the number of groups.
+pub fn group_duplicates(entries: &mut [BatchEntry], config: &DuplicateConfig) -> usize {
+    let mut parents: Vec<usize> = (0..entries.len()).collect();
+
+    // Byte-identical files share an MD5
+    let mut first_with_md5: HashMap<&str, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let md5 = entry.context.special.md5.as_str();
+        if md5.is_empty() {
+            continue;
+        }
+        match first_with_md5.get(md5) {
+            Some(&first) => union(&mut parents, first, index),
+            None => {
+                first_with_md5.insert(md5, index);
+            }
+        }
+    }
+
+    // Near-duplicates are compared once per set of identical copies
+    let mut hashes: Vec<(usize, u64)> = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if find(&mut parents, index) != index {
+            continue;
+        }
+        if let Ok(hash) = u64::from_str_radix(&entry.context.special.phash, 16) {
+            hashes.push((index, hash));
+        }
+    }
+    let mut near: Vec<Vec<usize>> = vec![Vec::new(); hashes.len()];
+    for (a, b) in near_pairs(&hashes, config.max_distance) {
+        near[a].push(b);
+        near[b].push(a);
+    }
+
+    // The best copies seed groups; closer candidates join first, and only while
+    // they are within `max_distance` of every member
+    let distance = |a: usize, b: usize| (hashes[a].1 ^ hashes[b].1).count_ones();
+    let mut seeds: Vec<usize> = (0..hashes.len()).collect();
+    seeds.sort_by(|&a, &b| quality(&entries[hashes[b].0]).cmp(&quality(&entries[hashes[a].0])));
+    let mut grouped = vec![false; hashes.len()];
+    for seed in seeds {
+        if grouped[seed] {
+            continue;
+        }
+        grouped[seed] = true;
+
+        let mut candidates = near[seed].clone();
+        candidates.sort_by_key(|&candidate| (distance(seed, candidate), candidate));
+        let mut members = vec![seed];
+        for candidate in candidates {
+            if !grouped[candidate]
+                && members
+                    .iter()
+                    .all(|&member| distance(member, candidate) <= config.max_distance)
+            {
+                grouped[candidate] = true;
+                members.push(candidate);
+                union(&mut parents, hashes[seed].0, hashes[candidate].0);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    for index in 0..entries.len() {
+        let root = find(&mut parents, index);
+        groups[root].push(index);
+    }
+
+    let mut count = 0;
+    for members in groups.iter().filter(|members| members.len() > 1) {
+        let best = *members
+            .iter()
+            .max_by_key(|&&index| quality(&entries[index]))
+            .unwrap();
+        let dup_group = entries[best].context.special.md5_short.clone();
+
+        for &index in members {
+            let special = &mut entries[index].context.special;
+            special.dup_group = dup_group.clone();
+            special.is_best = index == best;
+        }
+        count += 1;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhai::Dynamic;
+
+    fn entry(name: &str, md5: &str, phash: &str, width: i64, size: u64) -> BatchEntry {
+        let mut context = MediaContext::default();
+        context.special.md5 = md5.to_string();
+        context.special.md5_short = md5.chars().take(8).collect();
+        context.special.phash = phash.to_string();
+        context.special.is_best = true;
+        context.source.size = size;
+        context
+            .meta
+            .insert("ImageWidth".into(), Dynamic::from(width));
+        context
+            .meta
+            .insert("ImageHeight".into(), Dynamic::from(width * 3 / 4));
+        BatchEntry {
+            path: name.into(),
+            context,
+        }
+    }
+
+    #[test]
+    fn test_groups_near_duplicates() {
+        let mut entries = vec![
+            entry(
+                "IMG_0001.jpg",
+                "aaaaaaaa01",
+                "f0f0f0f0f0f0f0f0",
+                4000,
+                5_000_000,
+            ),
+            entry(
+                "whatsapp.jpg",
+                "bbbbbbbb02",
+                "f0f0f0f0f0f0f0f1",
+                1600,
+                300_000,
+            ),
+            entry(
+                "copy.jpg",
+                "aaaaaaaa01",
+                "f0f0f0f0f0f0f0f0",
+                4000,
+                5_000_000,
+            ),
+            entry(
+                "other.jpg",
+                "cccccccc03",
+                "0f0f0f0f0f0f0f0f",
+                4000,
+                5_000_000,
+            ),
+            entry("video.mp4", "dddddddd04", "", 0, 9_000_000),
+        ];
+
+        let count = group_duplicates(&mut entries, &DuplicateConfig { max_distance: 6 });
+        assert_eq!(count, 1);
+
+        for index in [0, 1, 2] {
+            assert_eq!(entries[index].context.special.dup_group, "aaaaaaaa");
+        }
+        // Identical copies tie on quality; the path sorting first wins
+        assert!(entries[0].context.special.is_best);
+        assert!(!entries[2].context.special.is_best);
+        assert!(!entries[1].context.special.is_best);
+
+        assert_eq!(entries[3].context.special.dup_group, "");
+        assert!(entries[3].context.special.is_best);
+        assert!(entries[4].context.special.is_best);
+    }
+
+    #[test]
+    fn test_near_duplicates_do_not_chain() {
+        // A and C are each 6 bits from B but 12 bits from each other
+        let mut entries = vec![
+            entry("a.jpg", "aaaaaaaa01", "0000000000000000", 1600, 300_000),
+            entry("b.jpg", "bbbbbbbb02", "000000000000003f", 4000, 5_000_000),
+            entry("c.jpg", "cccccccc03", "0000000000000fff", 1200, 200_000),
+        ];
+
+        let count = group_duplicates(&mut entries, &DuplicateConfig { max_distance: 6 });
+        assert_eq!(count, 1);
+
+        let special = |index: usize| &entries[index].context.special;
+        assert_eq!(special(0).dup_group, "bbbbbbbb");
+        assert_eq!(special(1).dup_group, "bbbbbbbb");
+        assert!(special(1).is_best);
+        assert!(!special(0).is_best);
+        assert_eq!(special(2).dup_group, "");
+    }
+
+    #[test]
+    fn test_near_pairs() {
+        let hashes = [
+            (0, 0u64),
+            (1, 0x0101_0101_0101_0101),
+            (2, 0xffff_ffff_0000_0000),
+            (3, u64::MAX),
+        ];
+        let mut pairs = near_pairs(&hashes, 8);
+        pairs.sort_unstable();
+        assert_eq!(pairs, [(0, 1)]);
+
+        let mut pairs = near_pairs(&hashes, 32);
+        pairs.sort_unstable();
+        assert_eq!(pairs, [(0, 1), (0, 2), (1, 2), (2, 3)]);
+    }
+}
@@ -0,0 +1,221 @@
+// Events Module
+// Groups the files of a run into events (a birthday, a day trip, a wedding) by
+// splitting the timeline wherever there is a long pause or a long jump in space.
+
+use serde::{Deserialize, Serialize};
+
+use super::{BatchEntry, most_common, timeline};
+use crate::metadata::Datetime;
+use crate::metadata::context::{EventContext, SpaceContext};
+use crate::metadata::geo::haversine_km;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventConfig {
+    /// A pause longer than this between consecutive files starts a new event
+    #[serde(default = "default_max_gap_minutes")]
+    pub max_gap_minutes: u64,
+    /// A move farther than this from the previous located file starts a new event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_distance_km: Option<f64>,
+}
+
+fn default_max_gap_minutes() -> u64 {
+    240
+}
+
+/// Assigns every file with a capture time to an event and fills in its `event`
+/// context. Events are numbered from 1 in chronological order. Returns the number
+/// of events found.
+pub fn cluster_events(entries: &mut [BatchEntry], config: &EventConfig) -> usize {
+    let max_gap_ms = (config.max_gap_minutes * 60 * 1000) as i64;
+
+    // Split the timeline into runs of file indices
+    let mut events: Vec<Vec<usize>> = Vec::new();
+    let mut previous: Option<i64> = None;
+    let mut last_position: Option<(f64, f64)> = None;
+    for (timestamp_ms, index) in timeline(entries) {
+        let current_position = entries[index].context.space.position();
+
+        let paused = previous.is_some_and(|ts| timestamp_ms - ts > max_gap_ms);
+        let moved = match (config.max_distance_km, last_position, current_position) {
+            (Some(max_km), Some((lat, lon)), Some((lat2, lon2))) => {
+                haversine_km(lat, lon, lat2, lon2) > max_km
+            }
+            _ => false,
+        };
+
+        if events.is_empty() || paused || moved {
+            events.push(Vec::new());
+            last_position = None;
+        }
+        events.last_mut().unwrap().push(index);
+
+        previous = Some(timestamp_ms);
+        last_position = current_position.or(last_position);
+    }
+
+    for (number, members) in events.iter().enumerate() {
+        let times: Vec<Datetime> = members
+            .iter()
+            .filter_map(|&index| Datetime::from_time(&entries[index].context.time))
+            .collect();
+        let start = times.first().copied();
+        let end = times.last().copied();
+
+        let name = match (start, common_location(entries, members)) {
+            (Some(start), Some(location)) => {
+                format!("{} {location}", start.local().format("%Y-%m-%d"))
+            }
+            (Some(start), None) => start.local().format("%Y-%m-%d").to_string(),
+            (None, _) => String::new(),
+        };
+
+        let event = EventContext {
+            id: number as u32 + 1,
+            name,
+            start,
+            end,
+            size: members.len() as u32,
+        };
+        for &index in members {
+            entries[index].context.event = event.clone();
+        }
+    }
+
+    events.len()
+}
+
+// The city most located files of the event were taken in, or the country when no
+// city is known. Unlocated files only carry the "unknown" placeholder.
+fn common_location(entries: &[BatchEntry], members: &[usize]) -> Option<String> {
+    let values = |field: fn(&SpaceContext) -> &str| {
+        members
+            .iter()
+            .map(move |&index| &entries[index].context.space)
+            .filter(|space| space.position().is_some())
+            .map(field)
+    };
+
+    most_common(values(|space| &space.city))
+        .or_else(|| most_common(values(|space| &space.country)))
+        .map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MediaContext;
+    use crate::metadata::extractor::apply_defaults;
+    use chrono::{TimeZone, Utc};
+
+    fn entry(name: &str, day: u32, hour: u32, city: Option<(&str, f64, f64)>) -> BatchEntry {
+        let mut context = MediaContext::default();
+        context.time.timestamp = Some(Utc.with_ymd_and_hms(2024, 7, day, hour, 0, 0).unwrap());
+        if let Some((city, lat, lon)) = city {
+            context.space.city = city.to_string();
+            context.space.country = "Portugal".to_string();
+            context.space.lat = lat;
+            context.space.lon = lon;
+        }
+        apply_defaults(&mut context);
+        BatchEntry {
+            path: name.into(),
+            context,
+        }
+    }
+
+    const LISBON: Option<(&str, f64, f64)> = Some(("Lisbon", 38.72, -9.14));
+    const PORTO: Option<(&str, f64, f64)> = Some(("Porto", 41.15, -8.61));
+
+    fn config(max_gap_minutes: u64, max_distance_km: Option<f64>) -> EventConfig {
+        EventConfig {
+            max_gap_minutes,
+            max_distance_km,
+        }
+    }
+
+    #[test]
+    fn test_splits_on_time_gaps() {
+        let mut entries = vec![
+            entry("c", 12, 13, LISBON),
+            entry("a", 12, 10, None),
+            entry("b", 12, 11, LISBON),
+            entry("d", 14, 9, None),
+            entry("undated", 1, 0, None),
+        ];
+        entries[4].context.time.timestamp = None;
+
+        assert_eq!(cluster_events(&mut entries, &config(240, None)), 2);
+
+        let first = &entries[1].context.event;
+        assert_eq!(first.id, 1);
+        assert_eq!(first.size, 3);
+        assert_eq!(first.name, "2024-07-12 Lisbon");
+        assert_eq!(
+            first.start.unwrap().to_string(),
+            "2024-07-12T10:00:00+00:00"
+        );
+        assert_eq!(first.end.unwrap().to_string(), "2024-07-12T13:00:00+00:00");
+        assert_eq!(entries[0].context.event.id, 1);
+
+        let second = &entries[3].context.event;
+        assert_eq!((second.id, second.size), (2, 1));
+        assert_eq!(second.name, "2024-07-14");
+
+        assert_eq!(entries[4].context.event.id, 0);
+    }
+
+    #[test]
+    fn test_splits_on_distance() {
+        let mut entries = vec![
+            entry("a", 12, 10, LISBON),
+            entry("b", 12, 11, None),
+            entry("c", 12, 13, PORTO),
+            entry("d", 12, 14, PORTO),
+        ];
+
+        assert_eq!(cluster_events(&mut entries, &config(240, None)), 1);
+        assert_eq!(entries[0].context.event.name, "2024-07-12 Porto");
+
+        assert_eq!(cluster_events(&mut entries, &config(240, Some(50.0))), 2);
+        assert_eq!(entries[1].context.event.id, 1);
+        assert_eq!(entries[2].context.event.name, "2024-07-12 Porto");
+        assert_eq!(entries[3].context.event.size, 2);
+    }
+
+    #[test]
+    fn test_naive_camera_joins_phone_event() {
+        // Phone at 12:00 local (UTC+2), DSLR clock reading 12:30 local
+        let mut phone = entry("phone", 12, 10, LISBON);
+        phone.context.time.utc_offset_secs = 7200;
+        let mut dslr = entry("dslr", 12, 12, None);
+        dslr.context.time.timestamp = Some(Utc.with_ymd_and_hms(2024, 7, 12, 12, 30, 0).unwrap());
+        dslr.context.time.naive = true;
+        let mut entries = vec![dslr, phone];
+
+        assert_eq!(cluster_events(&mut entries, &config(60, None)), 1);
+        let event = &entries[0].context.event;
+        assert_eq!(event.size, 2);
+        assert_eq!(
+            event.start.unwrap().to_string(),
+            "2024-07-12T12:00:00+02:00"
+        );
+        assert_eq!(event.end.unwrap().to_string(), "2024-07-12T12:30:00");
+    }
+
+    #[test]
+    fn test_ignores_unlocated_files() {
+        let mut entries = vec![
+            entry("a", 12, 10, None),
+            entry("b", 12, 11, None),
+            entry("c", 12, 12, LISBON),
+            entry("d", 14, 10, None),
+            entry("e", 14, 11, None),
+        ];
+        assert_eq!(entries[0].context.space.city, "unknown");
+
+        assert_eq!(cluster_events(&mut entries, &config(240, None)), 2);
+        assert_eq!(entries[0].context.event.name, "2024-07-12 Lisbon");
+        assert_eq!(entries[3].context.event.name, "2024-07-14");
+    }
+}
@@ -0,0 +1,319 @@
+// Groups Module
+// Joins files that make up one logical asset: a RAW and the JPEG the camera wrote
+// beside it, or the still and motion halves of a Live Photo. Members share the best
+// capture time and location found among them, and rules file them together.
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{BatchEntry, find, union};
+use crate::metadata::context::{GroupContext, MediaContext};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupConfig {
+    /// Pair RAW files with images of the same name in the same directory
+    #[serde(default = "default_enabled")]
+    pub raw_jpeg: bool,
+    /// Pair Live Photo stills with their videos, by ContentIdentifier or by name
+    #[serde(default = "default_enabled")]
+    pub live_photos: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Raw,
+    Still,
+    Video,
+}
+
+fn kind(context: &MediaContext) -> Option<Kind> {
+    match (context.r#type.as_str(), context.subtype.as_str()) {
+        ("image", "raw") => Some(Kind::Raw),
+        ("image", _) => Some(Kind::Still),
+        ("video", _) => Some(Kind::Video),
+        _ => None,
+    }
+}
+
+/// Directory and lowercase stem, shared by files written for the same shot.
+fn stem_key(path: &Utf8Path) -> (String, String) {
+    (
+        path.parent().map(|p| p.to_string()).unwrap_or_default(),
+        path.file_stem().unwrap_or("").to_lowercase(),
+    )
+}
+
+fn content_identifier(context: &MediaContext) -> Option<String> {
+    context
+        .meta
+        .get("ContentIdentifier")
+        .and_then(|value| value.clone().into_string().ok())
+}
+
+// Lower is better; files without a capture time or location rank last.
+fn time_rank(context: &MediaContext) -> usize {
+    match context.time.source.as_str() {
+        _ if context.time.timestamp.is_none() => 5,
+        "exif" => 0,
+        "takeout" => 1,
+        "filename" => 2,
+        "filesystem" => 3,
+        _ => 4,
+    }
+}
+
+fn space_rank(context: &MediaContext) -> usize {
+    match context.space.source.as_str() {
+        _ if context.space.position().is_none() => 5,
+        "exif" => 0,
+        "takeout" => 1,
+        "location_history" => 2,
+        "neighbor" => 3,
+        _ => 4,
+    }
+}
+
+/// Groups related files and fills in their `group` context. The primary of a group
+/// is its RAW file, otherwise its still image; every member gets the time and
+/// location of the member with the most trustworthy ones. Groups are numbered from
+/// 1 in path order of their primaries. Returns the number of groups.
+pub fn group_assets(entries: &mut [BatchEntry], config: &GroupConfig) -> usize {
+    let mut parents: Vec<usize> = (0..entries.len()).collect();
+    let kinds: Vec<Option<Kind>> = entries.iter().map(|e| kind(&e.context)).collect();
+
+    let mut by_stem: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut by_identifier: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if kinds[index].is_none() {
+            continue;
+        }
+        by_stem
+            .entry(stem_key(&entry.path))
+            .or_default()
+            .push(index);
+        if let Some(identifier) = content_identifier(&entry.context) {
+            by_identifier.entry(identifier).or_default().push(index);
+        }
+    }
+
+    let join = |parents: &mut Vec<usize>, members: &[usize], a: Kind, b: Kind| {
+        let first = members.iter().find(|&&i| kinds[i] == Some(a));
+        if let Some(&first) = first
+            && members.iter().any(|&i| kinds[i] == Some(b))
+        {
+            for &member in members {
+                if kinds[member] == Some(a) || kinds[member] == Some(b) {
+                    union(parents, first, member);
+                }
+            }
+        }
+    };
+
+    let mut stems: Vec<_> = by_stem.into_values().collect();
+    stems.sort_unstable();
+    for members in &stems {
+        if config.raw_jpeg {
+            join(&mut parents, members, Kind::Raw, Kind::Still);
+        }
+        if config.live_photos {
+            // Same-named stills and videos pair unless their identifiers disagree
+            let identifiers: Vec<String> = members
+                .iter()
+                .filter_map(|&i| content_identifier(&entries[i].context))
+                .collect();
+            if identifiers.windows(2).all(|pair| pair[0] == pair[1]) {
+                join(&mut parents, members, Kind::Still, Kind::Video);
+            }
+        }
+    }
+    if config.live_photos {
+        for members in by_identifier.values() {
+            join(&mut parents, members, Kind::Still, Kind::Video);
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    for index in 0..entries.len() {
+        let root = find(&mut parents, index);
+        groups[root].push(index);
+    }
+
+    let mut groups: Vec<(usize, Vec<usize>)> = groups
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let primary = *members
+                .iter()
+                .min_by_key(|&&i| (kinds[i], &entries[i].path))
+                .unwrap();
+            (primary, members)
+        })
+        .collect();
+    groups.sort_unstable_by(|(a, _), (b, _)| entries[*a].path.cmp(&entries[*b].path));
+
+    for (id, (primary, members)) in groups.iter().enumerate() {
+        let best_time = *members
+            .iter()
+            .min_by_key(|&&i| (time_rank(&entries[i].context), i != *primary))
+            .unwrap();
+        let best_space = *members
+            .iter()
+            .min_by_key(|&&i| (space_rank(&entries[i].context), i != *primary))
+            .unwrap();
+        let time = entries[best_time].context.time.clone();
+        let space = entries[best_space].context.space.clone();
+        let space_rank_best = space_rank(&entries[best_space].context);
+
+        let primary_kind = kinds[*primary];
+        for &member in members {
+            let role = if member == *primary {
+                "primary"
+            } else {
+                match kinds[member] {
+                    Some(Kind::Raw) => "raw",
+                    Some(Kind::Still) if primary_kind == Some(Kind::Raw) => "jpeg",
+                    Some(Kind::Still) => "still",
+                    _ => "live",
+                }
+            };
+
+            let context = &mut entries[member].context;
+            context.group = GroupContext {
+                id: id as u32 + 1,
+                role: role.to_string(),
+                size: members.len() as u32,
+            };
+            context.time = time.clone();
+            if space_rank_best < space_rank(context) {
+                context.space = space.clone();
+            }
+        }
+    }
+
+    groups.len()
+}
+
+/// Indices of the files of each asset, the primary first. Files outside any group
+/// are assets of their own.
+pub fn assets(entries: &[BatchEntry]) -> Vec<Vec<usize>> {
+    let mut assets: Vec<Vec<usize>> = Vec::new();
+    let mut by_id: HashMap<u32, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let group = &entry.context.group;
+        if group.id == 0 {
+            assets.push(vec![index]);
+            continue;
+        }
+        let asset = *by_id.entry(group.id).or_insert_with(|| {
+            assets.push(Vec::new());
+            assets.len() - 1
+        });
+        if group.role == "primary" {
+            assets[asset].insert(0, index);
+        } else {
+            assets[asset].push(index);
+        }
+    }
+    assets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use rhai::Dynamic;
+
+    fn entry(path: &str, r#type: &str, subtype: &str) -> BatchEntry {
+        let context = MediaContext {
+            r#type: r#type.to_string(),
+            subtype: subtype.to_string(),
+            ..Default::default()
+        };
+        BatchEntry {
+            path: path.into(),
+            context,
+        }
+    }
+
+    fn config() -> GroupConfig {
+        GroupConfig {
+            raw_jpeg: true,
+            live_photos: true,
+        }
+    }
+
+    #[test]
+    fn test_raw_jpeg_pairs() {
+        let mut entries = vec![
+            entry("/a/IMG_0001.JPG", "image", ""),
+            entry("/a/IMG_0001.CR2", "image", "raw"),
+            entry("/b/IMG_0001.JPG", "image", ""),
+            entry("/a/IMG_0002.JPG", "image", ""),
+        ];
+        entries[0].context.time.timestamp =
+            Some(Utc.with_ymd_and_hms(2024, 7, 12, 10, 0, 0).unwrap());
+        entries[0].context.time.source = "exif".to_string();
+        entries[1].context.time.timestamp =
+            Some(Utc.with_ymd_and_hms(2024, 7, 20, 0, 0, 0).unwrap());
+        entries[1].context.time.source = "filesystem".to_string();
+        entries[0].context.space.lat = 38.72;
+        entries[0].context.space.lon = -9.14;
+        entries[0].context.space.source = "exif".to_string();
+
+        assert_eq!(group_assets(&mut entries, &config()), 1);
+
+        let raw = &entries[1].context;
+        assert_eq!((raw.group.id, raw.group.role.as_str()), (1, "primary"));
+        assert_eq!(entries[0].context.group.role, "jpeg");
+        assert_eq!(raw.group.size, 2);
+        // The RAW takes the JPEG's EXIF time and location
+        assert_eq!(raw.time.timestamp, entries[0].context.time.timestamp);
+        assert_eq!(raw.space.position(), Some((38.72, -9.14)));
+        assert_eq!(entries[2].context.group.id, 0);
+
+        assert_eq!(assets(&entries), vec![vec![1, 0], vec![2], vec![3]]);
+
+        let disabled = GroupConfig {
+            raw_jpeg: false,
+            ..config()
+        };
+        let mut entries = vec![
+            entry("/a/IMG_0001.JPG", "image", ""),
+            entry("/a/IMG_0001.CR2", "image", "raw"),
+        ];
+        assert_eq!(group_assets(&mut entries, &disabled), 0);
+    }
+
+    #[test]
+    fn test_live_photos() {
+        let identify = |mut entry: BatchEntry, identifier: &str| {
+            entry.context.meta.insert(
+                "ContentIdentifier".to_string(),
+                Dynamic::from(identifier.to_string()),
+            );
+            entry
+        };
+        let mut entries = vec![
+            identify(entry("/a/IMG_0001.MOV", "video", ""), "ID-1"),
+            identify(entry("/a/IMG_0001.HEIC", "image", "heic"), "ID-1"),
+            // Renamed on export, paired by identifier only
+            identify(entry("/a/export.MOV", "video", ""), "ID-2"),
+            identify(entry("/a/IMG_0002.HEIC", "image", "heic"), "ID-2"),
+            // Same name, different shots
+            identify(entry("/a/IMG_0003.MOV", "video", ""), "ID-3"),
+            identify(entry("/a/IMG_0003.HEIC", "image", "heic"), "ID-4"),
+        ];
+
+        assert_eq!(group_assets(&mut entries, &config()), 2);
+        assert_eq!(entries[1].context.group.role, "primary");
+        assert_eq!(entries[0].context.group.role, "live");
+        assert_eq!(entries[2].context.group.id, entries[3].context.group.id);
+        assert_eq!(entries[4].context.group.id, 0);
+        assert_eq!(entries[5].context.group.id, 0);
+    }
+}
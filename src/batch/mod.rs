@@ -0,0 +1,111 @@
+// Batch Module
+// Stages that need to see every file of a run at once. They run after metadata
+// has been extracted for all files and before any rule is evaluated.
+
+pub mod calibrate;
+pub mod duplicates;
+pub mod events;
+pub mod groups;
+pub mod neighbors;
+pub mod trips;
+
+use camino::Utf8PathBuf;
+use std::collections::HashMap;
+
+use crate::metadata::MediaContext;
+
+pub use duplicates::{DuplicateConfig, group_duplicates};
+pub use events::{EventConfig, cluster_events};
+pub use groups::{GroupConfig, group_assets};
+pub use neighbors::propagate_neighbor_locations;
+pub use trips::{TripConfig, detect_trips};
+
+/// A file whose metadata has been extracted, waiting for enrichment and rules.
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub path: Utf8PathBuf,
+    pub context: MediaContext,
+}
+
+/// The capture instant of every file, in milliseconds, or `None` without a capture
+/// time. Naive camera times are wall-clock times rather than UTC: they are read
+/// with the UTC offset of the file with a known offset closest on the wall clock,
+/// usually a phone photo of the same moment, or as UTC when there is none.
+pub(crate) fn capture_instants(entries: &[BatchEntry]) -> Vec<Option<i64>> {
+    // Files with a known offset as (wall clock, offset), both in milliseconds
+    let mut aware: Vec<(i64, i64)> = entries
+        .iter()
+        .filter(|entry| !entry.context.time.naive)
+        .filter_map(|entry| {
+            let time = &entry.context.time;
+            let offset_ms = i64::from(time.utc_offset_secs) * 1000;
+            time.timestamp
+                .map(|ts| (ts.timestamp_millis() + offset_ms, offset_ms))
+        })
+        .collect();
+    aware.sort_unstable();
+
+    entries
+        .iter()
+        .map(|entry| {
+            let time = &entry.context.time;
+            let timestamp_ms = time.timestamp?.timestamp_millis();
+            if !time.naive {
+                return Some(timestamp_ms);
+            }
+            let insertion = aware.partition_point(|(wall_ms, _)| *wall_ms < timestamp_ms);
+            let offset_ms = [insertion.checked_sub(1), Some(insertion)]
+                .into_iter()
+                .flatten()
+                .filter_map(|i| aware.get(i))
+                .min_by_key(|(wall_ms, _)| (wall_ms - timestamp_ms).abs())
+                .map_or(0, |(_, offset_ms)| *offset_ms);
+            Some(timestamp_ms - offset_ms)
+        })
+        .collect()
+}
+
+/// Files with a capture time as (instant in milliseconds, index), in capture order.
+pub(crate) fn timeline(entries: &[BatchEntry]) -> Vec<(i64, usize)> {
+    let mut timeline: Vec<(i64, usize)> = capture_instants(entries)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, instant)| instant.map(|ms| (ms, index)))
+        .collect();
+    timeline.sort_unstable();
+    timeline
+}
+
+/// The most frequent non-empty value and how often it occurs. Ties go to the value
+/// seen first.
+pub(crate) fn most_common<'a>(values: impl Iterator<Item = &'a str>) -> Option<(String, usize)> {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (order, value) in values.enumerate() {
+        if !value.is_empty() {
+            counts.entry(value).or_insert((0, order)).0 += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, (count, first))| (*count, std::cmp::Reverse(*first)))
+        .map(|(value, (count, _))| (value.to_string(), count))
+}
+
+/// Root of a disjoint-set forest, compressing the path to it.
+pub(crate) fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut current = index;
+    while parents[current] != root {
+        current = std::mem::replace(&mut parents[current], root);
+    }
+    root
+}
+
+/// Merges the sets of `a` and `b`.
+pub(crate) fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (root_a, root_b) = (find(parents, a), find(parents, b));
+    parents[root_b] = root_a;
+}
@@ -0,0 +1,182 @@
+// Neighbor Location Module
+// Cameras without GPS can borrow the location of a geotagged file (usually a phone
+// photo) captured a few minutes apart in the same run.
+
+use super::{BatchEntry, capture_instants};
+use crate::metadata::context::SpaceContext;
+use crate::metadata::timezone::localize_time;
+
+fn has_coordinates(space: &SpaceContext) -> bool {
+    space.lat != 0.0 || space.lon != 0.0
+}
+
+/// Copies the location of the closest geotagged file in time to every file that has
+/// no coordinates, as long as both were captured within `max_minutes` of each other.
+/// Returns the number of files that received a location.
+pub fn propagate_neighbor_locations(entries: &mut [BatchEntry], max_minutes: u64) -> usize {
+    let max_time_diff_ms = (max_minutes * 60 * 1000) as i64;
+
+    // Naive camera clocks are compared on the same timeline as offset-aware phones
+    let instants = capture_instants(entries);
+
+    // Geotagged files sorted by capture time, as (timestamp_ms, index) pairs
+    let mut tagged: Vec<(i64, usize)> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| has_coordinates(&entry.context.space))
+        .filter_map(|(index, _)| instants[index].map(|ms| (ms, index)))
+        .collect();
+    tagged.sort_unstable();
+
+    if tagged.is_empty() {
+        return 0;
+    }
+
+    // Find a neighbor for every file first, so borrowed locations are never lent again
+    let mut borrowed = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if has_coordinates(&entry.context.space) {
+            continue;
+        }
+        let Some(timestamp_ms) = instants[index] else {
+            continue;
+        };
+
+        let insertion = tagged.partition_point(|(ts, _)| *ts < timestamp_ms);
+        let before = insertion.checked_sub(1).and_then(|i| tagged.get(i));
+        let after = tagged.get(insertion);
+
+        let nearest = [before, after]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(ts, _)| (ts - timestamp_ms).abs());
+
+        if let Some((ts, neighbor)) = nearest
+            && (ts - timestamp_ms).abs() <= max_time_diff_ms
+        {
+            borrowed.push((index, *neighbor, (ts - timestamp_ms).abs()));
+        }
+    }
+
+    for (index, neighbor, diff_ms) in &borrowed {
+        let mut space = entries[*neighbor].context.space.clone();
+        let own = &entries[*index].context.space;
+
+        // A place visit found for this file's own timestamp is more precise
+        if !own.place_id.is_empty() || !own.place_name.is_empty() {
+            space.place_name = own.place_name.clone();
+            space.place_address = own.place_address.clone();
+            space.place_id = own.place_id.clone();
+        }
+        space.source = "neighbor".to_string();
+
+        eprintln!(
+            "🧭 GPS from neighbor: {} <- {} ({}s apart) -> {}, {}",
+            entries[*index].path,
+            entries[*neighbor].path,
+            diff_ms / 1000,
+            space.country,
+            space.city
+        );
+
+        let context = &mut entries[*index].context;
+        localize_time(&mut context.time, &space.timezone);
+        context.space = space;
+    }
+
+    borrowed.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MediaContext;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn entry(
+        name: &str,
+        timestamp: Option<DateTime<Utc>>,
+        coords: Option<(f64, f64)>,
+    ) -> BatchEntry {
+        let mut context = MediaContext::default();
+        context.time.timestamp = timestamp;
+        if let Some((lat, lon)) = coords {
+            context.space.lat = lat;
+            context.space.lon = lon;
+            context.space.city = name.to_string();
+            context.space.source = "exif".to_string();
+        }
+        BatchEntry {
+            path: name.into(),
+            context,
+        }
+    }
+
+    fn at(h: u32, m: u32) -> Option<DateTime<Utc>> {
+        Some(Utc.with_ymd_and_hms(2024, 7, 12, h, m, 0).unwrap())
+    }
+
+    #[test]
+    fn test_borrows_closest_neighbor_within_window() {
+        let mut entries = vec![
+            entry("Lisbon", at(10, 0), Some((38.72, -9.14))),
+            entry("dslr_1", at(10, 20), None),
+            entry("Sintra", at(10, 30), Some((38.80, -9.38))),
+            entry("dslr_2", at(14, 0), None),
+        ];
+
+        let count = propagate_neighbor_locations(&mut entries, 30);
+        assert_eq!(count, 1);
+
+        let space = &entries[1].context.space;
+        assert_eq!(space.city, "Sintra");
+        assert_eq!(space.lat, 38.80);
+        assert_eq!(space.source, "neighbor");
+
+        // Too far from any geotagged file
+        assert_eq!(entries[3].context.space.lat, 0.0);
+        assert!(entries[3].context.space.source.is_empty());
+    }
+
+    #[test]
+    fn test_keeps_own_place_visit() {
+        let mut entries = vec![
+            entry("Madrid", at(10, 0), Some((40.41, -3.69))),
+            entry("dslr", at(10, 5), None),
+        ];
+        entries[1].context.space.place_name = "Museo del Prado".to_string();
+
+        propagate_neighbor_locations(&mut entries, 10);
+
+        let space = &entries[1].context.space;
+        assert_eq!(space.city, "Madrid");
+        assert_eq!(space.place_name, "Museo del Prado");
+    }
+
+    #[test]
+    fn test_naive_camera_uses_phone_wall_clock() {
+        // Phone in Madrid at 12:00 local (UTC+2), DSLR clock reading 12:05 local
+        let mut phone = entry("Madrid", at(10, 0), Some((40.41, -3.69)));
+        phone.context.time.utc_offset_secs = 7200;
+        phone.context.space.timezone = "Europe/Madrid".to_string();
+        let mut dslr = entry("dslr", at(12, 5), None);
+        dslr.context.time.naive = true;
+        let mut entries = vec![phone, dslr];
+
+        assert_eq!(propagate_neighbor_locations(&mut entries, 30), 1);
+
+        let context = &entries[1].context;
+        assert_eq!(context.space.city, "Madrid");
+        assert!(!context.time.naive);
+        assert_eq!(
+            context.time.timestamp,
+            Some(Utc.with_ymd_and_hms(2024, 7, 12, 10, 5, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_without_geotagged_files() {
+        let mut entries = vec![entry("a", at(10, 0), None), entry("b", None, None)];
+        assert_eq!(propagate_neighbor_locations(&mut entries, 60), 0);
+    }
+}
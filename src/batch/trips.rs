@@ -0,0 +1,251 @@
+// Trips Module
+// Detects trips as maximal runs of files captured far from home, so travel photos
+// can be filed per trip and per day without a rule for every destination.
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use super::{BatchEntry, most_common, timeline};
+use crate::metadata::context::{SpaceContext, TripContext};
+use crate::metadata::geo::haversine_km;
+use crate::metadata::{Datetime, Places};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripConfig {
+    /// Name of the configured place that counts as home
+    pub home: String,
+    /// Files captured farther than this from home are part of a trip
+    #[serde(default = "default_min_distance_km")]
+    pub min_distance_km: f64,
+    /// A pause longer than this between files away from home starts a new trip
+    #[serde(default = "default_max_gap_hours")]
+    pub max_gap_hours: u64,
+}
+
+fn default_min_distance_km() -> f64 {
+    100.0
+}
+
+fn default_max_gap_hours() -> u64 {
+    72
+}
+
+impl TripConfig {
+    /// Coordinates of the home place as (lat, lon).
+    pub fn home_position(&self, places: &Places) -> Result<(f64, f64)> {
+        match places.get(&self.home) {
+            Some(place) => Ok(place.center()),
+            None => bail!("Trip home '{}' is not a configured place", self.home),
+        }
+    }
+}
+
+/// Assigns the files captured away from `home` to trips and fills in their `trip`
+/// context. Files without coordinates join the trip they were captured in the
+/// middle of. Trips are numbered from 1 in chronological order. Returns the number
+/// of trips found.
+pub fn detect_trips(entries: &mut [BatchEntry], home: (f64, f64), config: &TripConfig) -> usize {
+    let max_gap_ms = (config.max_gap_hours * 3600 * 1000) as i64;
+
+    // Runs of files away from home; unlocated files are held back until the next
+    // located file tells whether they were still away
+    let mut trips: Vec<Vec<usize>> = Vec::new();
+    let mut pending: Vec<usize> = Vec::new();
+    let mut away = false;
+    let mut last_away_ms = 0;
+    for (timestamp_ms, index) in timeline(entries) {
+        let Some((lat, lon)) = entries[index].context.space.position() else {
+            if away {
+                pending.push(index);
+            }
+            continue;
+        };
+
+        if haversine_km(lat, lon, home.0, home.1) <= config.min_distance_km {
+            away = false;
+            pending.clear();
+            continue;
+        }
+
+        if !away || timestamp_ms - last_away_ms > max_gap_ms {
+            trips.push(Vec::new());
+            pending.clear();
+        }
+        let trip = trips.last_mut().unwrap();
+        trip.append(&mut pending);
+        trip.push(index);
+        away = true;
+        last_away_ms = timestamp_ms;
+    }
+
+    for (number, members) in trips.iter().enumerate() {
+        let times: Vec<Datetime> = members
+            .iter()
+            .filter_map(|&index| Datetime::from_time(&entries[index].context.time))
+            .collect();
+        let (Some(start), Some(end)) = (times.first().copied(), times.last().copied()) else {
+            continue;
+        };
+        let name = trip_name(entries, members);
+
+        for &index in members {
+            let day = Datetime::from_time(&entries[index].context.time)
+                .map(|dt| (dt.local().date() - start.local().date()).num_days() + 1)
+                .unwrap_or(0);
+            entries[index].context.trip = TripContext {
+                id: number as u32 + 1,
+                name: name.clone(),
+                start: Some(start),
+                end: Some(end),
+                day: day as u32,
+            };
+        }
+    }
+
+    trips.len()
+}
+
+// The city when most of the trip happened there, otherwise the country most files
+// were taken in. Unlocated files only carry the "unknown" placeholder.
+fn trip_name(entries: &[BatchEntry], members: &[usize]) -> String {
+    let values = |field: fn(&SpaceContext) -> &str| {
+        members
+            .iter()
+            .map(move |&index| &entries[index].context.space)
+            .filter(|space| space.position().is_some())
+            .map(field)
+    };
+
+    let located = values(|space| &space.country)
+        .filter(|country| !country.is_empty())
+        .count();
+    match most_common(values(|space| &space.city)) {
+        Some((city, count)) if count * 2 > located => city,
+        _ => most_common(values(|space| &space.country))
+            .map(|(country, _)| country)
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MediaContext;
+    use crate::metadata::extractor::apply_defaults;
+    use chrono::{TimeZone, Utc};
+
+    const HOME: (f64, f64) = (40.4168, -3.7038);
+
+    fn entry(day: u32, hour: u32, place: Option<(&str, &str, f64, f64)>) -> BatchEntry {
+        let mut context = MediaContext::default();
+        context.time.timestamp = Some(Utc.with_ymd_and_hms(2024, 7, day, hour, 0, 0).unwrap());
+        if let Some((city, country, lat, lon)) = place {
+            context.space.city = city.to_string();
+            context.space.country = country.to_string();
+            context.space.lat = lat;
+            context.space.lon = lon;
+        }
+        apply_defaults(&mut context);
+        BatchEntry {
+            path: format!("{day}-{hour}").into(),
+            context,
+        }
+    }
+
+    const MADRID: Option<(&str, &str, f64, f64)> = Some(("Madrid", "Spain", 40.42, -3.70));
+    const LISBON: Option<(&str, &str, f64, f64)> = Some(("Lisbon", "Portugal", 38.72, -9.14));
+    const PORTO: Option<(&str, &str, f64, f64)> = Some(("Porto", "Portugal", 41.15, -8.61));
+    const COIMBRA: Option<(&str, &str, f64, f64)> = Some(("Coimbra", "Portugal", 40.21, -8.43));
+    const TOKYO: Option<(&str, &str, f64, f64)> = Some(("Tokyo", "Japan", 35.68, 139.65));
+
+    fn config() -> TripConfig {
+        TripConfig {
+            home: "Home".to_string(),
+            min_distance_km: 100.0,
+            max_gap_hours: 72,
+        }
+    }
+
+    #[test]
+    fn test_detects_trips_away_from_home() {
+        let mut entries = vec![
+            entry(1, 10, MADRID),
+            entry(2, 10, LISBON),
+            entry(2, 12, None),
+            entry(3, 10, PORTO),
+            entry(4, 10, COIMBRA),
+            entry(4, 20, None),
+            entry(5, 10, MADRID),
+            entry(20, 10, LISBON),
+        ];
+
+        assert_eq!(detect_trips(&mut entries, HOME, &config()), 2);
+
+        let trip = &entries[1].context.trip;
+        assert_eq!((trip.id, trip.day), (1, 1));
+        // No city dominates, so the trip is named after the country
+        assert_eq!(trip.name, "Portugal");
+        assert_eq!(trip.start.unwrap().local().format("%d").to_string(), "02");
+        assert_eq!(trip.end.unwrap().local().format("%d").to_string(), "04");
+
+        // Unlocated files between two away files belong to the trip
+        assert_eq!(entries[2].context.trip.id, 1);
+        assert_eq!(entries[4].context.trip.day, 3);
+
+        // Unlocated files followed by a home file do not
+        assert_eq!(entries[5].context.trip.id, 0);
+        assert_eq!(entries[0].context.trip.id, 0);
+        assert_eq!(entries[6].context.trip.id, 0);
+
+        let trip = &entries[7].context.trip;
+        assert_eq!((trip.id, trip.day), (2, 1));
+        assert_eq!(trip.name, "Lisbon");
+    }
+
+    #[test]
+    fn test_long_pause_splits_trips() {
+        let mut entries = vec![
+            entry(2, 10, LISBON),
+            entry(3, 10, LISBON),
+            entry(10, 10, PORTO),
+        ];
+
+        assert_eq!(detect_trips(&mut entries, HOME, &config()), 2);
+        assert_eq!(entries[1].context.trip.id, 1);
+        assert_eq!(entries[2].context.trip.id, 2);
+    }
+
+    #[test]
+    fn test_naive_camera_stays_in_trip() {
+        // Phone at 19:00 local (UTC+9), camera clock reading 20:00 local
+        let mut phone = entry(2, 10, TOKYO);
+        phone.context.time.utc_offset_secs = 9 * 3600;
+        let mut camera = entry(2, 20, TOKYO);
+        camera.context.time.naive = true;
+        let mut entries = vec![camera, phone];
+
+        let config = TripConfig {
+            max_gap_hours: 2,
+            ..config()
+        };
+        assert_eq!(detect_trips(&mut entries, HOME, &config), 1);
+        assert_eq!(entries[0].context.trip.id, 1);
+        assert_eq!(entries[1].context.trip.id, 1);
+    }
+
+    #[test]
+    fn test_unlocated_files_do_not_name_trips() {
+        let mut entries = vec![
+            entry(2, 10, LISBON),
+            entry(2, 11, None),
+            entry(2, 12, None),
+            entry(2, 13, None),
+            entry(3, 10, LISBON),
+        ];
+        assert_eq!(entries[1].context.space.city, "unknown");
+
+        assert_eq!(detect_trips(&mut entries, HOME, &config()), 1);
+        assert_eq!(entries[2].context.trip.id, 1);
+        assert_eq!(entries[2].context.trip.name, "Lisbon");
+    }
+}
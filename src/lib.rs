@@ -1,4 +1,5 @@
 pub mod actions;
+pub mod batch;
 pub mod config;
 pub mod metadata;
 pub mod pipeline;
@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::sync::{
     Arc,
@@ -11,32 +12,47 @@ use walkdir::WalkDir;
 
 use monana::{
     actions::Action,
+    batch::{
+        BatchEntry,
+        calibrate::{CalibrateOptions, estimate_offsets, update_config},
+        cluster_events, detect_trips, group_assets, group_duplicates,
+        groups::assets,
+        propagate_neighbor_locations,
+    },
     metadata::{
-        LocationHistory,
+        ClockOffsets, ExifTool, ExtractOptions, LocationHistory, MediaTypeConfig, Places,
+        SidecarPatterns,
+        clock::format_offset,
+        configure_geocoder,
         context::{MediaContext, SourceContext},
-        extractor::extract_metadata_with_location_history,
+        extract_metadata_with_options,
+        sidecar::Sidecar,
     },
-    pipeline::{InputSpec, Pipeline, RuleEngine, Ruleset},
+    pipeline::{ActionSpec, InputSpec, Pipeline, RuleEngine, Ruleset},
 };
 
 #[derive(Parser)]
 #[command(name = "monana")]
 #[command(about = "MONANA - Media Organization, Normalization, and Archival via Named Automation")]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Run all cmdline rulesets with the given path
-    #[arg(long = "input-cmdline", value_name = "PATH")]
-    input_cmdline: Utf8PathBuf,
+    #[arg(long = "input-cmdline", value_name = "PATH", required = true)]
+    input_cmdline: Option<Utf8PathBuf>,
 
     /// Configuration file
-    #[arg(short, long, default_value = "monana.yaml")]
+    #[arg(short, long, default_value = "monana.yaml", global = true)]
     config: String,
 
-    /// Google Maps Timeline location history JSON file (overrides config)
-    #[arg(long = "location-history", value_name = "PATH")]
+    /// Google Maps Timeline location history JSON file or Takeout directory (overrides config)
+    #[arg(long = "location-history", value_name = "PATH", global = true)]
     location_history: Option<String>,
 
     /// Process directories recursively
-    #[arg(short = 'R', long)]
+    #[arg(short = 'R', long, global = true)]
     recursive: bool,
 
     /// Dry run - show what would be done without doing it
@@ -52,6 +68,24 @@ struct Args {
     parallel: Option<usize>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Estimate camera clock offsets from GPS-tagged phone photos and location history
+    Calibrate {
+        /// Files or directory with photos from the cameras to calibrate and a reference
+        path: Utf8PathBuf,
+
+        /// Add the estimated offsets to the clock_offsets section of the configuration
+        /// file (comments in it are not preserved; a .bak copy is kept)
+        #[arg(long)]
+        write: bool,
+
+        /// Fewest matches needed to trust an estimate
+        #[arg(long, default_value_t = 3)]
+        min_matches: usize,
+    },
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
@@ -64,6 +98,23 @@ fn main() -> Result<()> {
     let pipeline: Pipeline =
         serde_yaml::from_str(&config_content).with_context(|| "Failed to parse configuration")?;
 
+    configure_geocoder(&pipeline.geocoder).with_context(|| "Failed to configure geocoder")?;
+
+    let places = Arc::new(Places::load(&pipeline.places).with_context(|| "Failed to load places")?);
+
+    let clock_offsets = Arc::new(ClockOffsets::load(&pipeline.clock_offsets)?);
+
+    let sidecar_patterns = SidecarPatterns::load(&pipeline.sidecars)?;
+    let exiftool = match &pipeline.exiftool {
+        Some(config) => Some(Arc::new(ExifTool::start(config)?)),
+        None => None,
+    };
+
+    let trip_home = match &pipeline.trips {
+        Some(trips) => Some(trips.home_position(&places)?),
+        None => None,
+    };
+
     // Load location history - CLI argument takes precedence over config
     let location_history_path = args
         .location_history
@@ -71,7 +122,7 @@ fn main() -> Result<()> {
         .or(pipeline.location_history_path.as_ref());
 
     let location_history = if let Some(path) = location_history_path {
-        match LocationHistory::from_json_file(path) {
+        match LocationHistory::from_path(path) {
             Ok(history) => {
                 println!("📍 Loaded location history from: {path}");
                 if args.location_history.is_some() {
@@ -88,6 +139,31 @@ fn main() -> Result<()> {
         None
     };
 
+    if let Some(Command::Calibrate {
+        path,
+        write,
+        min_matches,
+    }) = &args.command
+    {
+        let options = CalibrateOptions {
+            min_matches: *min_matches,
+            ..Default::default()
+        };
+        return calibrate(
+            path,
+            args.recursive,
+            &pipeline.media_types,
+            &args.config,
+            &config_content,
+            location_history.as_deref(),
+            &options,
+            *write,
+        );
+    }
+    let Some(input_cmdline) = &args.input_cmdline else {
+        anyhow::bail!("--input-cmdline is required");
+    };
+
     // Find all cmdline rulesets
     let cmdline_rulesets: Vec<_> = pipeline
         .rulesets
@@ -108,13 +184,23 @@ fn main() -> Result<()> {
     // Note: RuleEngine will be created per thread due to Rhai not being thread-safe
 
     // Check if input path exists
-    if !args.input_cmdline.exists() {
-        eprintln!("⚠️  Path does not exist: {}", args.input_cmdline);
+    if !input_cmdline.exists() {
+        eprintln!("⚠️  Path does not exist: {input_cmdline}");
         return Ok(());
     }
 
     // Collect all files to process
-    let all_files = collect_files(&args.input_cmdline, args.recursive)?;
+    let media_filter = (!pipeline.include_non_media).then_some(&pipeline.media_types);
+    let mut all_files = collect_files(input_cmdline, args.recursive, media_filter)?;
+
+    // Sidecars travel with their single owner rather than on their own
+    let sidecars = sidecar_patterns.assign(&all_files);
+    let claimed: HashSet<&Utf8Path> = sidecars
+        .values()
+        .flatten()
+        .map(|sidecar| sidecar.path.as_path())
+        .collect();
+    all_files.retain(|path| !claimed.contains(path.as_path()));
 
     if all_files.is_empty() {
         println!("⚠️  No media files found");
@@ -147,84 +233,142 @@ fn main() -> Result<()> {
     let total_matched = AtomicUsize::new(0);
     let total_errors = AtomicUsize::new(0);
 
-    // Process files in parallel
-    all_files.par_iter().for_each(|file_path| {
-        total_processed.fetch_add(1, Ordering::Relaxed);
+    let extract_options = ExtractOptions {
+        location_history,
+        max_hours: Some(pipeline.location_history_max_hours),
+        clock_offsets: Some(clock_offsets),
+        perceptual_hash: pipeline.duplicates.is_some(),
+        media_types: Some(Arc::new(pipeline.media_types.clone())),
+        content_identifier: pipeline.groups.as_ref().is_some_and(|g| g.live_photos),
+        iptc_location: pipeline.iptc_location,
+        exiftool,
+        cameras: Some(Arc::new(pipeline.cameras.clone())),
+    };
 
-        // Create a new RuleEngine for this thread (Rhai is not thread-safe)
-        let engine = match RuleEngine::new() {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("❌ Error creating rule engine: {e}");
-                total_errors.fetch_add(1, Ordering::Relaxed);
-                return;
+    // Phase 1: extract metadata for every file
+    let mut entries: Vec<BatchEntry> = all_files
+        .par_iter()
+        .filter_map(|file_path| {
+            total_processed.fetch_add(1, Ordering::Relaxed);
+
+            match extract_metadata_with_options(file_path, &extract_options) {
+                Ok(context) => Some(BatchEntry {
+                    path: file_path.clone(),
+                    context,
+                }),
+                Err(e) => {
+                    eprintln!("❌ Error extracting metadata from {file_path}: {e}");
+                    total_errors.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
             }
-        };
+        })
+        .collect();
 
-        if args.verbose {
-            println!("\n🔄 Processing file: {file_path}");
+    // Phase 2: enrich files using the rest of the batch
+    if let Some(groups) = &pipeline.groups {
+        let count = group_assets(&mut entries, groups);
+        println!("🔗 Grouped related files into {count} asset(s)");
+    }
+
+    if let Some(max_minutes) = pipeline.neighbor_location_max_minutes {
+        let borrowed = propagate_neighbor_locations(&mut entries, max_minutes);
+        if borrowed > 0 {
+            println!("🧭 Borrowed GPS from neighboring files for {borrowed} file(s)");
         }
+    }
 
-        // Extract metadata once per file
-        let context = match extract_metadata_with_location_history(
-            file_path,
-            location_history.clone(),
-            Some(pipeline.location_history_max_hours),
-        ) {
-            Ok(ctx) => ctx,
+    if !places.is_empty() {
+        for entry in &mut entries {
+            places.tag(&mut entry.context.space);
+        }
+    }
+
+    if let Some(duplicates) = &pipeline.duplicates {
+        let count = group_duplicates(&mut entries, duplicates);
+        println!("👯 Found {count} group(s) of duplicate files");
+    }
+
+    if let Some(events) = &pipeline.events {
+        let count = cluster_events(&mut entries, events);
+        println!("🎉 Grouped files into {count} event(s)");
+    }
+
+    if let (Some(trips), Some(home)) = (&pipeline.trips, trip_home) {
+        let count = detect_trips(&mut entries, home, trips);
+        println!("✈️  Found {count} trip(s) away from {}", trips.home);
+    }
+
+    // Phase 3: process assets through the rulesets in parallel. The other files of a
+    // group follow the rules matched by its primary.
+    assets(&entries).par_iter().for_each(|asset| {
+        // Create a new RuleEngine for this thread (Rhai is not thread-safe)
+        let engine = match RuleEngine::with_places(places.clone()) {
+            Ok(e) => e,
             Err(e) => {
-                eprintln!("❌ Error extracting metadata from {file_path}: {e}");
+                eprintln!("❌ Error creating rule engine: {e}");
                 total_errors.fetch_add(1, Ordering::Relaxed);
                 return;
             }
         };
 
-        if args.verbose {
-            println!("  📊 Type: {}", context.r#type);
-            if !context.meta.is_empty() {
-                println!("  📷 EXIF tags found: {}", context.meta.len());
-            }
-        }
+        let mut state = AssetState::default();
+        for (position, &index) in asset.iter().enumerate() {
+            let entry = &entries[index];
+            let file_path = &entry.path;
+            let context = &entry.context;
+            state.follow = position > 0;
 
-        // Process through all cmdline rulesets (entry points)
-        let mut file_matched = false;
-        for ruleset in &cmdline_rulesets {
             if args.verbose {
-                println!("  🔧 Starting pipeline with ruleset: {}", ruleset.name);
+                println!("\n🔄 Processing file: {file_path}");
+                println!("  📊 Type: {}", context.r#type);
+                if !context.meta.is_empty() {
+                    println!("  📷 EXIF tags found: {}", context.meta.len());
+                }
             }
 
-            match process_file_recursive(
-                file_path,
-                &context,
-                ruleset,
-                &pipeline,
-                &engine,
-                args.dry_run,
-                args.verbose,
-                0, // Initial depth
-            ) {
-                Ok(true) => {
-                    file_matched = true;
+            // Process through all cmdline rulesets (entry points)
+            let mut file_matched = false;
+            for ruleset in &cmdline_rulesets {
+                if args.verbose {
+                    println!("  🔧 Starting pipeline with ruleset: {}", ruleset.name);
                 }
-                Ok(false) => {
-                    if args.verbose {
-                        println!("  ⚠️  No rules matched in ruleset: {}", ruleset.name);
+
+                match process_file_recursive(
+                    file_path,
+                    context,
+                    ruleset,
+                    &pipeline,
+                    &engine,
+                    sidecars.get(file_path).map_or(&[], Vec::as_slice),
+                    &mut state,
+                    args.dry_run,
+                    args.verbose,
+                    0, // Initial depth
+                ) {
+                    Ok(true) => {
+                        file_matched = true;
+                    }
+                    Ok(false) => {
+                        if args.verbose {
+                            println!("  ⚠️  No rules matched in ruleset: {}", ruleset.name);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "❌ Error processing {file_path} through ruleset '{}': {e}",
+                            ruleset.name
+                        );
+                        total_errors.fetch_add(1, Ordering::Relaxed);
                     }
-                }
-                Err(e) => {
-                    eprintln!(
-                        "❌ Error processing {file_path} through ruleset '{}': {e}",
-                        ruleset.name
-                    );
-                    total_errors.fetch_add(1, Ordering::Relaxed);
                 }
             }
-        }
 
-        if file_matched {
-            total_matched.fetch_add(1, Ordering::Relaxed);
-        } else if args.verbose {
-            println!("  ⚠️  File did not match any rules: {file_path}");
+            if file_matched {
+                total_matched.fetch_add(1, Ordering::Relaxed);
+            } else if args.verbose {
+                println!("  ⚠️  File did not match any rules: {file_path}");
+            }
         }
     });
 
@@ -243,11 +387,80 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn collect_files(path: &Utf8Path, recursive: bool) -> Result<Vec<Utf8PathBuf>> {
+#[allow(clippy::too_many_arguments)]
+fn calibrate(
+    path: &Utf8Path,
+    recursive: bool,
+    media_types: &MediaTypeConfig,
+    config_path: &str,
+    config_content: &str,
+    location_history: Option<&LocationHistory>,
+    options: &CalibrateOptions,
+    write: bool,
+) -> Result<()> {
+    let files = collect_files(path, recursive, Some(media_types))?;
+    println!("📁 Found {} file(s) to analyze", files.len());
+
+    // Raw camera times: no clock correction and no GPS borrowed from the history
+    let extract_options = ExtractOptions {
+        media_types: Some(Arc::new(media_types.clone())),
+        ..Default::default()
+    };
+    let entries: Vec<BatchEntry> = files
+        .par_iter()
+        .filter_map(
+            |file_path| match extract_metadata_with_options(file_path, &extract_options) {
+                Ok(context) => Some(BatchEntry {
+                    path: file_path.clone(),
+                    context,
+                }),
+                Err(e) => {
+                    eprintln!("❌ Error extracting metadata from {file_path}: {e}");
+                    None
+                }
+            },
+        )
+        .collect();
+
+    let estimates = estimate_offsets(&entries, location_history, options);
+    if estimates.is_empty() {
+        println!("⚠️  Not enough matches to estimate any camera clock offset");
+        return Ok(());
+    }
+
+    println!("\n🕰️  Estimated clock offsets:");
+    for estimate in &estimates {
+        println!(
+            "   {}: {} ({} matches, {})",
+            estimate.camera,
+            format_offset(estimate.offset),
+            estimate.matches,
+            estimate.method
+        );
+    }
+
+    if write {
+        let backup = format!("{config_path}.bak");
+        fs::write(&backup, config_content)
+            .with_context(|| format!("Failed to write backup: {backup}"))?;
+        fs::write(config_path, update_config(config_content, &estimates)?)
+            .with_context(|| format!("Failed to write config file: {config_path}"))?;
+        println!("\n💾 Wrote clock offsets to {config_path} (backup: {backup})");
+    }
+
+    Ok(())
+}
+
+/// Lists the files to process; `media_types` is None to accept non-media files too.
+fn collect_files(
+    path: &Utf8Path,
+    recursive: bool,
+    media_types: Option<&MediaTypeConfig>,
+) -> Result<Vec<Utf8PathBuf>> {
     let mut files = Vec::new();
 
     if path.is_file() {
-        if is_media_file(path)? {
+        if is_media_file(path, media_types) {
             files.push(path.to_path_buf());
         }
     } else if path.is_dir() {
@@ -264,7 +477,7 @@ fn collect_files(path: &Utf8Path, recursive: bool) -> Result<Vec<Utf8PathBuf>> {
                 let file_path = Utf8PathBuf::from_path_buf(entry.path().to_path_buf())
                     .map_err(|_| anyhow::anyhow!("Non-UTF8 path: {:?}", entry.path()))?;
 
-                if is_media_file(&file_path)? {
+                if is_media_file(&file_path, media_types) {
                     files.push(file_path);
                 }
             }
@@ -274,12 +487,17 @@ fn collect_files(path: &Utf8Path, recursive: bool) -> Result<Vec<Utf8PathBuf>> {
     Ok(files)
 }
 
-fn is_media_file(path: &Utf8Path) -> Result<bool> {
-    let mime_type = tree_magic_mini::from_filepath(path.as_std_path());
+fn is_media_file(path: &Utf8Path, media_types: Option<&MediaTypeConfig>) -> bool {
+    media_types.is_none_or(|media_types| media_types.collects(&media_types.classify(path)))
+}
 
-    Ok(mime_type
-        .map(|mime| mime.starts_with("image/") || mime.starts_with("video/"))
-        .unwrap_or(false))
+/// Shared by the files of an asset while rules are applied: the rule matched in
+/// each ruleset by the primary file, replayed for the other files.
+#[derive(Default)]
+struct AssetState {
+    /// Replay the recorded rules instead of evaluating conditions
+    follow: bool,
+    matched: HashMap<String, usize>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -289,6 +507,8 @@ fn process_file_recursive(
     ruleset: &Ruleset,
     pipeline: &Pipeline,
     engine: &RuleEngine,
+    sidecars: &[Sidecar],
+    state: &mut AssetState,
     dry_run: bool,
     verbose: bool,
     depth: usize,
@@ -301,24 +521,43 @@ fn process_file_recursive(
 
     // Process rules in order
     let mut destination_path: Option<Utf8PathBuf> = None;
+    // The sidecars next to `destination_path`, for dependent rulesets
+    let mut carried: Vec<Sidecar> = Vec::new();
+
+    for (index, rule) in ruleset.rules.iter().enumerate() {
+        let outcome = if state.follow {
+            match state.matched.get(&ruleset.name) {
+                Some(&matched) if matched == index => Some(engine.apply_rule(rule, context)?),
+                _ => continue,
+            }
+        } else {
+            engine.process_rule(rule, context)?
+        };
 
-    for rule in &ruleset.rules {
-        match engine.process_rule(rule, context)? {
+        match outcome {
             Some((destination, action)) => {
+                if !state.follow {
+                    state.matched.insert(ruleset.name.clone(), index);
+                }
                 if verbose {
-                    println!("{indent}✅ Rule matched: {}", rule.condition);
+                    if state.follow {
+                        println!("{indent}🔗 Following primary: {}", rule.condition);
+                    } else {
+                        println!("{indent}✅ Rule matched: {}", rule.condition);
+                    }
                     println!("{indent}📁 Destination: {destination}");
                     println!("{indent}🎯 Action: {action:?}");
                 }
 
+                // Sidecars get the same file action, next to the destination
                 if !dry_run {
                     // Convert ActionSpec to Action and execute
                     let action_enum = match &action {
-                        monana::pipeline::ActionSpec::Move => Action::Move,
-                        monana::pipeline::ActionSpec::Copy => Action::Copy,
-                        monana::pipeline::ActionSpec::Symlink => Action::Symlink,
-                        monana::pipeline::ActionSpec::Hardlink => Action::Hardlink,
-                        monana::pipeline::ActionSpec::Command(cmd) => {
+                        ActionSpec::Move => Action::Move,
+                        ActionSpec::Copy => Action::Copy,
+                        ActionSpec::Symlink => Action::Symlink,
+                        ActionSpec::Hardlink => Action::Hardlink,
+                        ActionSpec::Command(cmd) => {
                             // For now, just print custom commands
                             println!("{indent}🔧 Would run custom command: {cmd}");
                             destination_path = Some(Utf8PathBuf::from(&destination));
@@ -328,10 +567,28 @@ fn process_file_recursive(
 
                     let dest_path = Utf8PathBuf::from(&destination);
                     action_enum.execute(file_path, &dest_path)?;
+                    for sidecar in sidecars {
+                        action_enum.execute(&sidecar.path, &sidecar.destination(&dest_path))?;
+                        carried.push(sidecar.relocated(&dest_path));
+                    }
                     destination_path = Some(dest_path);
                 } else {
                     println!("{indent}{file_path} -> {destination}");
-                    destination_path = Some(Utf8PathBuf::from(&destination));
+                    let dest_path = Utf8PathBuf::from(&destination);
+                    // Custom commands leave sidecars where they are
+                    let sidecars = match action {
+                        ActionSpec::Command(_) => &[],
+                        _ => sidecars,
+                    };
+                    for sidecar in sidecars {
+                        println!(
+                            "{indent}{} -> {}",
+                            sidecar.path,
+                            sidecar.destination(&dest_path)
+                        );
+                        carried.push(sidecar.relocated(&dest_path));
+                    }
+                    destination_path = Some(dest_path);
                 }
 
                 // First matching rule wins, exit the loop
@@ -358,6 +615,7 @@ fn process_file_recursive(
             // Create new context with updated source path but preserve all other metadata
             let mut new_context = context.clone();
             new_context.source = create_source_context(&dest_path)?;
+            new_context.source.mime = context.source.mime.clone();
 
             // Recursively process through the dependent ruleset
             process_file_recursive(
@@ -366,6 +624,8 @@ fn process_file_recursive(
                 dependent,
                 pipeline,
                 engine,
+                &carried,
+                state,
                 dry_run,
                 verbose,
                 depth + 1,
@@ -408,5 +668,6 @@ fn create_source_context(path: &Utf8PathBuf) -> Result<SourceContext> {
         extension,
         original,
         size,
+        ..Default::default()
     })
 }
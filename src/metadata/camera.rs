@@ -0,0 +1,364 @@
+// Camera Module
+// Normalized camera identity for the `camera.*` namespace. EXIF makes are spelled
+// differently by every vendor ("NIKON CORPORATION", "samsung") and models often
+// repeat the make, so both are canonicalized through a built-in table that the
+// `cameras:` configuration extends.
+
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::clock::{camera_serial, meta_text};
+use super::context::CameraContext;
+
+/// EXIF makes and their canonical spelling, compared case-insensitively.
+const MAKES: &[(&str, &str)] = &[
+    ("apple", "Apple"),
+    ("arashi vision", "Insta360"),
+    ("canon", "Canon"),
+    ("dji", "DJI"),
+    ("eastman kodak company", "Kodak"),
+    ("fujifilm", "Fujifilm"),
+    ("google", "Google"),
+    ("gopro", "GoPro"),
+    ("hasselblad", "Hasselblad"),
+    ("hmd global", "Nokia"),
+    ("huawei", "Huawei"),
+    ("leica camera ag", "Leica"),
+    ("lg electronics", "LG"),
+    ("motorola", "Motorola"),
+    ("nikon", "Nikon"),
+    ("nikon corporation", "Nikon"),
+    ("olympus corporation", "Olympus"),
+    ("olympus imaging corp.", "Olympus"),
+    ("om digital solutions", "OM System"),
+    ("oneplus", "OnePlus"),
+    ("panasonic", "Panasonic"),
+    ("pentax", "Pentax"),
+    ("pentax corporation", "Pentax"),
+    ("ricoh imaging company, ltd.", "Ricoh"),
+    ("samsung", "Samsung"),
+    ("sigma", "Sigma"),
+    ("sony", "Sony"),
+    ("sz dji technology co., ltd", "DJI"),
+    ("xiaomi", "Xiaomi"),
+];
+
+/// The kind of device each canonical make builds, unless a model says otherwise.
+const MAKE_KINDS: &[(&str, &str)] = &[
+    ("Apple", "phone"),
+    ("Google", "phone"),
+    ("Huawei", "phone"),
+    ("LG", "phone"),
+    ("Motorola", "phone"),
+    ("Nokia", "phone"),
+    ("OnePlus", "phone"),
+    ("Samsung", "phone"),
+    ("Xiaomi", "phone"),
+    ("GoPro", "action_cam"),
+    ("Insta360", "action_cam"),
+    ("DJI", "drone"),
+    ("Canon", "dslr"),
+    ("Fujifilm", "dslr"),
+    ("Hasselblad", "dslr"),
+    ("Kodak", "dslr"),
+    ("Leica", "dslr"),
+    ("Nikon", "dslr"),
+    ("OM System", "dslr"),
+    ("Olympus", "dslr"),
+    ("Panasonic", "dslr"),
+    ("Pentax", "dslr"),
+    ("Ricoh", "dslr"),
+    ("Sigma", "dslr"),
+    ("Sony", "dslr"),
+];
+
+/// Models whose name or kind differ from what the make suggests, as (canonical
+/// make, model prefix, name, kind). An empty name keeps the model as written.
+const MODELS: &[(&str, &str, &str, &str)] = &[
+    ("DJI", "FC3582", "Mini 3 Pro", "drone"),
+    ("DJI", "FC7303", "Mini 2", "drone"),
+    ("DJI", "FC3411", "Air 2S", "drone"),
+    ("DJI", "OsmoAction", "Osmo Action", "action_cam"),
+    ("DJI", "OT-", "", "action_cam"),
+    // The Mavic 2 Pro's Hasselblad camera
+    ("Hasselblad", "L1D-20c", "", "drone"),
+    ("Sony", "XQ-", "", "phone"),
+    ("Sony", "ILCE-7M3", "A7 III", "dslr"),
+    ("Sony", "ILCE-7M4", "A7 IV", "dslr"),
+];
+
+/// Company suffixes dropped from makes the table does not know.
+const COMPANY_SUFFIXES: &[&str] = &[
+    " corporation",
+    " corp.",
+    " co., ltd.",
+    " co., ltd",
+    " co.,ltd",
+    " inc.",
+    " ltd.",
+    " gmbh",
+    " ag",
+    " imaging",
+    " company",
+];
+
+/// A model entry of the `cameras:` configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraModelConfig {
+    /// Canonical make the entry applies to; any make when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub make: Option<String>,
+    /// Model as written by the camera, without the make; a trailing "*" matches
+    /// any model starting with the rest
+    pub model: String,
+    /// Replaces the model name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// phone, dslr, action_cam, drone or anything else
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+/// The `cameras:` configuration section, consulted before the built-in tables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraConfig {
+    /// EXIF make, compared case-insensitively, to canonical make
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub makes: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<CameraModelConfig>,
+}
+
+impl CameraConfig {
+    /// The normalized camera identity from a file's EXIF metadata.
+    pub fn identify(&self, meta: &HashMap<String, Dynamic>) -> CameraContext {
+        let raw_make = meta_text(meta, "Make").unwrap_or_default();
+        let raw_model = meta_text(meta, "Model").unwrap_or_default();
+        if raw_make.is_empty() && raw_model.is_empty() {
+            return CameraContext::default();
+        }
+
+        let make = self.make(&raw_make);
+        let mut model = strip_make(&raw_model, &[&raw_make, &make]);
+        let mut kind = MAKE_KINDS
+            .iter()
+            .find(|(known, _)| *known == make)
+            .map(|(_, kind)| kind.to_string())
+            .unwrap_or_default();
+
+        let user = self.models.iter().find(|entry| {
+            entry
+                .make
+                .as_ref()
+                .is_none_or(|m| m.eq_ignore_ascii_case(&make))
+                && model_matches(&entry.model, &model)
+        });
+        if let Some(entry) = user {
+            if let Some(ref kind_override) = entry.kind {
+                kind = kind_override.clone();
+            }
+            if let Some(ref name) = entry.name {
+                model = name.clone();
+            }
+        } else if let Some((_, _, name, model_kind)) = MODELS
+            .iter()
+            .find(|(known, prefix, ..)| *known == make && model.starts_with(prefix))
+        {
+            kind = model_kind.to_string();
+            if !name.is_empty() {
+                model = name.to_string();
+            }
+        }
+
+        let lens = ["LensModel", "Lens", "LensID"]
+            .iter()
+            .find_map(|tag| meta_text(meta, tag))
+            .unwrap_or_default();
+
+        CameraContext {
+            make,
+            model,
+            serial: camera_serial(meta).unwrap_or_default(),
+            lens,
+            kind,
+        }
+    }
+
+    fn make(&self, raw: &str) -> String {
+        if let Some((_, make)) = self
+            .makes
+            .iter()
+            .find(|(written, _)| written.eq_ignore_ascii_case(raw))
+        {
+            return make.clone();
+        }
+        let lower = raw.to_lowercase();
+        if let Some((_, make)) = MAKES.iter().find(|(written, _)| *written == lower) {
+            return make.to_string();
+        }
+
+        // Unknown makes lose their company suffix and shouting
+        let mut name = raw;
+        while let Some(suffix) = COMPANY_SUFFIXES
+            .iter()
+            .find(|suffix| name.to_ascii_lowercase().ends_with(*suffix))
+        {
+            name = name[..name.len() - suffix.len()].trim_end_matches([' ', ',']);
+        }
+        if name == name.to_uppercase() || name == name.to_lowercase() {
+            title_case(name)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+/// The model without a leading make, e.g. "EOS R6" from "Canon EOS R6".
+fn strip_make(model: &str, makes: &[&str]) -> String {
+    for make in makes {
+        // Compare the first word too: "NIKON D850" from "NIKON CORPORATION"
+        let first_word = make.split_whitespace().next().unwrap_or_default();
+        for prefix in [*make, first_word] {
+            if !prefix.is_empty()
+                && let Some(head) = model.get(..prefix.len())
+                && head.eq_ignore_ascii_case(prefix)
+                && model[prefix.len()..].starts_with(' ')
+            {
+                return model[prefix.len()..].trim().to_string();
+            }
+        }
+    }
+    model.to_string()
+}
+
+fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.to_lowercase().starts_with(&prefix.to_lowercase()),
+        None => model.eq_ignore_ascii_case(pattern),
+    }
+}
+
+fn title_case(name: &str) -> String {
+    name.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identify(config: &CameraConfig, pairs: &[(&str, &str)]) -> CameraContext {
+        let meta = pairs
+            .iter()
+            .map(|(tag, value)| (tag.to_string(), Dynamic::from(value.to_string())))
+            .collect();
+        config.identify(&meta)
+    }
+
+    fn make_model(pairs: &[(&str, &str)]) -> (String, String, String) {
+        let camera = identify(&CameraConfig::default(), pairs);
+        (camera.make, camera.model, camera.kind)
+    }
+
+    #[test]
+    fn test_makes_and_models() {
+        let expect = |make: &str, model: &str, kind: &str| {
+            (make.to_string(), model.to_string(), kind.to_string())
+        };
+        assert_eq!(
+            make_model(&[("Make", "NIKON CORPORATION"), ("Model", "NIKON D850")]),
+            expect("Nikon", "D850", "dslr")
+        );
+        assert_eq!(
+            make_model(&[("Make", "Canon"), ("Model", "Canon EOS R6\0")]),
+            expect("Canon", "EOS R6", "dslr")
+        );
+        assert_eq!(
+            make_model(&[("Make", "samsung"), ("Model", "SM-G991B")]),
+            expect("Samsung", "SM-G991B", "phone")
+        );
+        assert_eq!(
+            make_model(&[("Make", "OLYMPUS IMAGING CORP."), ("Model", "E-M5MarkII")]),
+            expect("Olympus", "E-M5MarkII", "dslr")
+        );
+        assert_eq!(
+            make_model(&[("Make", "Apple"), ("Model", "iPhone 15 Pro")]),
+            expect("Apple", "iPhone 15 Pro", "phone")
+        );
+        assert_eq!(
+            make_model(&[("Make", "DJI"), ("Model", "FC3582")]),
+            expect("DJI", "Mini 3 Pro", "drone")
+        );
+        assert_eq!(
+            make_model(&[("Make", "GoPro"), ("Model", "HERO11 Black")]),
+            expect("GoPro", "HERO11 Black", "action_cam")
+        );
+        assert_eq!(
+            make_model(&[("Make", "ACME OPTICS CO., LTD."), ("Model", "X1")]),
+            expect("Acme Optics", "X1", "")
+        );
+        assert_eq!(make_model(&[]), expect("", "", ""));
+    }
+
+    #[test]
+    fn test_serial_and_lens() {
+        let camera = identify(
+            &CameraConfig::default(),
+            &[
+                ("Make", "Canon"),
+                ("Model", "Canon EOS R6"),
+                ("SerialNumber", "012345"),
+                ("LensModel", "RF24-105mm F4 L IS USM"),
+            ],
+        );
+        assert_eq!(camera.serial, "012345");
+        assert_eq!(camera.lens, "RF24-105mm F4 L IS USM");
+    }
+
+    #[test]
+    fn test_configured_entries() {
+        let config: CameraConfig = serde_yaml::from_str(
+            r#"
+makes:
+  "ACME OPTICS CO., LTD.": Acme
+models:
+  - make: Acme
+    model: X1
+    name: X-One
+    kind: action_cam
+  - make: Sony
+    model: "ILCE-*"
+    kind: mirrorless
+"#,
+        )
+        .unwrap();
+
+        let acme = identify(
+            &config,
+            &[("Make", "Acme Optics Co., Ltd."), ("Model", "X1")],
+        );
+        assert_eq!(
+            (acme.make.as_str(), acme.model.as_str(), acme.kind.as_str()),
+            ("Acme", "X-One", "action_cam")
+        );
+
+        // Configured entries come before the built-in ones
+        let sony = identify(&config, &[("Make", "SONY"), ("Model", "ILCE-7M3")]);
+        assert_eq!(
+            (sony.model.as_str(), sony.kind.as_str()),
+            ("ILCE-7M3", "mirrorless")
+        );
+    }
+}
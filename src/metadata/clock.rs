@@ -0,0 +1,228 @@
+// Clock Module
+// Per-camera clock corrections from the `clock_offsets:` configuration section, for
+// cameras whose clock drifts or was never switched to daylight saving time.
+
+use anyhow::{Context, Result, bail};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::values::Datetime;
+
+/// EXIF tags that may hold the camera body serial number. ExifTool calls the EXIF
+/// BodySerialNumber (0xA431) SerialNumber.
+const SERIAL_TAGS: [&str; 2] = ["SerialNumber", "BodySerialNumber"];
+
+/// A clock correction as written in the configuration. Every camera field that is
+/// set must match; `from` and `until` bound the camera's own (uncorrected) time,
+/// and a date-only `until` includes that whole day.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClockOffsetConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub make: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    /// Added to the camera time, as "[+-]HH:MM[:SS]"
+    pub offset: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ClockOffset {
+    make: Option<String>,
+    model: Option<String>,
+    serial: Option<String>,
+    offset: Duration,
+    from: Option<Datetime>,
+    until: Option<Datetime>,
+}
+
+/// All configured clock corrections, in configuration order.
+#[derive(Debug, Clone, Default)]
+pub struct ClockOffsets {
+    offsets: Vec<ClockOffset>,
+}
+
+/// Parses "[+-]HH:MM[:SS]" into a duration.
+pub fn parse_offset(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let parts = rest
+        .split(':')
+        .map(|part| part.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid clock offset: {value}"))?;
+    let seconds = match parts.as_slice() {
+        [hours, minutes] => hours * 3600 + minutes * 60,
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        _ => bail!("Invalid clock offset: {value} (expected [+-]HH:MM[:SS])"),
+    };
+
+    Ok(Duration::seconds(sign * seconds))
+}
+
+/// Formats a duration as "[+-]HH:MM:SS", the inverse of [`parse_offset`].
+pub fn format_offset(offset: Duration) -> String {
+    let sign = if offset < Duration::zero() { '-' } else { '+' };
+    let seconds = offset.num_seconds().abs();
+    format!(
+        "{sign}{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Reads a text tag from the EXIF metadata, trimmed of padding.
+pub(crate) fn meta_text(meta: &HashMap<String, Dynamic>, tag: &str) -> Option<String> {
+    let value = meta.get(tag)?;
+    let text = if value.is_string() {
+        value.clone().into_string().ok()?
+    } else {
+        value.to_string()
+    };
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The camera body serial number, if the EXIF metadata records one.
+pub fn camera_serial(meta: &HashMap<String, Dynamic>) -> Option<String> {
+    SERIAL_TAGS.iter().find_map(|tag| meta_text(meta, tag))
+}
+
+// A date-only `until` includes the whole day, not just its midnight
+fn end_of_day(value: &Option<String>, until: Datetime) -> Datetime {
+    let date_only = value
+        .as_deref()
+        .is_some_and(|v| NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d").is_ok());
+    if !date_only {
+        return until;
+    }
+    let end = until.local().date().and_time(NaiveTime::MIN) + Duration::days(1)
+        - Duration::nanoseconds(1);
+    Datetime::naive(end)
+}
+
+impl ClockOffsets {
+    pub fn load(configs: &[ClockOffsetConfig]) -> Result<Self> {
+        let parse_bound = |value: &Option<String>| -> Result<Option<Datetime>> {
+            value
+                .as_deref()
+                .map(|v| Datetime::parse(v).with_context(|| format!("Invalid date: {v}")))
+                .transpose()
+        };
+
+        let offsets = configs
+            .iter()
+            .map(|config| {
+                Ok(ClockOffset {
+                    make: config.make.clone(),
+                    model: config.model.clone(),
+                    serial: config.serial.clone(),
+                    offset: parse_offset(&config.offset)?,
+                    from: parse_bound(&config.from)?,
+                    until: parse_bound(&config.until)?
+                        .map(|until| end_of_day(&config.until, until)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| "Failed to load clock offsets")?;
+
+        Ok(Self { offsets })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The correction for a file from the given camera, captured at `time` by the
+    /// camera's clock.
+    pub fn find(&self, meta: &HashMap<String, Dynamic>, time: &Datetime) -> Option<Duration> {
+        let matches = |expected: &Option<String>, actual: Option<String>| match expected {
+            Some(expected) => {
+                actual.is_some_and(|actual| actual.eq_ignore_ascii_case(expected.trim()))
+            }
+            None => true,
+        };
+
+        self.offsets
+            .iter()
+            .find(|clock| {
+                matches(&clock.make, meta_text(meta, "Make"))
+                    && matches(&clock.model, meta_text(meta, "Model"))
+                    && matches(&clock.serial, camera_serial(meta))
+                    && clock.from.is_none_or(|from| *time >= from)
+                    && clock.until.is_none_or(|until| *time <= until)
+            })
+            .map(|clock| clock.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(pairs: &[(&str, &str)]) -> HashMap<String, Dynamic> {
+        pairs
+            .iter()
+            .map(|(tag, value)| (tag.to_string(), Dynamic::from(value.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("+01:00").unwrap(), Duration::hours(1));
+        assert_eq!(parse_offset("-00:03:20").unwrap(), Duration::seconds(-200));
+        assert_eq!(parse_offset("2:30:00").unwrap(), Duration::minutes(150));
+        assert!(parse_offset("1h").is_err());
+        assert!(parse_offset("1").is_err());
+
+        assert_eq!(format_offset(Duration::seconds(-200)), "-00:03:20");
+        assert_eq!(format_offset(Duration::hours(26)), "+26:00:00");
+    }
+
+    #[test]
+    fn test_find() {
+        let configs: Vec<ClockOffsetConfig> = serde_yaml::from_str(
+            r#"
+- model: Canon EOS R6
+  serial: "012345"
+  offset: "+01:00"
+  until: "2024-03-31"
+- model: canon eos r6
+  offset: "-00:02:00"
+"#,
+        )
+        .unwrap();
+        let offsets = ClockOffsets::load(&configs).unwrap();
+
+        let winter = Datetime::parse("2024-01-10 10:00").unwrap();
+        let summer = Datetime::parse("2024-07-10 10:00").unwrap();
+        let last_evening = Datetime::parse("2024-03-31 21:45").unwrap();
+        let next_day = Datetime::parse("2024-04-01 00:00").unwrap();
+
+        let body = meta(&[("Model", "Canon EOS R6\0"), ("SerialNumber", "012345")]);
+        assert_eq!(offsets.find(&body, &winter), Some(Duration::hours(1)));
+        assert_eq!(offsets.find(&body, &summer), Some(Duration::minutes(-2)));
+        assert_eq!(offsets.find(&body, &last_evening), Some(Duration::hours(1)));
+        assert_eq!(offsets.find(&body, &next_day), Some(Duration::minutes(-2)));
+
+        let other_body = meta(&[("Model", "Canon EOS R6"), ("BodySerialNumber", "999")]);
+        assert_eq!(
+            offsets.find(&other_body, &winter),
+            Some(Duration::minutes(-2))
+        );
+
+        assert_eq!(offsets.find(&meta(&[("Model", "X100V")]), &winter), None);
+    }
+}
@@ -1,4 +1,6 @@
 use chrono::{DateTime, Utc};
+
+use super::values::Datetime;
 use rhai::Dynamic;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -9,7 +11,17 @@ pub struct MediaContext {
     pub space: SpaceContext,
     pub source: SourceContext,
     pub special: SpecialContext,
+    pub event: EventContext,
+    pub trip: TripContext,
+    pub group: GroupContext,
+    pub takeout: TakeoutContext,
+    pub xmp: XmpContext,
+    pub iptc: IptcContext,
+    pub camera: CameraContext,
+    pub image: ImageContext,
     pub r#type: String,
+    /// Finer classification: raw, heic, gif, screenshot, scan, audio or document
+    pub subtype: String,
     pub meta: HashMap<String, Dynamic>,
 }
 
@@ -24,6 +36,14 @@ pub struct TimeContext {
     pub month_name: String,
     pub weekday: String,
     pub timestamp: Option<DateTime<Utc>>,
+    /// The timestamp is a wall-clock time without UTC offset, stored as if it were UTC
+    pub naive: bool,
+    /// Offset of the wall-clock fields from UTC, in seconds
+    pub utc_offset_secs: i32,
+    /// Capture time as recorded by the camera, before clock offset correction
+    pub original: Option<Datetime>,
+    /// Where the capture time came from: "exif", "takeout", "filename" or "filesystem"
+    pub source: String,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -31,12 +51,32 @@ pub struct SpaceContext {
     pub country: String,
     pub country_code: String,
     pub state: String,
+    pub county: String,
     pub city: String,
     pub district: String,
     pub road: String,
     pub lat: f64,
     pub lon: f64,
     pub altitude: Option<f64>,
+    /// Distance to the place matched by reverse geocoding
+    pub place_distance_km: Option<f64>,
+    pub place_name: String,
+    pub place_address: String,
+    pub place_id: String,
+    /// Name of the configured place containing the coordinates
+    pub place: String,
+    /// IANA time zone at the coordinates
+    pub timezone: String,
+    /// Where the coordinates came from: "exif", "takeout", "location_history" or
+    /// "neighbor"
+    pub source: String,
+}
+
+impl SpaceContext {
+    /// Coordinates as (lat, lon), unless the file has no location.
+    pub fn position(&self) -> Option<(f64, f64)> {
+        (self.lat != 0.0 || self.lon != 0.0).then_some((self.lat, self.lon))
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -46,6 +86,7 @@ pub struct SourceContext {
     pub extension: String,
     pub original: String,
     pub size: u64,
+    pub mime: String,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -53,4 +94,120 @@ pub struct SpecialContext {
     pub md5: String,
     pub md5_short: String,
     pub count: u32,
+    /// Perceptual hash of an image as 16 hex digits, empty when not computed
+    pub phash: String,
+    /// Short MD5 of the best copy when the file has duplicates, otherwise empty
+    pub dup_group: String,
+    /// No better copy of the file was found in the run
+    pub is_best: bool,
+}
+
+/// The event a file was clustered into; `id` is 0 when it belongs to none.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EventContext {
+    pub id: u32,
+    pub name: String,
+    pub start: Option<Datetime>,
+    pub end: Option<Datetime>,
+    pub size: u32,
+}
+
+/// The trip away from home a file belongs to; `id` is 0 when it belongs to none.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TripContext {
+    pub id: u32,
+    pub name: String,
+    pub start: Option<Datetime>,
+    pub end: Option<Datetime>,
+    /// Day of the trip the file was captured on, starting at 1
+    pub day: u32,
+}
+
+/// The asset a file belongs to with its companions, such as the JPEG next to a RAW
+/// or the video of a Live Photo; `id` is 0 when the file stands alone.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GroupContext {
+    pub id: u32,
+    /// "primary", "jpeg" for a JPEG beside the RAW primary, or "live" for the motion
+    /// video of a Live Photo
+    pub role: String,
+    pub size: u32,
+}
+
+/// What the Google Photos Takeout JSON sidecar of a file says; `json` is empty when
+/// the file has none.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TakeoutContext {
+    /// Path of the JSON sidecar
+    pub json: String,
+    pub title: String,
+    pub description: String,
+    pub people: Vec<String>,
+    pub url: String,
+    pub favorited: bool,
+    pub taken: Option<Datetime>,
+}
+
+/// Ratings and tags from the file's XMP, embedded or in a `.xmp` sidecar.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct XmpContext {
+    /// Star rating from 0 to 5, or -1 for rejected
+    pub rating: Option<i64>,
+    /// Color label, e.g. "Red"
+    pub label: String,
+    pub title: String,
+    /// Keywords (dc:subject)
+    pub keywords: Vec<String>,
+    /// Names of tagged regions, usually people
+    pub regions: Vec<String>,
+    /// "embedded", "sidecar" or empty when the file has no XMP
+    pub source: String,
+}
+
+/// IPTC-IIM fields from a JPEG's APP13 segment.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IptcContext {
+    /// Object name
+    pub title: String,
+    pub headline: String,
+    /// Caption/abstract
+    pub caption: String,
+    pub keywords: Vec<String>,
+    pub byline: Vec<String>,
+    pub credit: String,
+    pub source: String,
+    pub copyright: String,
+    pub city: String,
+    pub sublocation: String,
+    pub state: String,
+    pub country: String,
+    /// As written, usually ISO 3166 alpha-3
+    pub country_code: String,
+}
+
+/// The camera that took the file, normalized across vendors' EXIF spellings.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CameraContext {
+    /// Canonical make, e.g. "Nikon" for "NIKON CORPORATION"
+    pub make: String,
+    /// Model without the make, e.g. "D850"
+    pub model: String,
+    pub serial: String,
+    pub lens: String,
+    /// phone, dslr, action_cam or drone; empty when unknown
+    pub kind: String,
+}
+
+/// Size and shape as displayed, after the EXIF Orientation is applied.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ImageContext {
+    /// 0 when unknown
+    pub width: i64,
+    pub height: i64,
+    pub megapixels: f64,
+    /// Width over height
+    pub aspect_ratio: f64,
+    /// portrait, landscape or square; empty when the size is unknown
+    pub orientation_class: String,
+    pub is_panorama: bool,
 }
@@ -0,0 +1,175 @@
+// Dimensions Module
+// Image size as displayed, for the `image.*` namespace. Cameras store pixels in
+// sensor order and record the rotation in the EXIF Orientation tag, so a portrait
+// phone photo is stored 4032x3024; viewers turn it, and so does this module.
+
+use rhai::Dynamic;
+use std::collections::HashMap;
+
+use super::context::ImageContext;
+
+/// Long side over short side from which an image counts as a panorama. Phone
+/// screenshots (up to about 2.2) stay below it.
+const PANORAMA_RATIO: f64 = 2.5;
+
+/// Aspect ratios this close to 1 count as square.
+const SQUARE_TOLERANCE: f64 = 0.01;
+
+fn int(meta: &HashMap<String, Dynamic>, tags: &[&str]) -> Option<i64> {
+    tags.iter()
+        .find_map(|tag| meta.get(*tag).and_then(|v| v.as_int().ok()))
+        .filter(|value| *value > 0)
+}
+
+/// Whether the file says it is turned a quarter: EXIF Orientation 5 to 8, or a
+/// video Rotation of 90 or 270 degrees.
+fn quarter_turned(meta: &HashMap<String, Dynamic>) -> bool {
+    match int(meta, &["Orientation"]) {
+        Some(orientation) => (5..=8).contains(&orientation),
+        None => matches!(int(meta, &["Rotation"]), Some(90 | 270)),
+    }
+}
+
+/// Panorama viewers' own markers, written by phones and stitching software.
+fn tagged_panorama(meta: &HashMap<String, Dynamic>) -> bool {
+    let projection = meta
+        .get("ProjectionType")
+        .and_then(|v| v.clone().into_string().ok())
+        .is_some_and(|p| p.eq_ignore_ascii_case("equirectangular"));
+    let viewer = meta
+        .get("UsePanoramaViewer")
+        .is_some_and(|v| v.as_bool().unwrap_or(false) || v.as_int().unwrap_or(0) == 1);
+    projection || viewer
+}
+
+/// The displayed size and shape of an image or video. RAW files report the sensor
+/// size read from the RAW structure; other files prefer the EXIF IFD's pixel
+/// dimensions, as duplicate detection does.
+pub fn describe(meta: &HashMap<String, Dynamic>, raw: bool) -> ImageContext {
+    let (widths, heights): (&[&str], &[&str]) = if raw {
+        (
+            &["ImageWidth", "ExifImageWidth"],
+            &["ImageHeight", "ExifImageHeight"],
+        )
+    } else {
+        (
+            &["ExifImageWidth", "ImageWidth"],
+            &["ExifImageHeight", "ImageHeight"],
+        )
+    };
+    let (Some(stored_width), Some(stored_height)) = (int(meta, widths), int(meta, heights)) else {
+        return ImageContext::default();
+    };
+
+    let (width, height) = if quarter_turned(meta) {
+        (stored_height, stored_width)
+    } else {
+        (stored_width, stored_height)
+    };
+    let aspect_ratio = width as f64 / height as f64;
+    let orientation_class = if (aspect_ratio - 1.0).abs() < SQUARE_TOLERANCE {
+        "square"
+    } else if width > height {
+        "landscape"
+    } else {
+        "portrait"
+    };
+    let elongation = aspect_ratio.max(1.0 / aspect_ratio);
+
+    ImageContext {
+        width,
+        height,
+        megapixels: (width * height) as f64 / 1_000_000.0,
+        aspect_ratio,
+        orientation_class: orientation_class.to_string(),
+        is_panorama: elongation >= PANORAMA_RATIO || tagged_panorama(meta),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(pairs: &[(&str, i64)]) -> HashMap<String, Dynamic> {
+        pairs
+            .iter()
+            .map(|(tag, value)| (tag.to_string(), Dynamic::from_int(*value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_rotated_phone_photo() {
+        let image = describe(
+            &meta(&[
+                ("ExifImageWidth", 4032),
+                ("ExifImageHeight", 3024),
+                ("Orientation", 6),
+            ]),
+            false,
+        );
+        assert_eq!((image.width, image.height), (3024, 4032));
+        assert_eq!(image.orientation_class, "portrait");
+        assert!((image.megapixels - 12.19).abs() < 0.01);
+        assert!((image.aspect_ratio - 0.75).abs() < 1e-9);
+        assert!(!image.is_panorama);
+
+        let upright = describe(
+            &meta(&[
+                ("ImageWidth", 4032),
+                ("ImageHeight", 3024),
+                ("Orientation", 1),
+            ]),
+            false,
+        );
+        assert_eq!(upright.orientation_class, "landscape");
+    }
+
+    #[test]
+    fn test_shapes() {
+        let square = describe(&meta(&[("ImageWidth", 3000), ("ImageHeight", 3010)]), false);
+        assert_eq!(square.orientation_class, "square");
+
+        let panorama = describe(
+            &meta(&[("ImageWidth", 12000), ("ImageHeight", 3000)]),
+            false,
+        );
+        assert!(panorama.is_panorama);
+
+        // A tall phone screenshot is not a panorama
+        let screenshot = describe(&meta(&[("ImageWidth", 1170), ("ImageHeight", 2532)]), false);
+        assert_eq!(screenshot.orientation_class, "portrait");
+        assert!(!screenshot.is_panorama);
+
+        let mut sphere = meta(&[("ImageWidth", 4000), ("ImageHeight", 3000)]);
+        sphere.insert(
+            "ProjectionType".to_string(),
+            Dynamic::from("equirectangular".to_string()),
+        );
+        assert!(describe(&sphere, false).is_panorama);
+    }
+
+    #[test]
+    fn test_sources() {
+        // RAW files trust the sensor size over the preview's EXIF dimensions
+        let stored = meta(&[
+            ("ImageWidth", 6048),
+            ("ImageHeight", 4024),
+            ("ExifImageWidth", 1620),
+            ("ExifImageHeight", 1080),
+        ]);
+        assert_eq!(describe(&stored, true).width, 6048);
+        assert_eq!(describe(&stored, false).width, 1620);
+
+        let video = describe(
+            &meta(&[
+                ("ImageWidth", 1920),
+                ("ImageHeight", 1080),
+                ("Rotation", 90),
+            ]),
+            false,
+        );
+        assert_eq!((video.width, video.height), (1080, 1920));
+
+        assert_eq!(describe(&meta(&[("ImageWidth", 1920)]), false).width, 0);
+    }
+}
@@ -0,0 +1,410 @@
+// ExifTool Module
+// Optional metadata backend that asks an external exiftool for the tags the native
+// parsers miss. Each worker thread reuses one `-stay_open` process for the whole run
+// instead of paying exiftool's start-up time for every file.
+
+use anyhow::{Context, Result, bail};
+use camino::Utf8Path;
+use rhai::Dynamic;
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+
+use super::values::Datetime;
+
+/// Which files are passed to exiftool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExifToolMode {
+    /// Files whose EXIF could not be read or carries no capture time
+    #[default]
+    Fallback,
+    /// Every media file
+    Always,
+}
+
+/// The `exiftool:` configuration section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExifToolConfig {
+    /// Program to run; it must understand `-stay_open True -@ -`
+    #[serde(default = "default_command")]
+    pub command: String,
+    /// Arguments sent with every file; the output must be `-json`
+    #[serde(default = "default_args")]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub mode: ExifToolMode,
+}
+
+impl Default for ExifToolConfig {
+    fn default() -> Self {
+        Self {
+            command: default_command(),
+            args: default_args(),
+            mode: ExifToolMode::default(),
+        }
+    }
+}
+
+fn default_command() -> String {
+    "exiftool".to_string()
+}
+
+fn default_args() -> Vec<String> {
+    ["-json", "-n", "-G"].map(String::from).to_vec()
+}
+
+/// A running `exiftool -stay_open True -@ -`, reading arguments line by line.
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Process {
+    fn spawn(command: &str) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(["-stay_open", "True", "-@", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start {command}"))?;
+        let stdin = child.stdin.take().context("exiftool stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("exiftool stdout")?);
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Runs one command and returns its output, which ends at the "{ready}" line.
+    fn execute(&mut self, args: &[String], path: &Utf8Path) -> Result<String> {
+        for arg in args {
+            writeln!(self.stdin, "{arg}")?;
+        }
+        writeln!(self.stdin, "{path}")?;
+        writeln!(self.stdin, "-execute")?;
+        self.stdin.flush()?;
+
+        let mut output = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                bail!("exiftool exited while reading {path}");
+            }
+            if line.trim_end() == "{ready}" {
+                return Ok(output);
+            }
+            output.push_str(&line);
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "-stay_open\nFalse");
+        let _ = self.stdin.flush();
+        let _ = self.child.wait();
+    }
+}
+
+/// A pool of exiftool processes, one per thread that is reading at the same time.
+pub struct ExifTool {
+    config: ExifToolConfig,
+    idle: Mutex<Vec<Process>>,
+}
+
+impl ExifTool {
+    /// Starts the first process, failing early when the command cannot be run.
+    pub fn start(config: &ExifToolConfig) -> Result<Self> {
+        let process = Process::spawn(&config.command)?;
+        Ok(Self {
+            config: config.clone(),
+            idle: Mutex::new(vec![process]),
+        })
+    }
+
+    pub fn mode(&self) -> ExifToolMode {
+        self.config.mode
+    }
+
+    /// The tags exiftool reports for `path`, keyed and ordered as in its JSON output.
+    pub fn read(&self, path: &Utf8Path) -> Result<Vec<(String, Dynamic)>> {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut process = match idle {
+            Some(process) => process,
+            None => Process::spawn(&self.config.command)?,
+        };
+        // A process that failed mid-answer is dropped rather than reused
+        let output = process.execute(&self.config.args, path)?;
+        self.idle.lock().unwrap().push(process);
+        parse(&output)
+    }
+}
+
+impl fmt::Debug for ExifTool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExifTool")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// One file of exiftool's `-json` output, with the tags in the order written.
+struct Record(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RecordVisitor;
+
+        impl<'de> Visitor<'de> for RecordVisitor {
+            type Value = Record;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an exiftool JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Record, A::Error> {
+                let mut tags = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    tags.push(entry);
+                }
+                Ok(Record(tags))
+            }
+        }
+
+        deserializer.deserialize_map(RecordVisitor)
+    }
+}
+
+/// Parses exiftool's `-json` output for one file, keeping its order. Numbers,
+/// booleans and lists keep their types and EXIF-style dates become `Datetime`s;
+/// QuickTime dates without a zone are UTC, as the QuickTime format defines them.
+pub fn parse(output: &str) -> Result<Vec<(String, Dynamic)>> {
+    // exiftool prints nothing for files it cannot read
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let records: Vec<Record> =
+        serde_json::from_str(output).context("Failed to parse exiftool output")?;
+    Ok(records
+        .into_iter()
+        .next()
+        .map(|record| record.0)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| key != "SourceFile")
+        .map(|(key, value)| {
+            let utc = group(&key).is_some_and(|group| group.starts_with("QuickTime"));
+            let value = json_value(&value, utc);
+            (key, value)
+        })
+        .collect())
+}
+
+fn group(key: &str) -> Option<&str> {
+    key.rsplit_once(':').map(|(group, _)| group)
+}
+
+/// Which group's value a plain tag name gets when several report it: EXIF, then
+/// XMP, then QuickTime, then the rest in exiftool's order. Family 1 names such as
+/// ExifIFD or XMP-exif rank with their family 0 group.
+fn group_rank(group: &str) -> u8 {
+    match group {
+        "EXIF" | "IFD0" | "ExifIFD" | "GPS" => 0,
+        _ if group.starts_with("XMP") => 1,
+        _ if group.starts_with("QuickTime") => 2,
+        _ => 3,
+    }
+}
+
+/// Adds exiftool's tags to `meta`, under the group-qualified key as exiftool wrote
+/// it and under the plain tag name unless the native parser already set it.
+pub fn merge(meta: &mut HashMap<String, Dynamic>, tags: Vec<(String, Dynamic)>) {
+    let mut plain: HashMap<String, (u8, Dynamic)> = HashMap::new();
+    for (key, value) in tags {
+        if let Some((group, name)) = key.rsplit_once(':') {
+            let rank = group_rank(group);
+            if plain.get(name).is_none_or(|(best, _)| rank < *best) {
+                plain.insert(name.to_string(), (rank, value.clone()));
+            }
+        }
+        meta.entry(key).or_insert(value);
+    }
+    for (name, (_, value)) in plain {
+        meta.entry(name).or_insert(value);
+    }
+}
+
+fn json_value(value: &Value, utc: bool) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(b) => Dynamic::from_bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Dynamic::from_int(i),
+            None => Dynamic::from_float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => match exif_date(s, utc) {
+            Some(datetime) => Dynamic::from(datetime),
+            None => Dynamic::from(s.clone()),
+        },
+        Value::Array(items) => {
+            Dynamic::from_array(items.iter().map(|item| json_value(item, utc)).collect())
+        }
+        Value::Object(map) => Dynamic::from_map(
+            map.iter()
+                .map(|(key, value)| (key.into(), json_value(value, utc)))
+                .collect(),
+        ),
+    }
+}
+
+/// "2024:07:01 18:30:00", with optional sub-seconds and "+02:00" or "Z". Without
+/// a zone the time is naive, or UTC when `utc` is set.
+fn exif_date(text: &str, utc: bool) -> Option<Datetime> {
+    let bytes = text.as_bytes();
+    let shape = bytes.len() >= 19
+        && bytes[..19].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 | 13 | 16 => *b == b':',
+            10 => *b == b' ',
+            _ => b.is_ascii_digit(),
+        });
+    if !shape {
+        return None;
+    }
+
+    let (date, time) = (&text[..10], &text[11..19]);
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match rest {
+        "" if utc => "Z",
+        "" => "",
+        "Z" | "+00:00" => "Z",
+        _ if rest.len() == 6 && (rest.starts_with('+') || rest.starts_with('-')) => rest,
+        _ => return None,
+    };
+    Datetime::parse(&format!("{}T{time}{offset}", date.replace(':', "-")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r#"[{
+            "SourceFile": "IMG_0001.MOV",
+            "QuickTime:CreateDate": "2024:07:01 16:30:00",
+            "EXIF:DateTimeOriginal": "2024:07:01 18:30:00.25+02:00",
+            "EXIF:ModifyDate": "0000:00:00 00:00:00",
+            "EXIF:ISO": 200,
+            "EXIF:FNumber": 2.8,
+            "XMP:Subject": ["beach", "Lisbon"],
+            "Composite:Flash": true
+        }]"#;
+        let tags: HashMap<String, Dynamic> = parse(output).unwrap().into_iter().collect();
+        assert!(!tags.contains_key("SourceFile"));
+        assert_eq!(tags["EXIF:ISO"].as_int(), Ok(200));
+        assert_eq!(tags["EXIF:FNumber"].as_float(), Ok(2.8));
+        assert_eq!(tags["Composite:Flash"].as_bool(), Ok(true));
+        assert_eq!(tags["XMP:Subject"].clone().into_array().unwrap().len(), 2);
+
+        let taken = tags["EXIF:DateTimeOriginal"].clone().cast::<Datetime>();
+        assert_eq!(taken.to_string(), "2024-07-01T18:30:00+02:00");
+        // QuickTime stores UTC
+        let created = tags["QuickTime:CreateDate"].clone().cast::<Datetime>();
+        assert_eq!(created.to_string(), "2024-07-01T16:30:00+00:00");
+        assert!(tags["EXIF:ModifyDate"].is_string());
+
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("not json").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut meta = HashMap::from([("ISO".to_string(), Dynamic::from_int(100))]);
+        let tags = parse(r#"[{"EXIF:ISO": 200, "QuickTime:Duration": 12.5}]"#).unwrap();
+        merge(&mut meta, tags);
+
+        // The native value keeps the plain name
+        assert_eq!(meta["ISO"].as_int(), Ok(100));
+        assert_eq!(meta["EXIF:ISO"].as_int(), Ok(200));
+        assert_eq!(meta["Duration"].as_float(), Ok(12.5));
+    }
+
+    #[test]
+    fn test_merge_group_priority() {
+        let output = r#"[{
+            "QuickTime:CreateDate": "2024:07:01 16:30:00",
+            "XMP:CreateDate": "2024:07:01 18:29:00+02:00",
+            "XMP:DateTimeOriginal": "2024:07:01 18:29:00+02:00",
+            "EXIF:DateTimeOriginal": "2024:07:01 18:30:00",
+            "MakerNotes:Quality": "Fine",
+            "File:Quality": "Other"
+        }]"#;
+        let tags = parse(output).unwrap();
+        let keys: Vec<&str> = tags.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys[0], "QuickTime:CreateDate");
+
+        let mut meta = HashMap::new();
+        merge(&mut meta, tags);
+        let date = |tag: &str| meta[tag].clone().cast::<Datetime>().to_string();
+        assert_eq!(date("DateTimeOriginal"), "2024-07-01T18:30:00");
+        assert_eq!(date("CreateDate"), "2024-07-01T18:29:00+02:00");
+        assert_eq!(date("QuickTime:CreateDate"), "2024-07-01T16:30:00+00:00");
+        // Other groups keep exiftool's order
+        assert_eq!(meta["Quality"].clone().into_string().unwrap(), "Fine");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stay_open() {
+        let config = ExifToolConfig {
+            command: concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/exiftool_stub.sh").into(),
+            ..Default::default()
+        };
+        let exiftool = ExifTool::start(&config).unwrap();
+
+        let read = |path: &str| -> HashMap<String, Dynamic> {
+            exiftool
+                .read(Utf8Path::new(path))
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        let first = read("a.jpg");
+        assert_eq!(first["EXIF:ISO"].as_int(), Ok(200));
+        assert_eq!(
+            first["File:FileName"].clone().into_string().unwrap(),
+            "a.jpg"
+        );
+
+        // The same process answers the next file
+        let second = read("b.jpg");
+        assert_eq!(
+            second["File:FileName"].clone().into_string().unwrap(),
+            "b.jpg"
+        );
+        assert_eq!(second["Stub:Request"].as_int(), Ok(2));
+
+        // Files exiftool cannot read give no tags
+        assert!(read("missing.jpg").is_empty());
+
+        assert!(
+            ExifTool::start(&ExifToolConfig {
+                command: "/nonexistent/exiftool".into(),
+                ..Default::default()
+            })
+            .is_err()
+        );
+    }
+}
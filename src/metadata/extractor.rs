@@ -1,18 +1,54 @@
 use anyhow::{Context, Result};
 use camino::Utf8Path;
-use chrono::{DateTime, Utc};
-use nom_exif::{ExifIter, ExifTag, MediaParser, MediaSource};
+use chrono::{DateTime, FixedOffset, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use nom_exif::{EntryValue, ExifIter, ExifTag, MediaParser, MediaSource};
 use rhai::Dynamic;
+use std::fmt::Display;
 use std::fs;
 use std::io::Read;
 use std::sync::Arc;
 
-use super::context::{MediaContext, SourceContext, SpecialContext, TimeContext};
+use super::camera::CameraConfig;
+use super::clock::{ClockOffsets, format_offset};
+use super::context::{MediaContext, SourceContext, SpecialContext, TakeoutContext, TimeContext};
+use super::dimensions;
+use super::exiftool::{self, ExifTool, ExifToolMode};
+use super::iptc;
+use super::live_photo::content_identifier;
 use super::location::reverse_geocode;
-use super::location_history::LocationHistory;
+use super::location_history::{LocationHistory, LocationPoint};
+use super::media_type::MediaTypeConfig;
+use super::phash::phash_file;
+use super::raw;
+use super::tags;
+use super::takeout::{TakeoutData, read_sidecar as read_takeout_sidecar};
+use super::timezone::{localize_time, timezone_at};
+use super::values::{Datetime, exif_value};
+use super::xmp;
+
+/// Run-wide inputs to metadata extraction.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub location_history: Option<Arc<LocationHistory>>,
+    /// Maximum time difference in hours to search for location history
+    pub max_hours: Option<u64>,
+    pub clock_offsets: Option<Arc<ClockOffsets>>,
+    /// Decode images to compute `special.phash`
+    pub perceptual_hash: bool,
+    pub media_types: Option<Arc<MediaTypeConfig>>,
+    /// Read the Live Photo ContentIdentifier into `meta.ContentIdentifier`
+    pub content_identifier: bool,
+    /// Replace reverse-geocoded location names with IPTC ones
+    pub iptc_location: bool,
+    /// External exiftool for what the native parsers cannot read
+    pub exiftool: Option<Arc<ExifTool>>,
+    /// Make and model canonicalization for `camera.*`
+    pub cameras: Option<Arc<CameraConfig>>,
+}
 
 pub fn extract_metadata(path: &Utf8Path) -> Result<MediaContext> {
-    extract_metadata_with_location_history(path, None, None)
+    extract_metadata_with_options(path, &ExtractOptions::default())
 }
 
 pub fn extract_metadata_with_location_history(
@@ -20,32 +56,138 @@ pub fn extract_metadata_with_location_history(
     location_history: Option<Arc<LocationHistory>>,
     max_hours: Option<u64>,
 ) -> Result<MediaContext> {
+    let options = ExtractOptions {
+        location_history,
+        max_hours,
+        ..Default::default()
+    };
+    extract_metadata_with_options(path, &options)
+}
+
+pub fn extract_metadata_with_options(
+    path: &Utf8Path,
+    options: &ExtractOptions,
+) -> Result<MediaContext> {
+    let location_history = options.location_history.clone();
+    let max_hours = options.max_hours;
+
     let mut context = MediaContext {
         source: extract_source_info(path)?,
         ..Default::default()
     };
 
     // Detect media type
-    context.r#type = detect_media_type(path);
+    let media_type = match &options.media_types {
+        Some(media_types) => media_types.classify(path),
+        None => MediaTypeConfig::default().classify(path),
+    };
+    context.r#type = media_type.r#type;
+    context.subtype = media_type.subtype;
+    context.source.mime = media_type.mime;
 
     // Try EXIF extraction first
-    match extract_exif_metadata(path) {
+    let has_exif = match extract_exif_metadata(path) {
         Ok(exif_context) => {
             // Use EXIF data directly
             context.time = exif_context.time;
             context.space = exif_context.space;
             context.meta = exif_context.meta;
+            true
         }
         Err(_) => {
             // EXIF extraction failed completely, fallbacks will handle it
+            false
+        }
+    };
+
+    // RAW files keep their full size and sometimes their capture time out of reach
+    if context.subtype == "raw" {
+        apply_raw_info(&mut context, path);
+    }
+
+    // exiftool reads what the native parsers could not
+    if let Some(ref exiftool) = options.exiftool
+        && context.r#type != "unknown"
+        && (exiftool.mode() == ExifToolMode::Always
+            || !has_exif
+            || context.time.timestamp.is_none())
+    {
+        apply_exiftool(&mut context, path, exiftool);
+    }
+
+    context.camera = match &options.cameras {
+        Some(cameras) => cameras.identify(&context.meta),
+        None => CameraConfig::default().identify(&context.meta),
+    };
+
+    // Keep the camera's own time and correct its clock
+    if context.time.timestamp.is_some() {
+        context.time.source = "exif".to_string();
+        context.time.original = Datetime::from_time(&context.time);
+        if let Some(ref clock_offsets) = options.clock_offsets {
+            apply_clock_offset(&mut context, clock_offsets);
         }
     }
 
+    if options.content_identifier
+        && matches!(context.r#type.as_str(), "image" | "video")
+        && let Some(identifier) = content_identifier(path)
+    {
+        context
+            .meta
+            .insert("ContentIdentifier".to_string(), Dynamic::from(identifier));
+    }
+
+    // Ratings and keywords from photo editors
+    context.xmp = xmp::read(path);
+    if context.r#type == "image" {
+        context.iptc = iptc::read(path).unwrap_or_default();
+    }
+
+    // Google Photos Takeout keeps the dates and places it strips in JSON sidecars
+    let takeout = read_takeout_sidecar(path);
+    if let Some(ref takeout) = takeout {
+        context.takeout = TakeoutContext {
+            json: takeout.json.to_string(),
+            title: takeout.title.clone(),
+            description: takeout.description.clone(),
+            people: takeout.people.clone(),
+            url: takeout.url.clone(),
+            favorited: takeout.favorited,
+            taken: takeout.taken.map(|dt| Datetime::from(dt.fixed_offset())),
+        };
+    }
+
     // Apply fallbacks for missing data
-    apply_fallbacks(&mut context, path, location_history, max_hours)?;
+    apply_fallbacks(
+        &mut context,
+        path,
+        takeout.as_ref(),
+        location_history.clone(),
+        max_hours,
+    )?;
+
+    // The size as viewers show it, turned by the EXIF Orientation
+    context.image = dimensions::describe(&context.meta, context.subtype == "raw");
+
+    // Location names typed in by hand know better than reverse geocoding
+    if options.iptc_location {
+        iptc::override_location(&mut context.space, &context.iptc);
+    }
+
+    // Interpret the capture time in the time zone of the capture location
+    localize_time(&mut context.time, &context.space.timezone);
+
+    // Name the place the photo was taken at, if the history recorded a visit
+    if let Some(ref location_history) = location_history {
+        apply_place_visit(&mut context, location_history);
+    }
 
     // Calculate MD5 hash
     context.special = calculate_file_hash(path)?;
+    if options.perceptual_hash && context.r#type == "image" {
+        context.special.phash = phash_file(path).unwrap_or_default();
+    }
 
     // Ensure defaults for required fields
     apply_defaults(&mut context);
@@ -62,23 +204,10 @@ fn extract_source_info(path: &Utf8Path) -> Result<SourceContext> {
         extension: path.extension().unwrap_or("").to_string(),
         original: path.file_name().unwrap_or("unknown").to_string(),
         size: metadata.len(),
+        ..Default::default()
     })
 }
 
-fn detect_media_type(path: &Utf8Path) -> String {
-    if let Some(mime) = tree_magic_mini::from_filepath(path.as_std_path()) {
-        if mime.starts_with("image/") {
-            "image".to_string()
-        } else if mime.starts_with("video/") {
-            "video".to_string()
-        } else {
-            "unknown".to_string()
-        }
-    } else {
-        "unknown".to_string()
-    }
-}
-
 fn extract_exif_metadata(path: &Utf8Path) -> Result<MediaContext> {
     let mut context = MediaContext::default();
     let mut parser = MediaParser::new();
@@ -96,6 +225,7 @@ fn extract_exif_metadata(path: &Utf8Path) -> Result<MediaContext> {
 
         context.space.lat = lat;
         context.space.lon = lon;
+        context.space.source = "exif".to_string();
 
         // altitude is a Rational, not an Option
         context.space.altitude = Some(gps_info.altitude.0 as f64 / gps_info.altitude.1 as f64);
@@ -106,6 +236,7 @@ fn extract_exif_metadata(path: &Utf8Path) -> Result<MediaContext> {
             location.lat = lat;
             location.lon = lon;
             location.altitude = context.space.altitude;
+            location.source = "exif".to_string();
             context.space = location;
 
             // Log GPS source
@@ -122,86 +253,39 @@ fn extract_exif_metadata(path: &Utf8Path) -> Result<MediaContext> {
 
     for mut entry in iter.into_iter() {
         if let Ok(value) = entry.take_result() {
-            // Get tag name - use debug format of tag if no specific tag
-            let tag_name = if let Some(tag) = entry.tag() {
-                format!("{tag:?}")
-            } else {
-                // Use tag code if no enum variant
-                format!("Tag_{}", entry.tag_code())
-            };
-
-            // Convert value to Dynamic based on its type using available methods
-            let dynamic_value = if let Some(v) = value.as_u32() {
-                Dynamic::from(v as i64)
-            } else if let Some(v) = value.as_i32() {
-                Dynamic::from(v as i64)
-            } else if let Some(v) = value.as_u16() {
-                Dynamic::from(v as i64)
-            } else if let Some(v) = value.as_i16() {
-                Dynamic::from(v as i64)
-            } else if let Some(v) = value.as_u8() {
-                Dynamic::from(v as i64)
-            } else if let Some(v) = value.as_i8() {
-                Dynamic::from(v as i64)
-            } else if let Some(rational) = value.as_urational() {
-                Dynamic::from(rational.0 as f64 / rational.1 as f64)
-            } else if let Some(rational) = value.as_irational() {
-                Dynamic::from(rational.0 as f64 / rational.1 as f64)
-            } else if let Some(s) = value.as_str() {
-                Dynamic::from(s.to_string())
-            } else {
-                // For other types (Time, NaiveDateTime, etc.), store as string
-                Dynamic::from(format!("{value:?}"))
-            };
-
-            // Store in meta HashMap
-            context.meta.insert(tag_name.clone(), dynamic_value);
+            // ExifTool names, qualified by group ("GPS:GPSAltitude") so that tags
+            // found in several IFDs stay apart. The thumbnail's IFD1 values never
+            // shadow the main image's under the plain name.
+            let tag = tags::name(entry.tag(), entry.tag_code(), entry.ifd_index());
+            let value_dynamic = exif_value(&value);
+            if tag.is_main_image() || !context.meta.contains_key(&tag.name) {
+                if let Some(alias) = tag.alias {
+                    context
+                        .meta
+                        .insert(alias.to_string(), value_dynamic.clone());
+                }
+                context.meta.insert(tag.name.clone(), value_dynamic.clone());
+            }
+            context.meta.insert(tag.qualified(), value_dynamic);
 
             // Special handling for specific tags that affect other fields
-            match entry.tag() {
-                Some(ExifTag::DateTimeOriginal) | Some(ExifTag::CreateDate) => {
-                    // Try as string first
-                    if let Some(datetime_str) = value.as_str() {
+            if matches!(
+                entry.tag(),
+                Some(ExifTag::DateTimeOriginal) | Some(ExifTag::CreateDate)
+            ) {
+                match value {
+                    EntryValue::Text(ref datetime_str) => {
                         if let Ok(dt) = parse_exif_datetime(datetime_str) {
-                            context.time = create_time_context(dt);
-                        }
-                    } else {
-                        // Try to parse from debug representation
-                        let debug_str = format!("{value:?}");
-
-                        // Handle Time(YYYY-MM-DDTHH:MM:SS+TZ:TZ) format
-                        if debug_str.starts_with("Time(") && debug_str.ends_with(")") {
-                            if let Some(dt_str) = debug_str
-                                .strip_prefix("Time(")
-                                .and_then(|s| s.strip_suffix(")"))
-                            {
-                                if let Ok(dt) = DateTime::parse_from_rfc3339(dt_str) {
-                                    context.time = create_time_context(dt.with_timezone(&Utc));
-                                }
-                            }
-                        }
-                        // Handle NaiveDateTime format
-                        else if debug_str.starts_with("NaiveDateTime(")
-                            && debug_str.ends_with(")")
-                        {
-                            if let Some(dt_str) = debug_str
-                                .strip_prefix("NaiveDateTime(")
-                                .and_then(|s| s.strip_suffix(")"))
-                            {
-                                if let Ok(naive_dt) = chrono::NaiveDateTime::parse_from_str(
-                                    dt_str,
-                                    "%Y-%m-%dT%H:%M:%S",
-                                ) {
-                                    let dt =
-                                        DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc);
-                                    context.time = create_time_context(dt);
-                                }
-                            }
+                            context.time = create_naive_time_context(dt);
                         }
                     }
-                }
-                _ => {
-                    // Other tags are already stored in meta
+                    // Keep the wall clock of the recorded offset
+                    EntryValue::Time(dt) => context.time = create_time_context(dt),
+                    EntryValue::NaiveDateTime(naive_dt) => {
+                        let dt = DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc);
+                        context.time = create_naive_time_context(dt);
+                    }
+                    _ => {}
                 }
             }
         }
@@ -236,23 +320,160 @@ fn parse_exif_datetime(datetime_str: &str) -> Result<DateTime<Utc>> {
     }
 }
 
-fn create_time_context(dt: DateTime<Utc>) -> TimeContext {
+fn create_time_context<Tz: TimeZone>(dt: DateTime<Tz>) -> TimeContext
+where
+    Tz::Offset: Display,
+{
+    let mut time = TimeContext::default();
+    set_local_time(&mut time, dt);
+    time
+}
+
+// Camera wall-clock times carry no UTC offset; they are stored as if they were UTC
+// until the capture location tells us the real time zone.
+fn create_naive_time_context(dt: DateTime<Utc>) -> TimeContext {
     TimeContext {
-        yyyy: dt.format("%Y").to_string(),
-        mm: dt.format("%m").to_string(),
-        dd: dt.format("%d").to_string(),
-        hh: dt.format("%H").to_string(),
-        min: dt.format("%M").to_string(),
-        ss: dt.format("%S").to_string(),
-        month_name: dt.format("%B").to_string(),
-        weekday: dt.format("%A").to_string(),
-        timestamp: Some(dt),
+        naive: true,
+        ..create_time_context(dt)
+    }
+}
+
+/// Sets the time fields from the wall clock of `dt` and the timestamp from its instant.
+pub(crate) fn set_local_time<Tz: TimeZone>(time: &mut TimeContext, dt: DateTime<Tz>)
+where
+    Tz::Offset: Display,
+{
+    time.yyyy = dt.format("%Y").to_string();
+    time.mm = dt.format("%m").to_string();
+    time.dd = dt.format("%d").to_string();
+    time.hh = dt.format("%H").to_string();
+    time.min = dt.format("%M").to_string();
+    time.ss = dt.format("%S").to_string();
+    time.month_name = dt.format("%B").to_string();
+    time.weekday = dt.format("%A").to_string();
+    time.timestamp = Some(dt.with_timezone(&Utc));
+    time.naive = false;
+    time.utc_offset_secs = dt.offset().fix().local_minus_utc();
+}
+
+fn apply_clock_offset(context: &mut MediaContext, clock_offsets: &ClockOffsets) {
+    let (Some(original), Some(timestamp)) = (context.time.original, context.time.timestamp) else {
+        return;
+    };
+    let Some(offset) = clock_offsets.find(&context.meta, &original) else {
+        return;
+    };
+
+    let corrected = timestamp + offset;
+    context.time = if context.time.naive {
+        create_naive_time_context(corrected)
+    } else {
+        match FixedOffset::east_opt(context.time.utc_offset_secs) {
+            Some(utc_offset) => create_time_context(corrected.with_timezone(&utc_offset)),
+            None => create_time_context(corrected),
+        }
+    };
+    context.time.original = Some(original);
+
+    eprintln!(
+        "🕰️  Clock offset {} applied: {}",
+        format_offset(offset),
+        context.source.original
+    );
+}
+
+// The first IFD of most RAW formats describes a small preview, so the sensor size
+// read from the RAW structure replaces whatever EXIF reported.
+fn apply_raw_info(context: &mut MediaContext, path: &Utf8Path) {
+    let Some(info) = raw::sniff_format(path).and_then(|format| raw::inspect(path, format)) else {
+        return;
+    };
+
+    if let (Some(width), Some(height)) = (info.width, info.height) {
+        context
+            .meta
+            .insert("ImageWidth".to_string(), Dynamic::from(width as i64));
+        context
+            .meta
+            .insert("ImageHeight".to_string(), Dynamic::from(height as i64));
+    }
+    for (tag, value) in [("Make", info.make), ("Model", info.model)] {
+        if let Some(value) = value
+            && !context.meta.contains_key(tag)
+        {
+            context.meta.insert(tag.to_string(), Dynamic::from(value));
+        }
+    }
+    if context.time.timestamp.is_none()
+        && let Some(dt) = info
+            .datetime_original
+            .and_then(|text| parse_exif_datetime(&text).ok())
+    {
+        context.time = create_naive_time_context(dt);
+    }
+}
+
+/// Merges exiftool's tags into `meta` and takes the capture time and GPS position
+/// from them when the native parsers found none.
+fn apply_exiftool(context: &mut MediaContext, path: &Utf8Path, exiftool: &ExifTool) {
+    let tags = match exiftool.read(path) {
+        Ok(tags) => tags,
+        Err(e) => {
+            eprintln!("⚠️  exiftool failed for {path}: {e}");
+            return;
+        }
+    };
+    exiftool::merge(&mut context.meta, tags);
+
+    if context.time.timestamp.is_none()
+        && let Some(taken) = ["DateTimeOriginal", "CreateDate"]
+            .iter()
+            .find_map(|tag| context.meta.get(*tag)?.clone().try_cast::<Datetime>())
+    {
+        let local = taken.local();
+        context.time = match taken
+            .offset()
+            .and_then(FixedOffset::east_opt)
+            .and_then(|offset| offset.from_local_datetime(&local).single())
+        {
+            Some(dt) => create_time_context(dt),
+            None => create_naive_time_context(local.and_utc()),
+        };
+    }
+
+    // With -n, the composite position is signed decimal degrees
+    let degrees = |tag: &str| {
+        let value = context.meta.get(tag)?;
+        value
+            .as_float()
+            .ok()
+            .or(value.as_int().ok().map(|i| i as f64))
+    };
+    if context.space.lat == 0.0
+        && context.space.lon == 0.0
+        && let (Some(lat), Some(lon)) = (
+            degrees("Composite:GPSLatitude"),
+            degrees("Composite:GPSLongitude"),
+        )
+        && let Ok(mut location) = reverse_geocode(lat, lon)
+    {
+        location.lat = lat;
+        location.lon = lon;
+        location.altitude = degrees("Composite:GPSAltitude");
+        location.source = "exif".to_string();
+        context.space = location;
+
+        eprintln!(
+            "🛰️  GPS from exiftool: {:.6}, {:.6} -> {}, {}",
+            lat, lon, context.space.country, context.space.city
+        );
     }
 }
 
 fn apply_fallbacks(
     context: &mut MediaContext,
     path: &Utf8Path,
+    takeout: Option<&TakeoutData>,
     location_history: Option<Arc<LocationHistory>>,
     max_hours: Option<u64>,
 ) -> Result<()> {
@@ -262,36 +483,68 @@ fn apply_fallbacks(
     let has_height =
         context.meta.contains_key("ImageHeight") || context.meta.contains_key("ExifImageHeight");
 
-    if !has_width || !has_height {
-        if let Ok(img) = image::open(path.as_std_path()) {
-            if !has_width {
-                context
-                    .meta
-                    .insert("ImageWidth".to_string(), Dynamic::from(img.width() as i64));
-            }
-            if !has_height {
-                context.meta.insert(
-                    "ImageHeight".to_string(),
-                    Dynamic::from(img.height() as i64),
-                );
-            }
+    if (!has_width || !has_height)
+        && let Ok(img) = image::open(path.as_std_path())
+    {
+        if !has_width {
+            context
+                .meta
+                .insert("ImageWidth".to_string(), Dynamic::from(img.width() as i64));
+        }
+        if !has_height {
+            context.meta.insert(
+                "ImageHeight".to_string(),
+                Dynamic::from(img.height() as i64),
+            );
         }
     }
 
+    // Takeout records the capture instant in UTC
+    if context.time.timestamp.is_none()
+        && let Some(taken) = takeout.and_then(|takeout| takeout.taken)
+    {
+        context.time = create_time_context(taken);
+        context.time.source = "takeout".to_string();
+    }
+
     // Try to extract date from filename for videos
-    if context.time.timestamp.is_none() && context.r#type == "video" {
-        if let Some(dt) = extract_date_from_filename(path) {
-            context.time = create_time_context(dt);
-        }
+    if context.time.timestamp.is_none()
+        && context.r#type == "video"
+        && let Some(dt) = extract_date_from_filename(path)
+    {
+        context.time = create_naive_time_context(dt);
+        context.time.source = "filename".to_string();
     }
 
     // Use filesystem timestamp if no EXIF date or filename date
-    if context.time.timestamp.is_none() {
-        if let Ok(metadata) = fs::metadata(path) {
-            if let Ok(created) = metadata.created() {
-                let dt: DateTime<Utc> = created.into();
-                context.time = create_time_context(dt);
-            }
+    if context.time.timestamp.is_none()
+        && let Ok(metadata) = fs::metadata(path)
+        && let Ok(created) = metadata.created()
+    {
+        let dt: DateTime<Utc> = created.into();
+        context.time = create_time_context(dt);
+        context.time.source = "filesystem".to_string();
+    }
+
+    if context.space.position().is_none()
+        && let Some((lat, lon, altitude)) = takeout.and_then(|takeout| takeout.position)
+    {
+        context.space.lat = lat;
+        context.space.lon = lon;
+        context.space.altitude = Some(altitude);
+        context.space.source = "takeout".to_string();
+
+        if let Ok(mut location) = reverse_geocode(lat, lon) {
+            location.lat = lat;
+            location.lon = lon;
+            location.altitude = Some(altitude);
+            location.source = "takeout".to_string();
+            context.space = location;
+
+            eprintln!(
+                "📦 GPS from Takeout sidecar: {:.6}, {:.6} -> {}, {}",
+                lat, lon, context.space.country, context.space.city
+            );
         }
     }
 
@@ -308,14 +561,6 @@ fn apply_fallbacks(
                     timestamp.format("%Y-%m-%d %H:%M:%S UTC")
                 );
 
-                // Find closest location points
-                let (before, after) = location_history.find_closest_points(photo_timestamp_ms);
-                eprintln!(
-                    "🔍 Found location points: before={:?}, after={:?}",
-                    before.map(|p| (p.timestamp_ms, p.latitude_e7, p.longitude_e7)),
-                    after.map(|p| (p.timestamp_ms, p.latitude_e7, p.longitude_e7))
-                );
-
                 // Convert max hours to milliseconds (default 48 hours)
                 let max_hours_actual = max_hours.unwrap_or(48);
                 let max_time_diff_ms = max_hours_actual * 60 * 60 * 1000;
@@ -323,45 +568,12 @@ fn apply_fallbacks(
                     "🕒 Using location history threshold: {max_hours_actual} hours ({max_time_diff_ms} ms)"
                 );
 
-                // Select the closest point within 48 hours
-                let selected_point = match (before, after) {
-                    (Some(b), Some(a)) => {
-                        let diff_before = photo_timestamp_ms.saturating_sub(b.timestamp_ms);
-                        let diff_after = a.timestamp_ms.saturating_sub(photo_timestamp_ms);
-
-                        if diff_before <= max_time_diff_ms && diff_after <= max_time_diff_ms {
-                            // Both within threshold, choose closer one
-                            if diff_before <= diff_after {
-                                Some(b)
-                            } else {
-                                Some(a)
-                            }
-                        } else if diff_before <= max_time_diff_ms {
-                            Some(b)
-                        } else if diff_after <= max_time_diff_ms {
-                            Some(a)
-                        } else {
-                            None
-                        }
-                    }
-                    (Some(b), None) => {
-                        let diff = photo_timestamp_ms.saturating_sub(b.timestamp_ms);
-                        if diff <= max_time_diff_ms {
-                            Some(b)
-                        } else {
-                            None
-                        }
-                    }
-                    (None, Some(a)) => {
-                        let diff = a.timestamp_ms.saturating_sub(photo_timestamp_ms);
-                        if diff <= max_time_diff_ms {
-                            Some(a)
-                        } else {
-                            None
-                        }
-                    }
-                    (None, None) => None,
-                };
+                let selected_point = history_point(
+                    location_history,
+                    &context.time,
+                    photo_timestamp_ms,
+                    max_time_diff_ms,
+                );
 
                 // Apply the location if found
                 if let Some(point) = selected_point {
@@ -371,12 +583,14 @@ fn apply_fallbacks(
 
                     context.space.lat = lat;
                     context.space.lon = lon;
+                    context.space.source = "location_history".to_string();
 
                     // Reverse geocode to get location details
                     if let Ok(mut location) = reverse_geocode(lat, lon) {
                         // Preserve the GPS coordinates
                         location.lat = lat;
                         location.lon = lon;
+                        location.source = "location_history".to_string();
                         context.space = location;
 
                         // Log Location History source
@@ -402,6 +616,90 @@ fn apply_fallbacks(
     Ok(())
 }
 
+/// The history point closest to the capture time, within `max_time_diff_ms`. A
+/// naive camera time is a wall-clock time rather than UTC, so it is first read in
+/// the time zone of the point it lands near and looked up again at that instant.
+fn history_point<'a>(
+    location_history: &'a LocationHistory,
+    time: &TimeContext,
+    timestamp_ms: u64,
+    max_time_diff_ms: u64,
+) -> Option<&'a LocationPoint> {
+    let point = closest_point(location_history, timestamp_ms, max_time_diff_ms)?;
+    if !time.naive {
+        return Some(point);
+    }
+
+    let (lat, lon) = (
+        point.latitude_e7 as f64 / 1e7,
+        point.longitude_e7 as f64 / 1e7,
+    );
+    let instant_ms = timezone_at(lat, lon)
+        .and_then(|name| name.parse::<Tz>().ok())
+        .zip(time.timestamp)
+        .and_then(|(tz, timestamp)| tz.from_local_datetime(&timestamp.naive_utc()).earliest())
+        .map(|local| local.timestamp_millis() as u64);
+    match instant_ms {
+        Some(instant_ms) => closest_point(location_history, instant_ms, max_time_diff_ms),
+        None => Some(point),
+    }
+}
+
+fn closest_point(
+    location_history: &LocationHistory,
+    photo_timestamp_ms: u64,
+    max_time_diff_ms: u64,
+) -> Option<&LocationPoint> {
+    let (before, after) = location_history.find_closest_points(photo_timestamp_ms);
+    match (before, after) {
+        (Some(b), Some(a)) => {
+            let diff_before = photo_timestamp_ms.saturating_sub(b.timestamp_ms);
+            let diff_after = a.timestamp_ms.saturating_sub(photo_timestamp_ms);
+
+            if diff_before <= max_time_diff_ms && diff_after <= max_time_diff_ms {
+                // Both within threshold, choose closer one
+                if diff_before <= diff_after {
+                    Some(b)
+                } else {
+                    Some(a)
+                }
+            } else if diff_before <= max_time_diff_ms {
+                Some(b)
+            } else if diff_after <= max_time_diff_ms {
+                Some(a)
+            } else {
+                None
+            }
+        }
+        (Some(b), None) => {
+            let diff = photo_timestamp_ms.saturating_sub(b.timestamp_ms);
+            (diff <= max_time_diff_ms).then_some(b)
+        }
+        (None, Some(a)) => {
+            let diff = a.timestamp_ms.saturating_sub(photo_timestamp_ms);
+            (diff <= max_time_diff_ms).then_some(a)
+        }
+        (None, None) => None,
+    }
+}
+
+fn apply_place_visit(context: &mut MediaContext, location_history: &LocationHistory) {
+    let Some(ref timestamp) = context.time.timestamp else {
+        return;
+    };
+
+    if let Some(visit) = location_history.find_place_visit(timestamp.timestamp_millis() as u64) {
+        context.space.place_name = visit.name.clone();
+        context.space.place_address = visit.address.clone();
+        context.space.place_id = visit.place_id.clone();
+
+        eprintln!(
+            "📌 Place visit from Location History: {} ({})",
+            visit.name, visit.place_id
+        );
+    }
+}
+
 fn extract_date_from_filename(path: &Utf8Path) -> Option<DateTime<Utc>> {
     let filename = path.file_name()?;
 
@@ -415,23 +713,16 @@ fn extract_date_from_filename(path: &Utf8Path) -> Option<DateTime<Utc>> {
     for i in 0..chars.len().saturating_sub(7) {
         if chars[i..i + 8].iter().all(|c| c.is_ascii_digit()) {
             let date_str: String = chars[i..i + 8].iter().collect();
-            if let Ok(year) = date_str[0..4].parse::<i32>() {
-                if let Ok(month) = date_str[4..6].parse::<u32>() {
-                    if let Ok(day) = date_str[6..8].parse::<u32>() {
-                        if (1900..=2100).contains(&year)
-                            && (1..=12).contains(&month)
-                            && (1..=31).contains(&day)
-                        {
-                            if let Some(date) = chrono::NaiveDate::from_ymd_opt(year, month, day) {
-                                if let Some(datetime) = date.and_hms_opt(0, 0, 0) {
-                                    return Some(DateTime::from_naive_utc_and_offset(
-                                        datetime, Utc,
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                }
+            if let Ok(year) = date_str[0..4].parse::<i32>()
+                && let Ok(month) = date_str[4..6].parse::<u32>()
+                && let Ok(day) = date_str[6..8].parse::<u32>()
+                && (1900..=2100).contains(&year)
+                && (1..=12).contains(&month)
+                && (1..=31).contains(&day)
+                && let Some(date) = chrono::NaiveDate::from_ymd_opt(year, month, day)
+                && let Some(datetime) = date.and_hms_opt(0, 0, 0)
+            {
+                return Some(DateTime::from_naive_utc_and_offset(datetime, Utc));
             }
         }
     }
@@ -439,32 +730,25 @@ fn extract_date_from_filename(path: &Utf8Path) -> Option<DateTime<Utc>> {
     // Look for YYYY-MM-DD pattern
     if filename.contains('-') {
         let parts: Vec<&str> = filename.split(&['-', ' ', '.'][..]).collect();
-        if parts.len() >= 3 {
-            if let Ok(year) = parts[0].parse::<i32>() {
-                if let Ok(month) = parts[1].parse::<u32>() {
-                    if let Ok(day) = parts[2].parse::<u32>() {
-                        if (1900..=2100).contains(&year)
-                            && (1..=12).contains(&month)
-                            && (1..=31).contains(&day)
-                        {
-                            if let Some(date) = chrono::NaiveDate::from_ymd_opt(year, month, day) {
-                                if let Some(datetime) = date.and_hms_opt(0, 0, 0) {
-                                    return Some(DateTime::from_naive_utc_and_offset(
-                                        datetime, Utc,
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if parts.len() >= 3
+            && let Ok(year) = parts[0].parse::<i32>()
+            && let Ok(month) = parts[1].parse::<u32>()
+            && let Ok(day) = parts[2].parse::<u32>()
+            && (1900..=2100).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && let Some(date) = chrono::NaiveDate::from_ymd_opt(year, month, day)
+            && let Some(datetime) = date.and_hms_opt(0, 0, 0)
+        {
+            return Some(DateTime::from_naive_utc_and_offset(datetime, Utc));
         }
     }
 
     None
 }
 
-fn apply_defaults(context: &mut MediaContext) {
+/// Fills the placeholders templates show for unknown dates and places.
+pub(crate) fn apply_defaults(context: &mut MediaContext) {
     if context.time.yyyy.is_empty() {
         context.time.yyyy = "unknown".to_string();
         context.time.mm = "00".to_string();
@@ -493,5 +777,43 @@ fn calculate_file_hash(path: &Utf8Path) -> Result<SpecialContext> {
         md5: md5_full,
         md5_short,
         count: 0, // This will be used for collision handling in the future
+        is_best: true,
+        ..Default::default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(hour: u32, lat: f64, lon: f64) -> LocationPoint {
+        LocationPoint {
+            timestamp_ms: Utc
+                .with_ymd_and_hms(2024, 7, 12, hour, 0, 0)
+                .unwrap()
+                .timestamp_millis() as u64,
+            latitude_e7: (lat * 1e7) as i32,
+            longitude_e7: (lon * 1e7) as i32,
+        }
+    }
+
+    #[test]
+    fn test_history_point_localizes_naive_times() {
+        // In Tokyo (UTC+9) at 10:00 local, then in Osaka at 18:00 local
+        let history =
+            LocationHistory::from_points(vec![point(1, 35.68, 139.69), point(9, 34.69, 135.50)]);
+        let max_ms = 12 * 3600 * 1000;
+        let taken = Utc.with_ymd_and_hms(2024, 7, 12, 10, 0, 0).unwrap();
+        let taken_ms = taken.timestamp_millis() as u64;
+
+        // The camera's 10:00 is local time, taken in Tokyo
+        let naive = create_naive_time_context(taken);
+        let found = history_point(&history, &naive, taken_ms, max_ms).unwrap();
+        assert_eq!(found.latitude_e7, 356_800_000);
+
+        // A real 10:00 UTC instant is closest to the Osaka point
+        let instant = create_time_context(taken);
+        let found = history_point(&history, &instant, taken_ms, max_ms).unwrap();
+        assert_eq!(found.latitude_e7, 346_900_000);
+    }
+}
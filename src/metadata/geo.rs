@@ -0,0 +1,51 @@
+// Geodesy helpers shared by reverse geocoding and location-aware stages.
+
+/// Mean Earth radius in kilometers.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Great-circle distance in kilometers between two coordinates, in degrees.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+/// Initial compass bearing in degrees (0-360, clockwise from north) when travelling
+/// along the great circle from the first coordinate to the second.
+pub fn initial_bearing_deg(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlon = (lon2 - lon1).to_radians();
+
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haversine_km() {
+        // Madrid to Lisbon is about 503 km
+        let distance = haversine_km(40.4168, -3.7038, 38.7223, -9.1393);
+        assert!((distance - 503.0).abs() < 2.0, "{distance}");
+
+        assert_eq!(haversine_km(10.0, 20.0, 10.0, 20.0), 0.0);
+    }
+
+    #[test]
+    fn test_initial_bearing_deg() {
+        assert!((initial_bearing_deg(0.0, 0.0, 10.0, 0.0) - 0.0).abs() < 1e-9);
+        assert!((initial_bearing_deg(0.0, 0.0, 0.0, 10.0) - 90.0).abs() < 1e-9);
+        assert!((initial_bearing_deg(10.0, 0.0, 0.0, 0.0) - 180.0).abs() < 1e-9);
+        assert!((initial_bearing_deg(0.0, 10.0, 0.0, 0.0) - 270.0).abs() < 1e-9);
+
+        // Madrid to Lisbon heads west-southwest
+        let bearing = initial_bearing_deg(40.4168, -3.7038, 38.7223, -9.1393);
+        assert!((bearing - 248.0).abs() < 2.0, "{bearing}");
+    }
+}
@@ -0,0 +1,285 @@
+// IPTC Module
+// Parses IPTC-IIM, the captions, keywords, bylines and location names written by
+// news-style and older editing tools into the Photoshop resource block of a JPEG's
+// APP13 segment.
+
+use camino::Utf8Path;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+use super::context::{IptcContext, SpaceContext};
+use super::countries::{alpha2_code, country_code, country_name};
+
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE: u16 = 0x0404;
+/// ESC % G, the ISO 2022 escape selecting UTF-8 in dataset 1:90
+const UTF8_ESCAPE: &[u8] = b"\x1b%G";
+
+/// Reads the IPTC-IIM data of a JPEG file, if it has any.
+pub fn read(path: &Utf8Path) -> Option<IptcContext> {
+    let file = File::open(path.as_std_path()).ok()?;
+    let iim = read_app13(&mut BufReader::new(file))?;
+    Some(parse_iim(&iim))
+}
+
+// Collects the IIM resource from the APP13 segments before the image data.
+fn read_app13<R: Read>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut photoshop = Vec::new();
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // Start of scan or end of image: no more metadata segments
+        if matches!(marker[1], 0xDA | 0xD9) {
+            break;
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = (u16::from_be_bytes(length) as usize).checked_sub(2)?;
+        let mut segment = vec![0u8; length];
+        reader.read_exact(&mut segment).ok()?;
+
+        if marker[1] == 0xED {
+            match segment.strip_prefix(PHOTOSHOP_SIGNATURE) {
+                Some(resources) => photoshop.extend_from_slice(resources),
+                // A continuation of the previous APP13 segment
+                None if !photoshop.is_empty() => photoshop.extend_from_slice(&segment),
+                None => {}
+            }
+        }
+    }
+
+    iim_resource(&photoshop)
+}
+
+// Photoshop image resources: "8BIM", id, padded Pascal name, size, padded data.
+fn iim_resource(mut data: &[u8]) -> Option<Vec<u8>> {
+    while data.len() >= 12 && data.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([data[4], data[5]]);
+        let name_length = data[6] as usize;
+        let name_size = (name_length + 1 + 1) & !1;
+        let size_at = 6 + name_size;
+        let size = u32::from_be_bytes(data.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let start = size_at + 4;
+        let payload = data.get(start..start + size)?;
+        if id == IPTC_RESOURCE {
+            return Some(payload.to_vec());
+        }
+        data = data.get(start + ((size + 1) & !1)..)?;
+    }
+    None
+}
+
+/// Datasets as (record, number, value), in file order.
+fn datasets(mut data: &[u8]) -> Vec<(u8, u8, &[u8])> {
+    let mut datasets = Vec::new();
+    while data.len() >= 5 && data[0] == 0x1C {
+        let (record, number) = (data[1], data[2]);
+        let length = u16::from_be_bytes([data[3], data[4]]) as usize;
+        // Extended lengths (high bit set) are only used for binary objects
+        if length & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = data.get(5..5 + length) else {
+            break;
+        };
+        datasets.push((record, number, value));
+        data = &data[5 + length..];
+    }
+    datasets
+}
+
+/// Parses an IIM block. Text is UTF-8 when dataset 1:90 says so or when it is
+/// valid UTF-8, otherwise Latin-1.
+fn parse_iim(data: &[u8]) -> IptcContext {
+    let datasets = datasets(data);
+    let utf8 = datasets
+        .iter()
+        .any(|&(record, number, value)| record == 1 && number == 90 && value == UTF8_ESCAPE);
+    let text = |value: &[u8]| -> String {
+        let text = match std::str::from_utf8(value) {
+            Ok(text) => text.to_string(),
+            Err(_) if utf8 => String::from_utf8_lossy(value).into_owned(),
+            Err(_) => value.iter().map(|&byte| byte as char).collect(),
+        };
+        text.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_string()
+    };
+
+    let mut iptc = IptcContext::default();
+    for (record, number, value) in datasets {
+        if record != 2 {
+            continue;
+        }
+        let value = text(value);
+        if value.is_empty() {
+            continue;
+        }
+        match number {
+            5 => iptc.title = value,
+            25 => iptc.keywords.push(value),
+            80 => iptc.byline.push(value),
+            90 => iptc.city = value,
+            92 => iptc.sublocation = value,
+            95 => iptc.state = value,
+            100 => iptc.country_code = value,
+            101 => iptc.country = value,
+            105 => iptc.headline = value,
+            110 => iptc.credit = value,
+            115 => iptc.source = value,
+            116 => iptc.copyright = value,
+            120 => iptc.caption = value,
+            _ => {}
+        }
+    }
+    iptc
+}
+
+/// Replaces reverse-geocoded names with the location names entered in IPTC. The
+/// country code comes from the IPTC code or country name, and the geocoded code is
+/// kept when neither is known.
+pub fn override_location(space: &mut SpaceContext, iptc: &IptcContext) {
+    if !iptc.city.is_empty() {
+        space.city = iptc.city.clone();
+    }
+    if !iptc.sublocation.is_empty() {
+        space.district = iptc.sublocation.clone();
+    }
+    if !iptc.state.is_empty() {
+        space.state = iptc.state.clone();
+    }
+
+    // IIM codes are ISO 3166 alpha-3 in principle, but alpha-2 is common in practice
+    let code = alpha2_code(&iptc.country_code)
+        .or_else(|| country_code(&iptc.country))
+        .map(str::to_string);
+    if !iptc.country.is_empty() {
+        space.country = iptc.country.clone();
+        if let Some(code) = code {
+            space.country_code = code;
+        }
+    } else if let Some(code) = code {
+        space.country = country_name(&code).unwrap_or_default().to_string();
+        space.country_code = code;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(record: u8, number: u8, value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1C, record, number];
+        data.extend((value.len() as u16).to_be_bytes());
+        data.extend(value);
+        data
+    }
+
+    fn jpeg(iim: &[u8]) -> Vec<u8> {
+        let mut resources = b"8BIM\x04\x25\0\0".to_vec();
+        resources.extend(16u32.to_be_bytes());
+        resources.extend([0u8; 16]);
+        resources.extend(b"8BIM\x04\x04\0\0");
+        resources.extend((iim.len() as u32).to_be_bytes());
+        resources.extend(iim);
+        if iim.len() % 2 == 1 {
+            resources.push(0);
+        }
+
+        let mut segment = PHOTOSHOP_SIGNATURE.to_vec();
+        segment.extend(resources);
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46];
+        data.extend([0xFF, 0xED]);
+        data.extend(((segment.len() + 2) as u16).to_be_bytes());
+        data.extend(segment);
+        data.extend([0xFF, 0xDA, 0x00, 0x02]);
+        data
+    }
+
+    #[test]
+    fn test_parse_jpeg() {
+        let mut iim = dataset(1, 90, UTF8_ESCAPE);
+        iim.extend(dataset(2, 0, &[0, 4]));
+        iim.extend(dataset(2, 120, "Festa de Santo António".as_bytes()));
+        iim.extend(dataset(2, 25, b"festival"));
+        iim.extend(dataset(2, 25, b"Lisbon"));
+        iim.extend(dataset(2, 80, b"Ana Silva"));
+        iim.extend(dataset(2, 90, b"Lisboa"));
+        iim.extend(dataset(2, 101, b"Portugal"));
+        iim.extend(dataset(2, 100, b"PRT"));
+
+        let data = read_app13(&mut jpeg(&iim).as_slice()).unwrap();
+        let iptc = parse_iim(&data);
+        assert_eq!(iptc.caption, "Festa de Santo António");
+        assert_eq!(iptc.keywords, ["festival", "Lisbon"]);
+        assert_eq!(iptc.byline, ["Ana Silva"]);
+        assert_eq!(iptc.city, "Lisboa");
+        assert_eq!(iptc.country_code, "PRT");
+    }
+
+    #[test]
+    fn test_latin1() {
+        let iptc = parse_iim(&dataset(2, 90, b"M\xFCnchen"));
+        assert_eq!(iptc.city, "München");
+    }
+
+    #[test]
+    fn test_override_location() {
+        let mut space = SpaceContext {
+            city: "Amadora".to_string(),
+            country: "Portugal".to_string(),
+            country_code: "PT".to_string(),
+            ..Default::default()
+        };
+        let iptc = IptcContext {
+            city: "Lisboa".to_string(),
+            country_code: "PRT".to_string(),
+            ..Default::default()
+        };
+        override_location(&mut space, &iptc);
+        assert_eq!(space.city, "Lisboa");
+        assert_eq!(
+            (space.country.as_str(), space.country_code.as_str()),
+            ("Portugal", "PT")
+        );
+
+        let iptc = IptcContext {
+            country: "Spain".to_string(),
+            ..Default::default()
+        };
+        override_location(&mut space, &iptc);
+        assert_eq!(
+            (space.country.as_str(), space.country_code.as_str()),
+            ("Spain", "ES")
+        );
+
+        // Alpha-3 codes map to alpha-2, and local names keep the known code
+        let iptc = IptcContext {
+            country: "España".to_string(),
+            country_code: "ESP".to_string(),
+            ..Default::default()
+        };
+        override_location(&mut space, &iptc);
+        assert_eq!(
+            (space.country.as_str(), space.country_code.as_str()),
+            ("España", "ES")
+        );
+
+        let iptc = IptcContext {
+            country: "Catalunya".to_string(),
+            ..Default::default()
+        };
+        override_location(&mut space, &iptc);
+        assert_eq!(
+            (space.country.as_str(), space.country_code.as_str()),
+            ("Catalunya", "ES")
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Live Photo Module
+// Reads the ContentIdentifier that iPhones write into both halves of a Live Photo:
+// tag 0x0011 of the Apple MakerNote in the still image, and a QuickTime metadata
+// item in the motion video. nom-exif exposes neither.
+
+use camino::Utf8Path;
+use regex::bytes::Regex;
+use std::fs;
+
+lazy_static::lazy_static! {
+    static ref UUID: Regex = Regex::new(
+        r"[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}"
+    )
+    .unwrap();
+}
+
+const MAKER_NOTE_SIGNATURE: &[u8] = b"Apple iOS\0";
+const QUICKTIME_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+const TAG_CONTENT_IDENTIFIER: u16 = 0x0011;
+
+/// Live Photo halves are a few megabytes; larger files are not read.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The Live Photo ContentIdentifier of an image or video, uppercased.
+pub fn content_identifier(path: &Utf8Path) -> Option<String> {
+    let size = fs::metadata(path.as_std_path()).ok()?.len();
+    if size > MAX_FILE_SIZE {
+        return None;
+    }
+    let data = fs::read(path.as_std_path()).ok()?;
+    from_maker_note(&data).or_else(|| from_quicktime(&data))
+}
+
+// Apple MakerNote: signature, version, "MM", then a big-endian IFD whose value
+// offsets are relative to the start of the signature.
+fn from_maker_note(data: &[u8]) -> Option<String> {
+    let start = find(data, MAKER_NOTE_SIGNATURE)?;
+    let note = &data[start..];
+    if note.get(12..14)? != b"MM" {
+        return None;
+    }
+    let be16 = |at: usize| Some(u16::from_be_bytes(note.get(at..at + 2)?.try_into().ok()?));
+    let be32 = |at: usize| Some(u32::from_be_bytes(note.get(at..at + 4)?.try_into().ok()?));
+
+    let count = be16(14)? as usize;
+    for index in 0..count {
+        let entry = 16 + index * 12;
+        if be16(entry)? != TAG_CONTENT_IDENTIFIER || be16(entry + 2)? != 2 {
+            continue;
+        }
+        let length = be32(entry + 4)? as usize;
+        let offset = be32(entry + 8)? as usize;
+        let value = note.get(offset..offset + length)?;
+        return UUID.find(value).map(|m| uppercase(m.as_bytes()));
+    }
+    None
+}
+
+// QuickTime metadata lists its keys before the values, so the identifier is the
+// first UUID following its key.
+fn from_quicktime(data: &[u8]) -> Option<String> {
+    let key = find(data, QUICKTIME_KEY)?;
+    UUID.find(&data[key + QUICKTIME_KEY.len()..])
+        .map(|m| uppercase(m.as_bytes()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn uppercase(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "DA1A7EE8-0925-4C9F-9266-DDA3F0BB80F0";
+
+    #[test]
+    fn test_from_maker_note() {
+        // Exif bytes, then a MakerNote with an unrelated UUID tag before 0x0011
+        let mut data = b"Exif\0\0MM\0*".to_vec();
+        let start = data.len();
+        data.extend(MAKER_NOTE_SIGNATURE);
+        data.extend([0, 1]);
+        data.extend(b"MM");
+        data.extend(2u16.to_be_bytes());
+        let entries_end = 16 + 2 * 12 + 4;
+        for (tag, offset) in [
+            (0x0020u16, entries_end),
+            (TAG_CONTENT_IDENTIFIER, entries_end + 37),
+        ] {
+            data.extend(tag.to_be_bytes());
+            data.extend(2u16.to_be_bytes());
+            data.extend(37u32.to_be_bytes());
+            data.extend((offset as u32).to_be_bytes());
+        }
+        data.extend(0u32.to_be_bytes());
+        assert_eq!(data.len() - start, entries_end);
+        data.extend(b"11111111-2222-3333-4444-555555555555\0");
+        data.extend(ID.to_lowercase().as_bytes());
+        data.push(0);
+
+        assert_eq!(from_maker_note(&data).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn test_from_quicktime() {
+        let mut data = b"....keys....mdta".to_vec();
+        data.extend(QUICKTIME_KEY);
+        data.extend(b"....mdtacom.apple.quicktime.make....ilst....data....");
+        data.extend(ID.as_bytes());
+        assert_eq!(from_quicktime(&data).as_deref(), Some(ID));
+        assert_eq!(from_quicktime(b"moov....mvhd"), None);
+    }
+}
@@ -1,22 +1,261 @@
-use anyhow::Result;
-use reverse_geocoder::ReverseGeocoder;
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use kiddo::float::{distance::SquaredEuclidean, kdtree::KdTree};
+use reverse_geocoder::{Record, ReverseGeocoder, degrees_lat_lng_to_unit_sphere};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::sync::OnceLock;
 
 use super::context::SpaceContext;
+use super::countries::country_name;
+use super::geo::haversine_km;
+use super::timezone::timezone_at;
 
-lazy_static::lazy_static! {
-    static ref GEOCODER: ReverseGeocoder = ReverseGeocoder::new();
+static GEOCODER: OnceLock<Geocoder> = OnceLock::new();
+
+static ADMIN_NAMES: OnceLock<AdminNames> = OnceLock::new();
+
+/// Reverse geocoding settings from the `geocoder:` configuration section.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeocoderConfig {
+    /// Local GeoNames dump (e.g. `cities500.txt` or `allCountries.txt`) replacing
+    /// the bundled dataset of cities above 1000 inhabitants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset_path: Option<String>,
+    /// GeoNames `admin1CodesASCII.txt`, used to turn admin1 codes into names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin1_codes_path: Option<String>,
+    /// GeoNames `admin2Codes.txt`, used to turn admin2 codes into names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin2_codes_path: Option<String>,
+}
+
+/// Nearest-place lookup over either the bundled dataset or a GeoNames dump.
+enum Geocoder {
+    Bundled(Box<ReverseGeocoder>),
+    GeoNames(GeoNamesIndex),
+}
+
+impl Geocoder {
+    fn search(&self, latitude: f64, longitude: f64) -> &Record {
+        match self {
+            Geocoder::Bundled(geocoder) => geocoder.search((latitude, longitude)).record,
+            Geocoder::GeoNames(index) => index.search(latitude, longitude),
+        }
+    }
+}
+
+/// A KD-tree over populated places read from a GeoNames dump. Admin divisions keep
+/// their GeoNames codes, which are resolved through the admin code files.
+struct GeoNamesIndex {
+    records: Vec<Record>,
+    tree: KdTree<f64, u64, 3, 32, u32>,
+}
+
+impl GeoNamesIndex {
+    fn from_path(path: &str) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open GeoNames dataset: {path}"))?;
+
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("Failed to read GeoNames dataset: {path}"))?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 12 {
+                continue;
+            }
+
+            // Only populated places, skipping historical, abandoned and destroyed ones
+            let (feature_class, feature_code) = (fields[6], fields[7]);
+            if feature_class != "P" || matches!(feature_code, "PPLH" | "PPLQ" | "PPLW") {
+                continue;
+            }
+
+            let (Ok(lat), Ok(lon)) = (fields[4].parse::<f64>(), fields[5].parse::<f64>()) else {
+                continue;
+            };
+
+            records.push(Record {
+                lat,
+                lon,
+                name: fields[1].to_string(),
+                admin1: fields[10].to_string(),
+                admin2: fields[11].to_string(),
+                cc: fields[8].to_string(),
+            });
+        }
+
+        if records.is_empty() {
+            anyhow::bail!("No populated places found in GeoNames dataset: {path}");
+        }
+
+        let mut tree = KdTree::with_capacity(records.len());
+        for (index, record) in records.iter().enumerate() {
+            tree.add(&record.as_xyz(), index as u64);
+        }
+
+        Ok(Self { records, tree })
+    }
+
+    fn search(&self, latitude: f64, longitude: f64) -> &Record {
+        let query = degrees_lat_lng_to_unit_sphere(latitude, longitude);
+        let nearest = self.tree.nearest_one::<SquaredEuclidean>(&query);
+        &self.records[nearest.item as usize]
+    }
+}
+
+/// Administrative division names keyed by GeoNames codes ("ES.29", "ES.29.M").
+#[derive(Debug, Default)]
+struct AdminNames {
+    admin1: HashMap<String, String>,
+    admin2: HashMap<String, String>,
+}
+
+impl AdminNames {
+    fn resolve_admin1(&self, cc: &str, admin1: &str) -> String {
+        self.admin1
+            .get(&format!("{cc}.{admin1}"))
+            .cloned()
+            .unwrap_or_else(|| admin1.to_string())
+    }
+
+    fn resolve_admin2(&self, cc: &str, admin1: &str, admin2: &str) -> String {
+        self.admin2
+            .get(&format!("{cc}.{admin1}.{admin2}"))
+            .cloned()
+            .unwrap_or_else(|| admin2.to_string())
+    }
+}
+
+// Parses a GeoNames admin code file: code, name, ASCII name and geonameid per line.
+fn load_admin_codes(path: &str) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read admin codes file: {path}"))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let code = fields.next()?;
+            let name = fields.next()?;
+            Some((code.to_string(), name.to_string()))
+        })
+        .collect())
+}
+
+/// Applies the `geocoder:` configuration. Must be called before the first lookup,
+/// and at most once.
+pub fn configure_geocoder(config: &GeocoderConfig) -> Result<()> {
+    let mut admin1_codes_path = config.admin1_codes_path.clone();
+    let mut admin2_codes_path = config.admin2_codes_path.clone();
+
+    if let Some(ref path) = config.dataset_path {
+        let index = GeoNamesIndex::from_path(path)?;
+        eprintln!(
+            "🌍 Loaded {} places from GeoNames dataset: {path}",
+            index.records.len()
+        );
+        GEOCODER
+            .set(Geocoder::GeoNames(index))
+            .map_err(|_| anyhow::anyhow!("Geocoder is already configured"))?;
+
+        // GeoNames downloads ship the admin code files next to the dumps
+        let directory = Utf8Path::new(path).parent().unwrap_or(Utf8Path::new("."));
+        for (configured, file_name) in [
+            (&mut admin1_codes_path, "admin1CodesASCII.txt"),
+            (&mut admin2_codes_path, "admin2Codes.txt"),
+        ] {
+            let candidate = directory.join(file_name);
+            if configured.is_none() && candidate.is_file() {
+                *configured = Some(candidate.to_string());
+            }
+        }
+    }
+
+    let mut names = AdminNames::default();
+    if let Some(ref path) = admin1_codes_path {
+        names.admin1 = load_admin_codes(path)?;
+    }
+    if let Some(ref path) = admin2_codes_path {
+        names.admin2 = load_admin_codes(path)?;
+    }
+
+    ADMIN_NAMES
+        .set(names)
+        .map_err(|_| anyhow::anyhow!("Geocoder is already configured"))
 }
 
 pub fn reverse_geocode(latitude: f64, longitude: f64) -> Result<SpaceContext> {
-    let result = GEOCODER.search((latitude, longitude));
+    let geocoder = GEOCODER.get_or_init(|| Geocoder::Bundled(Box::new(ReverseGeocoder::new())));
+    let record = geocoder.search(latitude, longitude);
+    let names = ADMIN_NAMES.get_or_init(AdminNames::default);
 
     Ok(SpaceContext {
-        city: result.record.name.clone(),
-        country: result.record.cc.clone(),
-        country_code: result.record.cc.clone(),
-        state: result.record.admin1.clone(),
+        city: record.name.clone(),
+        country: country_name(&record.cc)
+            .map(str::to_string)
+            .unwrap_or_else(|| record.cc.clone()),
+        country_code: record.cc.clone(),
+        state: names.resolve_admin1(&record.cc, &record.admin1),
+        county: names.resolve_admin2(&record.cc, &record.admin1, &record.admin2),
         lat: latitude,
         lon: longitude,
+        place_distance_km: Some(haversine_km(latitude, longitude, record.lat, record.lon)),
+        timezone: timezone_at(latitude, longitude).unwrap_or_default(),
         ..Default::default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_geocode_names() {
+        let space = reverse_geocode(40.4168, -3.7038).unwrap();
+        assert_eq!(space.city, "Madrid");
+        assert_eq!(space.country, "Spain");
+        assert_eq!(space.country_code, "ES");
+        assert_eq!(space.state, "Madrid");
+        assert_eq!(space.county, "Provincia de Madrid");
+        assert!(space.place_distance_km.unwrap() < 1.0);
+    }
+
+    #[test]
+    fn test_geonames_index() {
+        let index = GeoNamesIndex::from_path("test_data/geonames/cities_sample.txt").unwrap();
+
+        // The historical place and the mountain are not indexed
+        assert_eq!(index.records.len(), 3);
+
+        let record = index.search(40.6, -4.0);
+        assert_eq!(record.name, "Guadarrama");
+        assert_eq!(record.cc, "ES");
+        assert_eq!(record.admin1, "29");
+        assert_eq!(record.admin2, "M");
+
+        assert_eq!(index.search(38.7, -9.1).name, "Lisbon");
+    }
+
+    #[test]
+    fn test_geonames_index_without_places() {
+        assert!(GeoNamesIndex::from_path("test_data/geonames/admin1CodesASCII.txt").is_err());
+        assert!(GeoNamesIndex::from_path("test_data/geonames/missing.txt").is_err());
+    }
+
+    #[test]
+    fn test_admin_names_resolution() {
+        let names = AdminNames {
+            admin1: HashMap::from([("ES.29".to_string(), "Madrid".to_string())]),
+            admin2: HashMap::from([("ES.29.M".to_string(), "Provincia de Madrid".to_string())]),
+        };
+
+        assert_eq!(names.resolve_admin1("ES", "29"), "Madrid");
+        assert_eq!(names.resolve_admin2("ES", "29", "M"), "Provincia de Madrid");
+        // Names that are not codes pass through untouched
+        assert_eq!(names.resolve_admin1("ES", "Andalusia"), "Andalusia");
+        assert_eq!(names.resolve_admin2("ES", "51", "GR"), "GR");
+    }
+}
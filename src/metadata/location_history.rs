@@ -23,11 +23,23 @@ impl PartialOrd for LocationPoint {
     }
 }
 
+/// A stay at a named place, taken from Semantic Location History or Timeline exports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaceVisit {
+    pub start_timestamp_ms: u64,
+    pub end_timestamp_ms: u64,
+    pub name: String,
+    pub address: String,
+    pub place_id: String,
+}
+
 /// Holds a sorted collection of `LocationPoint` instances for efficient querying.
 #[derive(Debug, Default)]
 pub struct LocationHistory {
     /// A collection of location points, guaranteed to be sorted by `timestamp_ms`.
     data: Vec<LocationPoint>,
+    /// Place visits, sorted by `start_timestamp_ms`.
+    visits: Vec<PlaceVisit>,
 }
 
 // Private structs for deserializing the JSON file.
@@ -35,6 +47,7 @@ use serde::Deserialize;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 
 // Deserializes a string timestamp into a u64.
 fn parse_str_to_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
@@ -45,10 +58,21 @@ where
     s.parse::<u64>().map_err(serde::de::Error::custom)
 }
 
+// Parses an RFC 3339 timestamp into milliseconds since the epoch.
+fn parse_rfc3339_ms(s: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .ok()
+        .and_then(|dt| u64::try_from(dt.timestamp_millis()).ok())
+}
+
+// A Takeout file may hold raw records (`Records.json`), Semantic Location History
+// (`2024_JULY.json`) or the newer on-device Timeline export (`Timeline.json`).
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct TakeoutRoot {
-    locations: Vec<TakeoutLocation>,
+    locations: Option<Vec<TakeoutLocation>>,
+    timeline_objects: Option<Vec<TimelineObject>>,
+    semantic_segments: Option<Vec<SemanticSegment>>,
 }
 
 #[derive(Deserialize)]
@@ -69,6 +93,85 @@ struct TakeoutActivity {
     // The nested 'activity' array with type/confidence is ignored by serde
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimelineObject {
+    place_visit: Option<SemanticPlaceVisit>,
+    // 'activitySegment' entries are ignored by serde
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SemanticPlaceVisit {
+    location: SemanticPlace,
+    duration: SemanticDuration,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SemanticPlace {
+    place_id: Option<String>,
+    address: Option<String>,
+    name: Option<String>,
+}
+
+// Older exports use millisecond strings, newer ones RFC 3339 timestamps.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SemanticDuration {
+    start_timestamp: Option<String>,
+    end_timestamp: Option<String>,
+    start_timestamp_ms: Option<String>,
+    end_timestamp_ms: Option<String>,
+}
+
+impl SemanticDuration {
+    fn bounds(&self) -> Option<(u64, u64)> {
+        let start = match (&self.start_timestamp, &self.start_timestamp_ms) {
+            (Some(ts), _) => parse_rfc3339_ms(ts)?,
+            (None, Some(ms)) => ms.parse().ok()?,
+            (None, None) => return None,
+        };
+        let end = match (&self.end_timestamp, &self.end_timestamp_ms) {
+            (Some(ts), _) => parse_rfc3339_ms(ts)?,
+            (None, Some(ms)) => ms.parse().ok()?,
+            (None, None) => return None,
+        };
+        Some((start, end))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SemanticSegment {
+    start_time: String,
+    end_time: String,
+    visit: Option<SegmentVisit>,
+    // 'activity' and 'timelinePath' segments are ignored by serde
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SegmentVisit {
+    top_candidate: Option<VisitCandidate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VisitCandidate {
+    place_id: Option<String>,
+    semantic_type: Option<String>,
+}
+
+// The Timeline export carries no place names, only the user's own labels.
+fn semantic_type_name(semantic_type: &str) -> &'static str {
+    match semantic_type {
+        "HOME" | "INFERRED_HOME" => "Home",
+        "WORK" | "INFERRED_WORK" => "Work",
+        _ => "",
+    }
+}
+
 impl LocationHistory {
     /// Loads location history from a Google Takeout JSON file.
     pub fn from_json_file(path: &str) -> Result<Self, Box<dyn Error>> {
@@ -76,9 +179,16 @@ impl LocationHistory {
         let reader = BufReader::new(file);
         let root: TakeoutRoot = serde_json::from_reader(reader)?;
 
+        if root.locations.is_none()
+            && root.timeline_objects.is_none()
+            && root.semantic_segments.is_none()
+        {
+            return Err(format!("No location data found in {path}").into());
+        }
+
         let mut points = Vec::new();
 
-        for loc in root.locations {
+        for loc in root.locations.unwrap_or_default() {
             // Add the main location point
             points.push(LocationPoint {
                 timestamp_ms: loc.timestamp_ms,
@@ -102,7 +212,103 @@ impl LocationHistory {
         // We need to sort the entire collection of points.
         points.sort_unstable();
 
-        Ok(LocationHistory { data: points })
+        let mut visits = Vec::new();
+
+        for object in root.timeline_objects.unwrap_or_default() {
+            if let Some(visit) = object.place_visit
+                && let Some((start, end)) = visit.duration.bounds()
+            {
+                visits.push(PlaceVisit {
+                    start_timestamp_ms: start,
+                    end_timestamp_ms: end,
+                    name: visit.location.name.unwrap_or_default(),
+                    address: visit.location.address.unwrap_or_default(),
+                    place_id: visit.location.place_id.unwrap_or_default(),
+                });
+            }
+        }
+
+        for segment in root.semantic_segments.unwrap_or_default() {
+            if let Some(candidate) = segment.visit.and_then(|v| v.top_candidate)
+                && let Some(start) = parse_rfc3339_ms(&segment.start_time)
+                && let Some(end) = parse_rfc3339_ms(&segment.end_time)
+            {
+                visits.push(PlaceVisit {
+                    start_timestamp_ms: start,
+                    end_timestamp_ms: end,
+                    name: semantic_type_name(candidate.semantic_type.as_deref().unwrap_or(""))
+                        .to_string(),
+                    address: String::new(),
+                    place_id: candidate.place_id.unwrap_or_default(),
+                });
+            }
+        }
+
+        visits.sort_unstable_by_key(|v| v.start_timestamp_ms);
+
+        Ok(LocationHistory {
+            data: points,
+            visits,
+        })
+    }
+
+    /// Loads location history from a JSON file, or from every JSON file below a
+    /// directory (Semantic Location History is exported as one file per month).
+    pub fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        if !Path::new(path).is_dir() {
+            return Self::from_json_file(path);
+        }
+
+        let mut history = LocationHistory::default();
+        let mut loaded = 0;
+
+        for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+            let entry = entry?;
+            let is_json = entry
+                .path()
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+            if !entry.file_type().is_file() || !is_json {
+                continue;
+            }
+
+            // Takeout folders contain unrelated JSON files, which are skipped.
+            let Some(file_path) = entry.path().to_str() else {
+                continue;
+            };
+            if let Ok(file_history) = Self::from_json_file(file_path) {
+                history.data.extend(file_history.data);
+                history.visits.extend(file_history.visits);
+                loaded += 1;
+            }
+        }
+
+        if loaded == 0 {
+            return Err(format!("No location history files found in {path}").into());
+        }
+
+        history.data.sort_unstable();
+        history
+            .visits
+            .sort_unstable_by_key(|v| v.start_timestamp_ms);
+
+        Ok(history)
+    }
+
+    /// Builds a history from raw points, e.g. for analysis of synthetic data.
+    pub fn from_points(mut points: Vec<LocationPoint>) -> Self {
+        points.sort_unstable();
+        Self {
+            data: points,
+            visits: Vec::new(),
+        }
+    }
+
+    /// All location points recorded within `[start_ms, end_ms]`.
+    pub fn points_between(&self, start_ms: u64, end_ms: u64) -> &[LocationPoint] {
+        let start = self.data.partition_point(|p| p.timestamp_ms < start_ms);
+        let end = self.data.partition_point(|p| p.timestamp_ms <= end_ms);
+        &self.data[start..end.max(start)]
     }
 
     /// Finds the two closest location points for a given timestamp.
@@ -137,6 +343,19 @@ impl LocationHistory {
             }
         }
     }
+
+    /// Finds the place visit covering a given timestamp. When visits overlap,
+    /// the shortest (most specific) one wins.
+    pub fn find_place_visit(&self, target_timestamp_ms: u64) -> Option<&PlaceVisit> {
+        let candidates = self
+            .visits
+            .partition_point(|v| v.start_timestamp_ms <= target_timestamp_ms);
+
+        self.visits[..candidates]
+            .iter()
+            .filter(|v| v.end_timestamp_ms >= target_timestamp_ms)
+            .min_by_key(|v| v.end_timestamp_ms.saturating_sub(v.start_timestamp_ms))
+    }
 }
 
 #[cfg(test)]
@@ -209,6 +428,7 @@ mod tests {
                     longitude_e7: 4,
                 },
             ],
+            visits: vec![],
         }
     }
 
@@ -244,9 +464,23 @@ mod tests {
         assert!(after.is_none());
     }
 
+    #[test]
+    fn test_points_between() {
+        let history = create_test_history();
+        let timestamps = |points: &[LocationPoint]| -> Vec<u64> {
+            points.iter().map(|p| p.timestamp_ms).collect()
+        };
+        assert_eq!(timestamps(history.points_between(150, 300)), vec![200, 300]);
+        assert_eq!(timestamps(history.points_between(0, 50)), Vec::<u64>::new());
+        assert_eq!(
+            timestamps(history.points_between(300, 100)),
+            Vec::<u64>::new()
+        );
+    }
+
     #[test]
     fn test_find_closest_points_empty() {
-        let history = LocationHistory { data: vec![] };
+        let history = LocationHistory::default();
         let (before, after) = history.find_closest_points(100);
         assert!(before.is_none());
         assert!(after.is_none());
@@ -260,6 +494,7 @@ mod tests {
                 latitude_e7: 1,
                 longitude_e7: 1,
             }],
+            visits: vec![],
         };
         // Before
         let (before, after) = history.find_closest_points(50);
@@ -274,4 +509,85 @@ mod tests {
         assert_eq!(before.unwrap().timestamp_ms, 100);
         assert_eq!(after.unwrap().timestamp_ms, 100);
     }
+
+    #[test]
+    fn test_from_json_file_semantic_location_history() {
+        let history =
+            LocationHistory::from_json_file("test_data/timeline/semantic_location_history.json")
+                .unwrap();
+
+        assert!(history.data.is_empty());
+        assert_eq!(history.visits.len(), 2);
+
+        let visit = history.find_place_visit(1720778400000).unwrap();
+        assert_eq!(visit.name, "Museo del Prado");
+        assert_eq!(
+            visit.address,
+            "Calle de Ruiz de Alarcón, 23, 28014 Madrid, Spain"
+        );
+        assert_eq!(visit.place_id, "ChIJ1RsMxHQmQg0RvBGGBVSXyOs");
+
+        // Legacy millisecond durations are understood as well
+        let visit = history.find_place_visit(1720800000000).unwrap();
+        assert_eq!(visit.name, "Mercado de San Miguel");
+
+        // Activity segments do not produce visits
+        assert!(history.find_place_visit(1720790000000).is_none());
+    }
+
+    #[test]
+    fn test_from_json_file_timeline_export() {
+        let history = LocationHistory::from_json_file("test_data/timeline/timeline.json").unwrap();
+
+        assert_eq!(history.visits.len(), 2);
+
+        let visit = history.find_place_visit(1720807200000).unwrap();
+        assert_eq!(visit.name, "Home");
+        assert!(visit.address.is_empty());
+        assert_eq!(visit.place_id, "ChIJgTwKgJcpQg0RaSKMYcHeNsQ");
+    }
+
+    #[test]
+    fn test_from_json_file_without_location_data() {
+        let result = LocationHistory::from_json_file("test_data/timeline/settings.json");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_path_directory() {
+        // Unrelated and malformed JSON files in the directory are skipped
+        let history = LocationHistory::from_path("test_data/timeline").unwrap();
+        assert!(history.data.is_empty());
+        assert_eq!(history.visits.len(), 4);
+
+        let starts: Vec<u64> = history
+            .visits
+            .iter()
+            .map(|v| v.start_timestamp_ms)
+            .collect();
+        let mut sorted = starts.clone();
+        sorted.sort_unstable();
+        assert_eq!(starts, sorted);
+    }
+
+    #[test]
+    fn test_find_place_visit_prefers_shortest() {
+        let visit = |start, end, name: &str| PlaceVisit {
+            start_timestamp_ms: start,
+            end_timestamp_ms: end,
+            name: name.to_string(),
+            address: String::new(),
+            place_id: String::new(),
+        };
+        let history = LocationHistory {
+            data: vec![],
+            visits: vec![visit(100, 500, "Park"), visit(200, 300, "Cafe")],
+        };
+
+        assert_eq!(history.find_place_visit(150).unwrap().name, "Park");
+        assert_eq!(history.find_place_visit(250).unwrap().name, "Cafe");
+        assert_eq!(history.find_place_visit(500).unwrap().name, "Park");
+        assert!(history.find_place_visit(50).is_none());
+        assert!(history.find_place_visit(501).is_none());
+    }
 }
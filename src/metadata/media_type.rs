@@ -0,0 +1,269 @@
+// Media Type Module
+// Classifies files into a broad `type` (image, video, audio, document) and a finer
+// `subtype` (raw, heic, gif, screenshot, scan, audio, document) from their MIME
+// type, extension and name, with overrides from the `media_types:` configuration.
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::raw;
+
+/// Extensions of camera RAW formats, which MIME sniffing often misidentifies.
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dcr", "dng", "erf", "iiq", "kdc", "mef", "mos", "mrw",
+    "nef", "nrw", "orf", "pef", "raf", "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+
+const DOCUMENT_MIME_PREFIXES: &[&str] = &[
+    "application/pdf",
+    "application/msword",
+    "application/rtf",
+    "application/vnd.ms-",
+    "application/vnd.oasis.opendocument.",
+    "application/vnd.openxmlformats-officedocument.",
+];
+
+const SCREENSHOT_PREFIXES: &[&str] = &[
+    "screenshot",
+    "screen shot",
+    "captura de pantalla",
+    "bildschirmfoto",
+    "capture d'écran",
+    "schermafbeelding",
+];
+
+/// Overrides as written in the configuration. Values are "type" or "type/subtype",
+/// e.g. `m4a: audio` or `pdf: document/scan`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaTypeConfig {
+    /// Types processed besides images and videos when non-media files are left
+    /// out, e.g. `[audio, document]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Keyed by lowercase file extension, without the dot
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extensions: HashMap<String, String>,
+    /// Keyed by MIME type
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mime: HashMap<String, String>,
+}
+
+/// The classification of a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaType {
+    pub r#type: String,
+    pub subtype: String,
+    pub mime: String,
+}
+
+impl MediaType {
+    /// Images and videos, the files processed by default.
+    pub fn is_media(&self) -> bool {
+        matches!(self.r#type.as_str(), "image" | "video")
+    }
+}
+
+/// Whether a lowercase file name marks a scan: a "scan" word, alone or numbered
+/// ("scan_0001.jpg", "Family scan.pdf", "scan0001.tif"), but not "Scandinavia.jpg".
+fn is_scan_name(name: &str) -> bool {
+    name.split(|c: char| !c.is_alphanumeric()).any(|word| {
+        word.strip_prefix("scan")
+            .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+impl MediaTypeConfig {
+    /// Whether files of this type are processed when non-media files are left out.
+    pub fn collects(&self, media_type: &MediaType) -> bool {
+        media_type.is_media() || self.include.contains(&media_type.r#type)
+    }
+
+    /// Classifies a file on disk, sniffing its MIME type from the content. RAW
+    /// headers are recognized first, as generic sniffing mostly reports them as TIFF.
+    pub fn classify(&self, path: &Utf8Path) -> MediaType {
+        let mime = match raw::sniff_format(path) {
+            Some(format) => format.mime(),
+            None => tree_magic_mini::from_filepath(path.as_std_path()).unwrap_or_default(),
+        };
+        self.classify_parts(path.file_name().unwrap_or(""), mime)
+    }
+
+    /// Classifies a file from its name and MIME type.
+    pub fn classify_parts(&self, file_name: &str, mime: &str) -> MediaType {
+        let name = file_name.to_lowercase();
+        let extension = Utf8Path::new(&name).extension().unwrap_or("");
+
+        let overridden = self
+            .extensions
+            .get(extension)
+            .or_else(|| self.mime.get(mime));
+        if let Some(value) = overridden {
+            let (r#type, subtype) = value.split_once('/').unwrap_or((value, ""));
+            return MediaType {
+                r#type: r#type.to_string(),
+                subtype: subtype.to_string(),
+                mime: mime.to_string(),
+            };
+        }
+
+        let (r#type, subtype) = if RAW_EXTENSIONS.contains(&extension) || raw::is_raw_mime(mime) {
+            ("image", "raw")
+        } else if let Some(image) = mime.strip_prefix("image/") {
+            let subtype = if matches!(image, "heic" | "heif" | "heic-sequence" | "avif")
+                || matches!(extension, "heic" | "heif")
+            {
+                "heic"
+            } else if image == "gif" {
+                "gif"
+            } else if SCREENSHOT_PREFIXES.iter().any(|p| name.starts_with(p)) {
+                "screenshot"
+            } else if is_scan_name(&name) {
+                "scan"
+            } else {
+                ""
+            };
+            ("image", subtype)
+        } else if mime.starts_with("video/") {
+            ("video", "")
+        } else if mime.starts_with("audio/") {
+            ("audio", "audio")
+        } else if DOCUMENT_MIME_PREFIXES.iter().any(|p| mime.starts_with(p)) {
+            let scanned = mime == "application/pdf" && is_scan_name(&name);
+            ("document", if scanned { "scan" } else { "document" })
+        } else {
+            ("unknown", "")
+        };
+
+        MediaType {
+            r#type: r#type.to_string(),
+            subtype: subtype.to_string(),
+            mime: mime.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(config: &MediaTypeConfig, name: &str, mime: &str) -> (String, String) {
+        let media_type = config.classify_parts(name, mime);
+        (media_type.r#type, media_type.subtype)
+    }
+
+    fn pair(r#type: &str, subtype: &str) -> (String, String) {
+        (r#type.to_string(), subtype.to_string())
+    }
+
+    #[test]
+    fn test_builtin_classification() {
+        let config = MediaTypeConfig::default();
+        assert_eq!(
+            classify(&config, "IMG_0001.JPG", "image/jpeg"),
+            pair("image", "")
+        );
+        assert_eq!(
+            classify(&config, "IMG_0001.CR3", "application/octet-stream"),
+            pair("image", "raw")
+        );
+        assert_eq!(
+            classify(&config, "DSC_0001.TIF", "image/x-nikon-nef"),
+            pair("image", "raw")
+        );
+        assert_eq!(
+            classify(&config, "IMG_0001.HEIC", "image/heif"),
+            pair("image", "heic")
+        );
+        assert_eq!(
+            classify(&config, "funny.gif", "image/gif"),
+            pair("image", "gif")
+        );
+        assert_eq!(
+            classify(
+                &config,
+                "Screenshot 2024-07-12 at 10.00.00.png",
+                "image/png"
+            ),
+            pair("image", "screenshot")
+        );
+        assert_eq!(
+            classify(&config, "clip.mp4", "video/mp4"),
+            pair("video", "")
+        );
+        assert_eq!(
+            classify(&config, "memo.m4a", "audio/mp4"),
+            pair("audio", "audio")
+        );
+        assert_eq!(
+            classify(&config, "Scan_0001.pdf", "application/pdf"),
+            pair("document", "scan")
+        );
+        assert_eq!(
+            classify(&config, "invoice.pdf", "application/pdf"),
+            pair("document", "document")
+        );
+        assert_eq!(
+            classify(&config, "notes.zip", "application/zip"),
+            pair("unknown", "")
+        );
+    }
+
+    #[test]
+    fn test_scan_names() {
+        let config = MediaTypeConfig::default();
+        for name in [
+            "scan_0001.jpg",
+            "Family scan.jpg",
+            "SCAN0001.JPG",
+            "scan.png",
+        ] {
+            assert_eq!(classify(&config, name, "image/jpeg"), pair("image", "scan"));
+        }
+        for name in [
+            "Scandinavia_001.jpg",
+            "scanner-test.jpg",
+            "Vulcano scans.jpg",
+        ] {
+            assert_eq!(classify(&config, name, "image/jpeg"), pair("image", ""));
+        }
+    }
+
+    #[test]
+    fn test_collected_types() {
+        let config = MediaTypeConfig::default();
+        let collects = |config: &MediaTypeConfig, name: &str, mime: &str| {
+            config.collects(&config.classify_parts(name, mime))
+        };
+        assert!(collects(&config, "IMG_0001.JPG", "image/jpeg"));
+        assert!(collects(&config, "clip.mp4", "video/mp4"));
+        // Audio and documents only when asked for
+        assert!(!collects(&config, "memo.m4a", "audio/mp4"));
+        assert!(!collects(&config, "invoice.pdf", "application/pdf"));
+        assert!(!collects(&config, "notes.zip", "application/zip"));
+
+        let config: MediaTypeConfig = serde_yaml::from_str("include: [document]").unwrap();
+        assert!(collects(&config, "invoice.pdf", "application/pdf"));
+        assert!(!collects(&config, "memo.m4a", "audio/mp4"));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config: MediaTypeConfig = serde_yaml::from_str(
+            "extensions:\n  zip: document\n  pdf: document/scan\nmime:\n  application/x-voice: audio\n",
+        )
+        .unwrap();
+        assert_eq!(
+            classify(&config, "notes.ZIP", "application/zip"),
+            pair("document", "")
+        );
+        assert_eq!(
+            classify(&config, "invoice.pdf", "application/pdf"),
+            pair("document", "scan")
+        );
+        assert_eq!(
+            classify(&config, "memo.amr", "application/x-voice"),
+            pair("audio", "")
+        );
+    }
+}
@@ -1,8 +1,38 @@
+pub mod camera;
+pub mod clock;
 pub mod context;
+pub mod countries;
+pub mod dimensions;
+pub mod exiftool;
 pub mod extractor;
+pub mod geo;
+pub mod iptc;
+pub mod live_photo;
 pub mod location;
 pub mod location_history;
+pub mod media_type;
+pub mod phash;
+pub mod places;
+pub mod raw;
+pub mod sidecar;
+pub mod tags;
+pub mod takeout;
+pub mod timezone;
+pub mod values;
+pub mod xmp;
 
+pub use camera::CameraConfig;
+pub use clock::{ClockOffsetConfig, ClockOffsets};
 pub use context::MediaContext;
-pub use extractor::{extract_metadata, extract_metadata_with_location_history};
-pub use location_history::{LocationHistory, LocationPoint};
+pub use countries::country_name;
+pub use exiftool::{ExifTool, ExifToolConfig};
+pub use extractor::{
+    ExtractOptions, extract_metadata, extract_metadata_with_location_history,
+    extract_metadata_with_options,
+};
+pub use location::{GeocoderConfig, configure_geocoder};
+pub use location_history::{LocationHistory, LocationPoint, PlaceVisit};
+pub use media_type::{MediaType, MediaTypeConfig};
+pub use places::{PlaceConfig, Places};
+pub use sidecar::SidecarPatterns;
+pub use values::Datetime;
@@ -0,0 +1,126 @@
+// Perceptual Hash Module
+// Difference hashes (dHash) that stay the same when a photo is re-encoded, resized
+// or lightly edited, so re-exported copies can be recognized as near-duplicates.
+
+use camino::Utf8Path;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::io::{BufRead, Seek};
+
+/// 64-bit difference hash: each bit tells whether a pixel of a 9x8 grayscale
+/// thumbnail is brighter than its right neighbor.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let thumbnail = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = thumbnail.get_pixel(x, y)[0];
+            let right = thumbnail.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+/// Hashes an image file as a 16-digit hex string, or `None` if it cannot be decoded.
+pub fn phash_file(path: &Utf8Path) -> Option<String> {
+    let image = decode(ImageReader::open(path.as_std_path()).ok()?)?;
+    Some(format!("{:016x}", dhash(&image)))
+}
+
+/// Decodes an image turned upright by its EXIF Orientation, so that a copy whose
+/// pixels were rotated on export hashes like the original.
+fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> Option<DynamicImage> {
+    let mut decoder = reader.with_guessed_format().ok()?.into_decoder().ok()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).ok()?;
+    image.apply_orientation(orientation);
+    Some(image)
+}
+
+/// Number of differing bits between two hex hashes; 0 means visually identical and
+/// up to about 10 (of 64) usually means the same picture.
+pub fn phash_distance(a: &str, b: &str) -> Option<u32> {
+    let a = u64::from_str_radix(a, 16).ok()?;
+    let b = u64::from_str_radix(b, 16).ok()?;
+    Some((a ^ b).count_ones())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn picture(width: u32, height: u32, invert: bool) -> DynamicImage {
+        let image = ImageBuffer::from_fn(width, height, |x, y| {
+            // A diagonal gradient with a bright blob, so rows differ
+            let dx = x as f64 / width as f64 - 0.3;
+            let dy = y as f64 / height as f64 - 0.6;
+            let blob = (1.0 - (dx * dx + dy * dy).sqrt() * 3.0).max(0.0);
+            let value = ((x + y) as f64 / (width + height) as f64 * 0.5 + blob * 0.5) * 255.0;
+            let value = if invert { 255.0 - value } else { value } as u8;
+            Rgb([value, value / 2, 255 - value])
+        });
+        DynamicImage::ImageRgb8(image)
+    }
+
+    fn hash(image: &DynamicImage) -> String {
+        format!("{:016x}", dhash(image))
+    }
+
+    #[test]
+    fn test_resized_copy_matches() {
+        let original = hash(&picture(400, 300, false));
+        let resized = hash(&picture(160, 120, false));
+        assert!(phash_distance(&original, &resized).unwrap() <= 4);
+    }
+
+    #[test]
+    fn test_different_picture_differs() {
+        let original = hash(&picture(400, 300, false));
+        let inverted = hash(&picture(400, 300, true));
+        assert!(phash_distance(&original, &inverted).unwrap() > 32);
+    }
+
+    // A JPEG of `image` with an APP1 EXIF segment holding only the Orientation
+    fn jpeg(image: &DynamicImage, orientation: u8) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut encoded),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        tiff.extend([orientation, 0, 0, 0, 0, 0, 0, 0]);
+        let mut app1 = vec![0xff, 0xe1];
+        app1.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        app1.extend(b"Exif\0\0");
+        app1.extend(tiff);
+        encoded.splice(2..2, app1);
+        encoded
+    }
+
+    #[test]
+    fn test_rotated_copy_matches() {
+        let original = picture(400, 300, false);
+        // Stored sideways with Orientation 6 (rotate 90° clockwise to display)
+        let rotated = jpeg(&original.rotate270(), 6);
+        let reader = ImageReader::new(std::io::Cursor::new(rotated));
+        let upright = decode(reader).unwrap();
+
+        assert_eq!((upright.width(), upright.height()), (400, 300));
+        assert!(phash_distance(&hash(&original), &hash(&upright)).unwrap() <= 4);
+    }
+
+    #[test]
+    fn test_phash_distance() {
+        assert_eq!(
+            phash_distance("00000000000000ff", "000000000000000f"),
+            Some(4)
+        );
+        assert_eq!(phash_distance("zz", "00"), None);
+    }
+}
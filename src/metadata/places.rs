@@ -0,0 +1,327 @@
+// Places Module
+// Named geofences from the `places:` configuration section: circles around a center
+// point and polygons loaded from GeoJSON files.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+
+use super::context::SpaceContext;
+use super::geo::haversine_km;
+
+/// A named place as written in the configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaceConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub shape: PlaceShapeConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlaceShapeConfig {
+    /// A circle of `radius_m` meters around a center point
+    Circle { lat: f64, lon: f64, radius_m: f64 },
+    /// Polygons from a GeoJSON file (Polygon, MultiPolygon, Feature or FeatureCollection)
+    GeoJson { geojson: String },
+}
+
+/// A polygon ring as (lon, lat) pairs, following GeoJSON axis order.
+type Ring = Vec<(f64, f64)>;
+
+/// A polygon made of an outer ring and optional holes.
+#[derive(Debug, Clone)]
+struct Polygon {
+    outer: Ring,
+    holes: Vec<Ring>,
+}
+
+impl Polygon {
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        ring_contains(&self.outer, lat, lon)
+            && !self.holes.iter().any(|hole| ring_contains(hole, lat, lon))
+    }
+}
+
+// Signed planar area and area-weighted centroid sums (shoelace formula) of a ring,
+// relative to `origin` to keep precision with small rings far from (0, 0).
+fn ring_moments(ring: &Ring, (ox, oy): (f64, f64)) -> (f64, f64, f64) {
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    let mut j = ring.len().wrapping_sub(1);
+
+    for i in 0..ring.len() {
+        let (xi, yi) = (ring[i].0 - ox, ring[i].1 - oy);
+        let (xj, yj) = (ring[j].0 - ox, ring[j].1 - oy);
+        let cross = xj * yi - xi * yj;
+        area += cross;
+        cx += (xj + xi) * cross;
+        cy += (yj + yi) * cross;
+        j = i;
+    }
+
+    (area / 2.0, cx / 6.0, cy / 6.0)
+}
+
+// Ray casting point-in-polygon test.
+fn ring_contains(ring: &Ring, lat: f64, lon: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > lat) != (yj > lat) && lon < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+#[derive(Debug, Clone)]
+enum Shape {
+    Circle { lat: f64, lon: f64, radius_km: f64 },
+    Polygons(Vec<Polygon>),
+}
+
+/// A place with its geometry resolved and ready for lookups.
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub name: String,
+    shape: Shape,
+}
+
+impl Place {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match &self.shape {
+            Shape::Circle {
+                lat: center_lat,
+                lon: center_lon,
+                radius_km,
+            } => haversine_km(lat, lon, *center_lat, *center_lon) <= *radius_km,
+            Shape::Polygons(polygons) => polygons.iter().any(|p| p.contains(lat, lon)),
+        }
+    }
+
+    /// Representative point as (lat, lon): the circle center, or the area-weighted
+    /// centroid of the outer rings of a polygonal place.
+    pub fn center(&self) -> (f64, f64) {
+        match &self.shape {
+            Shape::Circle { lat, lon, .. } => (*lat, *lon),
+            Shape::Polygons(polygons) => {
+                let origin = polygons[0].outer[0];
+                let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+                for polygon in polygons {
+                    // Normalize the winding so every ring adds up
+                    let (a, x, y) = ring_moments(&polygon.outer, origin);
+                    let sign = a.signum();
+                    area += a * sign;
+                    cx += x * sign;
+                    cy += y * sign;
+                }
+                if area > 0.0 {
+                    (origin.1 + cy / area, origin.0 + cx / area)
+                } else {
+                    // Degenerate rings: average the vertices instead
+                    let points: Vec<_> = polygons.iter().flat_map(|p| &p.outer).collect();
+                    let n = points.len().max(1) as f64;
+                    let lat = points.iter().map(|(_, lat)| lat).sum::<f64>() / n;
+                    let lon = points.iter().map(|(lon, _)| lon).sum::<f64>() / n;
+                    (lat, lon)
+                }
+            }
+        }
+    }
+}
+
+/// All configured places, in configuration order.
+#[derive(Debug, Clone, Default)]
+pub struct Places {
+    places: Vec<Place>,
+}
+
+impl Places {
+    /// Resolves the configured places, reading GeoJSON files from disk.
+    pub fn load(configs: &[PlaceConfig]) -> Result<Self> {
+        let places = configs
+            .iter()
+            .map(|config| {
+                let shape = match &config.shape {
+                    PlaceShapeConfig::Circle { lat, lon, radius_m } => Shape::Circle {
+                        lat: *lat,
+                        lon: *lon,
+                        radius_km: radius_m / 1000.0,
+                    },
+                    PlaceShapeConfig::GeoJson { geojson } => Shape::Polygons(
+                        load_geojson_polygons(geojson)
+                            .with_context(|| format!("Failed to load place '{}'", config.name))?,
+                    ),
+                };
+                Ok(Place {
+                    name: config.name.clone(),
+                    shape,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { places })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Place> {
+        self.places.iter().find(|p| p.name == name)
+    }
+
+    /// Returns the first configured place containing the coordinates.
+    pub fn find(&self, lat: f64, lon: f64) -> Option<&Place> {
+        self.places.iter().find(|p| p.contains(lat, lon))
+    }
+
+    /// Whether the named place exists and contains the coordinates.
+    pub fn contains(&self, name: &str, lat: f64, lon: f64) -> bool {
+        self.places
+            .iter()
+            .any(|p| p.name == name && p.contains(lat, lon))
+    }
+
+    /// Sets `space.place` for files with coordinates inside a configured place.
+    pub fn tag(&self, space: &mut SpaceContext) {
+        if space.lat == 0.0 && space.lon == 0.0 {
+            return;
+        }
+        if let Some(place) = self.find(space.lat, space.lon) {
+            space.place = place.name.clone();
+        }
+    }
+}
+
+fn load_geojson_polygons(path: &str) -> Result<Vec<Polygon>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read GeoJSON: {path}"))?;
+    let value: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse GeoJSON: {path}"))?;
+
+    let mut polygons = Vec::new();
+    collect_polygons(&value, &mut polygons);
+
+    if polygons.is_empty() {
+        anyhow::bail!("No polygons found in GeoJSON: {path}");
+    }
+
+    Ok(polygons)
+}
+
+fn collect_polygons(value: &Value, polygons: &mut Vec<Polygon>) {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                collect_polygons(feature, polygons);
+            }
+        }
+        Some("Feature") => collect_polygons(&value["geometry"], polygons),
+        Some("GeometryCollection") => {
+            for geometry in value["geometries"].as_array().into_iter().flatten() {
+                collect_polygons(geometry, polygons);
+            }
+        }
+        Some("Polygon") => polygons.extend(parse_polygon(&value["coordinates"])),
+        Some("MultiPolygon") => {
+            for polygon in value["coordinates"].as_array().into_iter().flatten() {
+                polygons.extend(parse_polygon(polygon));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_polygon(coordinates: &Value) -> Option<Polygon> {
+    let mut rings = coordinates.as_array()?.iter().map(|ring| {
+        ring.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|position| Some((position[0].as_f64()?, position[1].as_f64()?)))
+            .collect::<Ring>()
+    });
+
+    let outer = rings.next().filter(|ring| ring.len() >= 3)?;
+    Some(Polygon {
+        outer,
+        holes: rings.filter(|ring| ring.len() >= 3).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Vec<PlaceConfig> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_circle() {
+        let places = Places::load(&config(
+            "- name: Home\n  lat: 40.4168\n  lon: -3.7038\n  radius_m: 200\n",
+        ))
+        .unwrap();
+
+        assert!(places.contains("Home", 40.4175, -3.7030));
+        assert!(!places.contains("Home", 40.4300, -3.7038));
+        assert!(!places.contains("Office", 40.4168, -3.7038));
+        assert_eq!(places.find(40.4168, -3.7038).unwrap().name, "Home");
+        assert_eq!(places.get("Home").unwrap().center(), (40.4168, -3.7038));
+    }
+
+    #[test]
+    fn test_geojson_polygon_with_hole() {
+        let places = Places::load(&config(
+            "- name: Retiro\n  geojson: test_data/places/retiro.geojson\n",
+        ))
+        .unwrap();
+
+        assert!(places.contains("Retiro", 40.4180, -3.6880));
+        // The pond is cut out of the park
+        assert!(!places.contains("Retiro", 40.4175, -3.6830));
+        assert!(!places.contains("Retiro", 40.4168, -3.7038));
+
+        let (lat, lon) = places.get("Retiro").unwrap().center();
+        assert!(
+            (lat - 40.4150).abs() < 1e-9 && (lon + 3.6860).abs() < 1e-9,
+            "{lat} {lon}"
+        );
+    }
+
+    #[test]
+    fn test_missing_geojson() {
+        let result = Places::load(&config(
+            "- name: Nowhere\n  geojson: test_data/places/missing.geojson\n",
+        ));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_tag_uses_first_matching_place() {
+        let places = Places::load(&config(
+            "- name: Home\n  lat: 40.4168\n  lon: -3.7038\n  radius_m: 100\n\
+             - name: Madrid\n  lat: 40.4168\n  lon: -3.7038\n  radius_m: 10000\n",
+        ))
+        .unwrap();
+
+        let mut space = SpaceContext {
+            lat: 40.4168,
+            lon: -3.7038,
+            ..Default::default()
+        };
+        places.tag(&mut space);
+        assert_eq!(space.place, "Home");
+
+        let mut space = SpaceContext::default();
+        places.tag(&mut space);
+        assert!(space.place.is_empty());
+    }
+}
@@ -0,0 +1,702 @@
+// RAW Module
+// Recognizes camera RAW files by their headers and reads what generic decoders
+// cannot: full sensor dimensions and the capture time from the TIFF structure most
+// RAW formats share, the image size from the Fujifilm RAF header, and the same
+// from the TIFF blocks Canon embeds in CR3 (CMT1 with IFD0, CMT2 with the EXIF
+// IFD). RAF EXIF is otherwise parsed by nom-exif like any other file.
+
+use camino::Utf8Path;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::media_type::RAW_EXTENSIONS;
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0x00FE;
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xA003;
+const TAG_DNG_VERSION: u16 = 0xC612;
+// Panasonic RW2 sensor size, in place of ImageWidth/ImageLength
+const TAG_RW2_SENSOR_WIDTH: u16 = 0x0002;
+const TAG_RW2_SENSOR_HEIGHT: u16 = 0x0003;
+
+/// Upper bound on IFDs visited, against corrupt or looping offsets.
+const MAX_IFDS: usize = 32;
+
+/// Upper bound on boxes read from one CR3 container.
+const MAX_BOXES: usize = 64;
+
+/// The `uuid` box inside `moov` holding Canon's metadata boxes.
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xC0, 0xB6, 0x87, 0x82, 0x0F, 0x11, 0xE0, 0x81, 0x11, 0xF4, 0xCE, 0x46, 0x2B, 0x6A, 0x48,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    Cr2,
+    Cr3,
+    Nef,
+    Arw,
+    Raf,
+    Dng,
+    Orf,
+    Rw2,
+    Pef,
+    /// Another TIFF-based RAW recognized by its extension
+    Tiff,
+}
+
+impl RawFormat {
+    const ALL: [RawFormat; 10] = [
+        RawFormat::Cr2,
+        RawFormat::Cr3,
+        RawFormat::Nef,
+        RawFormat::Arw,
+        RawFormat::Raf,
+        RawFormat::Dng,
+        RawFormat::Orf,
+        RawFormat::Rw2,
+        RawFormat::Pef,
+        RawFormat::Tiff,
+    ];
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            RawFormat::Cr2 => "image/x-canon-cr2",
+            RawFormat::Cr3 => "image/x-canon-cr3",
+            RawFormat::Nef => "image/x-nikon-nef",
+            RawFormat::Arw => "image/x-sony-arw",
+            RawFormat::Raf => "image/x-fuji-raf",
+            RawFormat::Dng => "image/x-adobe-dng",
+            RawFormat::Orf => "image/x-olympus-orf",
+            RawFormat::Rw2 => "image/x-panasonic-rw2",
+            RawFormat::Pef => "image/x-pentax-pef",
+            RawFormat::Tiff => "image/x-raw",
+        }
+    }
+
+    fn is_tiff_based(&self) -> bool {
+        !matches!(self, RawFormat::Cr3 | RawFormat::Raf)
+    }
+}
+
+/// What could be read from a RAW file's own structure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// EXIF-style "YYYY:MM:DD HH:MM:SS"
+    pub datetime_original: Option<String>,
+}
+
+/// Recognizes a RAW file from its first bytes, using the extension only to name
+/// TIFF-based formats without a distinctive signature.
+pub fn sniff_format(path: &Utf8Path) -> Option<RawFormat> {
+    let mut file = File::open(path.as_std_path()).ok()?;
+    let mut header = [0u8; 16];
+    file.read_exact(&mut header).ok()?;
+
+    let extension = path.extension().unwrap_or("").to_lowercase();
+    sniff_header(&header, &extension).or_else(|| {
+        let tiff = header.starts_with(b"II*\0") || header.starts_with(b"MM\0*");
+        (tiff && is_dng(&mut file)).then_some(RawFormat::Dng)
+    })
+}
+
+/// Whether a MIME type is one of those assigned to sniffed RAW files.
+pub fn is_raw_mime(mime: &str) -> bool {
+    RawFormat::ALL.iter().any(|format| format.mime() == mime)
+}
+
+fn sniff_header(header: &[u8; 16], extension: &str) -> Option<RawFormat> {
+    if header.starts_with(b"FUJIFILMCCD-RAW") {
+        return Some(RawFormat::Raf);
+    }
+    if &header[4..12] == b"ftypcrx " {
+        return Some(RawFormat::Cr3);
+    }
+    if header.starts_with(b"IIRO") || header.starts_with(b"IIRS") || header.starts_with(b"MMOR") {
+        return Some(RawFormat::Orf);
+    }
+    if header.starts_with(b"IIU\0") {
+        return Some(RawFormat::Rw2);
+    }
+
+    let tiff = header.starts_with(b"II*\0") || header.starts_with(b"MM\0*");
+    if !tiff {
+        return None;
+    }
+    if &header[8..10] == b"CR" {
+        return Some(RawFormat::Cr2);
+    }
+    match extension {
+        "nef" | "nrw" => Some(RawFormat::Nef),
+        "arw" | "srf" | "sr2" => Some(RawFormat::Arw),
+        "dng" => Some(RawFormat::Dng),
+        "pef" => Some(RawFormat::Pef),
+        // Plain TIFFs are only RAW when the extension says so or DNGVersion is set
+        ext if RAW_EXTENSIONS.contains(&ext) => Some(RawFormat::Tiff),
+        _ => None,
+    }
+}
+
+/// Reads dimensions, camera and capture time from a RAW file.
+pub fn inspect(path: &Utf8Path, format: RawFormat) -> Option<RawInfo> {
+    let mut file = File::open(path.as_std_path()).ok()?;
+    if format == RawFormat::Raf {
+        return read_raf(&mut file).ok();
+    }
+    if format == RawFormat::Cr3 {
+        return read_cr3(&mut file).ok();
+    }
+    if format.is_tiff_based() {
+        return read_tiff(&mut file).ok();
+    }
+    None
+}
+
+// A TIFF carrying a DNGVersion tag is a DNG whatever its extension.
+fn is_dng<R: Read + Seek>(reader: &mut R) -> bool {
+    let Ok(mut tiff) = Tiff::new(reader, 0) else {
+        return false;
+    };
+    let Ok(first) = tiff.first_ifd() else {
+        return false;
+    };
+    tiff.read_ifd(first)
+        .map(|ifd| ifd.entries.iter().any(|e| e.tag == TAG_DNG_VERSION))
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// The value itself when it fits in four bytes, otherwise its offset
+    value: [u8; 4],
+}
+
+struct Ifd {
+    entries: Vec<Entry>,
+    next: u32,
+}
+
+struct Tiff<'a, R: Read + Seek> {
+    reader: &'a mut R,
+    /// Offset of the TIFF header within the file
+    base: u64,
+    little_endian: bool,
+}
+
+impl<'a, R: Read + Seek> Tiff<'a, R> {
+    fn new(reader: &'a mut R, base: u64) -> io::Result<Self> {
+        reader.seek(SeekFrom::Start(base))?;
+        let mut order = [0u8; 2];
+        reader.read_exact(&mut order)?;
+        let little_endian = match &order {
+            b"II" => true,
+            b"MM" => false,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a TIFF")),
+        };
+        Ok(Self {
+            reader,
+            base,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn read_at(&mut self, offset: u32, buffer: &mut [u8]) -> io::Result<()> {
+        self.reader
+            .seek(SeekFrom::Start(self.base + u64::from(offset)))?;
+        self.reader.read_exact(buffer)
+    }
+
+    fn first_ifd(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.read_at(4, &mut bytes)?;
+        Ok(self.u32(bytes))
+    }
+
+    fn read_ifd(&mut self, offset: u32) -> io::Result<Ifd> {
+        let mut count = [0u8; 2];
+        self.read_at(offset, &mut count)?;
+        let count = self.u16(count) as usize;
+
+        let mut raw = vec![0u8; count * 12 + 4];
+        self.read_at(offset + 2, &mut raw)?;
+        let entries = raw
+            .chunks_exact(12)
+            .map(|chunk| Entry {
+                tag: self.u16([chunk[0], chunk[1]]),
+                kind: self.u16([chunk[2], chunk[3]]),
+                count: self.u32([chunk[4], chunk[5], chunk[6], chunk[7]]),
+                value: [chunk[8], chunk[9], chunk[10], chunk[11]],
+            })
+            .collect();
+        let tail = &raw[count * 12..];
+        let next = self.u32([tail[0], tail[1], tail[2], tail[3]]);
+
+        Ok(Ifd { entries, next })
+    }
+
+    /// First value of a SHORT or LONG entry.
+    fn number(&self, entry: &Entry) -> Option<u32> {
+        match entry.kind {
+            3 => Some(u32::from(self.u16([entry.value[0], entry.value[1]]))),
+            4 => Some(self.u32(entry.value)),
+            _ => None,
+        }
+    }
+
+    /// All values of a LONG (or IFD) entry.
+    fn longs(&mut self, entry: &Entry) -> io::Result<Vec<u32>> {
+        if entry.count <= 1 {
+            return Ok(vec![self.u32(entry.value)]);
+        }
+        let count = entry.count.min(64) as usize;
+        let mut raw = vec![0u8; count * 4];
+        self.read_at(self.u32(entry.value), &mut raw)?;
+        Ok(raw
+            .chunks_exact(4)
+            .map(|chunk| self.u32([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    fn ascii(&mut self, entry: &Entry) -> io::Result<Option<String>> {
+        if entry.kind != 2 {
+            return Ok(None);
+        }
+        let count = entry.count.min(256) as usize;
+        let bytes = if count <= 4 {
+            entry.value[..count].to_vec()
+        } else {
+            let mut raw = vec![0u8; count];
+            self.read_at(self.u32(entry.value), &mut raw)?;
+            raw
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        Ok((!text.is_empty()).then(|| text.to_string()))
+    }
+}
+
+fn read_tiff<R: Read + Seek>(reader: &mut R) -> io::Result<RawInfo> {
+    read_tiff_at(reader, 0)
+}
+
+fn read_tiff_at<R: Read + Seek>(reader: &mut R, base: u64) -> io::Result<RawInfo> {
+    let mut tiff = Tiff::new(reader, base)?;
+    let mut info = RawInfo::default();
+    let mut largest = 0u64;
+
+    let mut pending = vec![tiff.first_ifd()?];
+    let mut visited = Vec::new();
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.contains(&offset) || visited.len() >= MAX_IFDS {
+            continue;
+        }
+        visited.push(offset);
+        let Ok(ifd) = tiff.read_ifd(offset) else {
+            continue;
+        };
+        if ifd.next != 0 {
+            pending.push(ifd.next);
+        }
+
+        let (mut width, mut height, mut reduced) = (None, None, false);
+        for entry in &ifd.entries {
+            match entry.tag {
+                TAG_NEW_SUBFILE_TYPE => reduced = tiff.number(entry).is_some_and(|v| v & 1 == 1),
+                TAG_IMAGE_WIDTH | TAG_PIXEL_X_DIMENSION | TAG_RW2_SENSOR_WIDTH => {
+                    width = tiff.number(entry)
+                }
+                TAG_IMAGE_LENGTH | TAG_PIXEL_Y_DIMENSION | TAG_RW2_SENSOR_HEIGHT => {
+                    height = tiff.number(entry)
+                }
+                TAG_MAKE if info.make.is_none() => info.make = tiff.ascii(entry)?,
+                TAG_MODEL if info.model.is_none() => info.model = tiff.ascii(entry)?,
+                TAG_DATE_TIME_ORIGINAL => info.datetime_original = tiff.ascii(entry)?,
+                TAG_DATE_TIME if info.datetime_original.is_none() => {
+                    info.datetime_original = tiff.ascii(entry)?
+                }
+                TAG_SUB_IFDS | TAG_EXIF_IFD => pending.extend(tiff.longs(entry)?),
+                _ => {}
+            }
+        }
+
+        // The full-resolution image is the largest one that is not a preview
+        if let (Some(w), Some(h)) = (width, height)
+            && !reduced
+            && u64::from(w) * u64::from(h) > largest
+        {
+            largest = u64::from(w) * u64::from(h);
+            info.width = Some(w);
+            info.height = Some(h);
+        }
+    }
+
+    Ok(info)
+}
+
+// CR3: an ISO media file whose `moov` box holds Canon's `uuid` box, in which CMT1
+// is a TIFF with IFD0 (camera and full image size) and CMT2 one with the EXIF IFD.
+fn read_cr3<R: Read + Seek>(reader: &mut R) -> io::Result<RawInfo> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let not_found = || io::Error::new(io::ErrorKind::InvalidData, "no Canon metadata");
+
+    let (_, moov_start, moov_end) = read_boxes(reader, 0, end)?
+        .into_iter()
+        .find(|(kind, _, _)| kind == b"moov")
+        .ok_or_else(not_found)?;
+
+    let mut canon = None;
+    for (kind, start, end) in read_boxes(reader, moov_start, moov_end)? {
+        let mut uuid = [0u8; 16];
+        reader.seek(SeekFrom::Start(start))?;
+        if &kind == b"uuid" && reader.read_exact(&mut uuid).is_ok() && uuid == CANON_UUID {
+            canon = Some((start + 16, end));
+            break;
+        }
+    }
+    let (canon_start, canon_end) = canon.ok_or_else(not_found)?;
+
+    let mut info = RawInfo::default();
+    for (kind, start, _) in read_boxes(reader, canon_start, canon_end)? {
+        match &kind {
+            b"CMT1" => {
+                let ifd0 = read_tiff_at(reader, start)?;
+                info.make = ifd0.make;
+                info.model = ifd0.model;
+                info.width = ifd0.width.or(info.width);
+                info.height = ifd0.height.or(info.height);
+                info.datetime_original = info.datetime_original.or(ifd0.datetime_original);
+            }
+            b"CMT2" => {
+                let exif = read_tiff_at(reader, start)?;
+                info.datetime_original = exif.datetime_original.or(info.datetime_original);
+                if info.width.is_none() {
+                    info.width = exif.width;
+                    info.height = exif.height;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+// The (type, payload start, payload end) of the boxes between `start` and `end`
+fn read_boxes<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> io::Result<Vec<([u8; 4], u64, u64)>> {
+    let mut boxes = Vec::new();
+    let mut offset = start;
+    while offset + 8 <= end && boxes.len() < MAX_BOXES {
+        let mut header = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        let kind = [header[4], header[5], header[6], header[7]];
+
+        let (size, header_size) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                0 => (end - offset, 8),
+                1 => {
+                    let mut large = [0u8; 8];
+                    reader.read_exact(&mut large)?;
+                    (u64::from_be_bytes(large), 16)
+                }
+                size => (u64::from(size), 8),
+            };
+        if size < header_size || offset + size > end {
+            break;
+        }
+
+        boxes.push((kind, offset + header_size, offset + size));
+        offset += size;
+    }
+    Ok(boxes)
+}
+
+// RAF: a big-endian header pointing to an embedded JPEG (with the EXIF data) and to
+// a table of CFA records holding the sensor size.
+fn read_raf<R: Read + Seek>(reader: &mut R) -> io::Result<RawInfo> {
+    let mut header = [0u8; 0x64];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header)?;
+    let be32 = |at: usize| {
+        u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+    };
+
+    let model = String::from_utf8_lossy(&header[0x1C..0x3C])
+        .trim_matches(char::from(0))
+        .to_string();
+    let mut info = RawInfo {
+        make: Some("FUJIFILM".to_string()),
+        model: (!model.is_empty()).then_some(model),
+        ..Default::default()
+    };
+
+    let records_offset = be32(0x5C);
+    reader.seek(SeekFrom::Start(u64::from(records_offset)))?;
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut count)?;
+    let count = u32::from_be_bytes(count).min(256);
+
+    let mut full_size = None;
+    let mut cropped_size = None;
+    for _ in 0..count {
+        let mut record = [0u8; 4];
+        reader.read_exact(&mut record)?;
+        let tag = u16::from_be_bytes([record[0], record[1]]);
+        let size = u16::from_be_bytes([record[2], record[3]]) as usize;
+        let mut data = vec![0u8; size];
+        reader.read_exact(&mut data)?;
+
+        if size >= 4 {
+            // Sizes are stored as height, width
+            let height = u32::from(u16::from_be_bytes([data[0], data[1]]));
+            let width = u32::from(u16::from_be_bytes([data[2], data[3]]));
+            match tag {
+                0x100 => full_size = Some((width, height)),
+                0x111 => cropped_size = Some((width, height)),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some((width, height)) = cropped_size.or(full_size) {
+        info.width = Some(width);
+        info.height = Some(height);
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Builds a little-endian TIFF: IFD0 with a 160x120 preview, camera tags, a SubIFD
+    // with the full-resolution raw image and an EXIF IFD with DateTimeOriginal.
+    fn nef_like() -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            let mut e = Vec::new();
+            e.extend(tag.to_le_bytes());
+            e.extend(kind.to_le_bytes());
+            e.extend(count.to_le_bytes());
+            e.extend(value.to_le_bytes());
+            e
+        };
+        let ifd = |entries: Vec<Vec<u8>>| {
+            let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+            for e in entries {
+                ifd.extend(e);
+            }
+            ifd.extend(0u32.to_le_bytes());
+            ifd
+        };
+
+        // IFD0 at 8 (7 entries = 90 bytes), strings at 100, SubIFD at 200, EXIF at 300
+        let ifd0 = ifd(vec![
+            entry(TAG_NEW_SUBFILE_TYPE, 4, 1, 1),
+            entry(TAG_IMAGE_WIDTH, 3, 1, 160),
+            entry(TAG_IMAGE_LENGTH, 3, 1, 120),
+            entry(TAG_MAKE, 2, 6, 100),
+            entry(TAG_MODEL, 2, 10, 106),
+            entry(TAG_SUB_IFDS, 4, 1, 200),
+            entry(TAG_EXIF_IFD, 4, 1, 300),
+        ]);
+        data.extend(ifd0);
+        data.resize(100, 0);
+        data.extend(b"NIKON\0NIKON Z 6\0");
+        data.resize(200, 0);
+        data.extend(ifd(vec![
+            entry(TAG_NEW_SUBFILE_TYPE, 4, 1, 0),
+            entry(TAG_IMAGE_WIDTH, 4, 1, 6048),
+            entry(TAG_IMAGE_LENGTH, 4, 1, 4024),
+        ]));
+        data.resize(300, 0);
+        data.extend(ifd(vec![entry(TAG_DATE_TIME_ORIGINAL, 2, 20, 400)]));
+        data.resize(400, 0);
+        data.extend(b"2024:07:12 10:30:00\0");
+        data
+    }
+
+    #[test]
+    fn test_read_tiff() {
+        let info = read_tiff(&mut Cursor::new(nef_like())).unwrap();
+        assert_eq!(info.width, Some(6048));
+        assert_eq!(info.height, Some(4024));
+        assert_eq!(info.make.as_deref(), Some("NIKON"));
+        assert_eq!(info.model.as_deref(), Some("NIKON Z 6"));
+        assert_eq!(
+            info.datetime_original.as_deref(),
+            Some("2024:07:12 10:30:00")
+        );
+    }
+
+    // Wraps a payload in an ISO media box
+    fn iso_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend(kind);
+        data.extend(payload);
+        data
+    }
+
+    #[test]
+    fn test_read_cr3() {
+        // CMT1: IFD0 with camera and full image size
+        let mut cmt1 = b"II*\0".to_vec();
+        cmt1.extend(8u32.to_le_bytes());
+        cmt1.extend(4u16.to_le_bytes());
+        for (tag, kind, count, value) in [
+            (TAG_IMAGE_WIDTH, 3u16, 1u32, 6000u32),
+            (TAG_IMAGE_LENGTH, 3, 1, 4000),
+            (TAG_MAKE, 2, 6, 100),
+            (TAG_MODEL, 2, 13, 106),
+        ] {
+            cmt1.extend(tag.to_le_bytes());
+            cmt1.extend(kind.to_le_bytes());
+            cmt1.extend(count.to_le_bytes());
+            cmt1.extend(value.to_le_bytes());
+        }
+        cmt1.extend(0u32.to_le_bytes());
+        cmt1.resize(100, 0);
+        cmt1.extend(b"Canon\0Canon EOS R6\0");
+
+        // CMT2: the EXIF IFD, big-endian
+        let mut cmt2 = b"MM\0*".to_vec();
+        cmt2.extend(8u32.to_be_bytes());
+        cmt2.extend(1u16.to_be_bytes());
+        cmt2.extend(TAG_DATE_TIME_ORIGINAL.to_be_bytes());
+        cmt2.extend(2u16.to_be_bytes());
+        cmt2.extend(20u32.to_be_bytes());
+        cmt2.extend(26u32.to_be_bytes());
+        cmt2.extend(0u32.to_be_bytes());
+        cmt2.extend(b"2024:07:12 10:30:00\0");
+
+        let mut canon = CANON_UUID.to_vec();
+        canon.extend(iso_box(b"CNCV", b"CanonCR3_001/00.10.00/00.00.00"));
+        canon.extend(iso_box(b"CMT1", &cmt1));
+        canon.extend(iso_box(b"CMT2", &cmt2));
+        let mut moov = iso_box(b"mvhd", &[0; 100]);
+        moov.extend(iso_box(b"uuid", &canon));
+        let mut data = iso_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        data.extend(iso_box(b"moov", &moov));
+        data.extend(iso_box(b"mdat", &[0; 64]));
+
+        let info = read_cr3(&mut Cursor::new(data)).unwrap();
+        assert_eq!((info.width, info.height), (Some(6000), Some(4000)));
+        assert_eq!(info.make.as_deref(), Some("Canon"));
+        assert_eq!(info.model.as_deref(), Some("Canon EOS R6"));
+        assert_eq!(
+            info.datetime_original.as_deref(),
+            Some("2024:07:12 10:30:00")
+        );
+
+        assert!(read_cr3(&mut Cursor::new(iso_box(b"ftyp", b"crx "))).is_err());
+    }
+
+    #[test]
+    fn test_is_dng() {
+        assert!(!is_dng(&mut Cursor::new(nef_like())));
+
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(TAG_DNG_VERSION.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(4u32.to_le_bytes());
+        data.extend([1, 4, 0, 0]);
+        data.extend(0u32.to_le_bytes());
+        assert!(is_dng(&mut Cursor::new(data)));
+    }
+
+    #[test]
+    fn test_read_raf() {
+        let mut data = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        data.resize(0x1C, 0);
+        data.extend(b"X-T4");
+        data.resize(0x5C, 0);
+        data.extend(0x64u32.to_be_bytes());
+        data.resize(0x64, 0);
+        data.extend(2u32.to_be_bytes());
+        for (tag, height, width) in [(0x100u16, 4182u16, 6384u16), (0x111, 4160, 6240)] {
+            data.extend(tag.to_be_bytes());
+            data.extend(4u16.to_be_bytes());
+            data.extend(height.to_be_bytes());
+            data.extend(width.to_be_bytes());
+        }
+
+        let info = read_raf(&mut Cursor::new(data)).unwrap();
+        assert_eq!((info.width, info.height), (Some(6240), Some(4160)));
+        assert_eq!(info.model.as_deref(), Some("X-T4"));
+    }
+
+    #[test]
+    fn test_sniff_header() {
+        let header = |bytes: &[u8]| {
+            let mut header = [0u8; 16];
+            header[..bytes.len()].copy_from_slice(bytes);
+            header
+        };
+
+        assert_eq!(
+            sniff_header(&header(b"FUJIFILMCCD-RAW "), "raf"),
+            Some(RawFormat::Raf)
+        );
+        assert_eq!(
+            sniff_header(&header(b"\0\0\0\x18ftypcrx "), "cr3"),
+            Some(RawFormat::Cr3)
+        );
+        assert_eq!(
+            sniff_header(&header(b"II*\0\x10\0\0\0CR\x02\0"), "tif"),
+            Some(RawFormat::Cr2)
+        );
+        assert_eq!(
+            sniff_header(&header(b"IIRO\x08\0\0\0"), "orf"),
+            Some(RawFormat::Orf)
+        );
+        assert_eq!(
+            sniff_header(&header(b"II*\0\x08\0\0\0"), "nef"),
+            Some(RawFormat::Nef)
+        );
+        assert_eq!(
+            sniff_header(&header(b"MM\0*\0\0\0\x08"), "srw"),
+            Some(RawFormat::Tiff)
+        );
+        assert_eq!(sniff_header(&header(b"II*\0\x08\0\0\0"), "tif"), None);
+        assert_eq!(sniff_header(&header(b"\xFF\xD8\xFF\xE1"), "nef"), None);
+    }
+}
@@ -0,0 +1,339 @@
+// Sidecar Module
+// Finds the files editors and phones write next to a media file (XMP, AAE, THM,
+// Takeout JSON) so that actions carry them along with it.
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{HashMap, HashSet};
+
+use super::takeout;
+
+/// Patterns used when the configuration has no `sidecars:` entry.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    "{name}.xmp",
+    "{original}.xmp",
+    "{name}.aae",
+    "{name}.thm",
+    "{original}.supplemental-metadata.json",
+    "{original}.json",
+];
+
+pub fn default_patterns() -> Vec<String> {
+    DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Base {
+    /// The media file name without extension
+    Name,
+    /// The full media file name
+    Original,
+    /// A Takeout JSON under one of the truncated or numbered names Google uses
+    Takeout,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    base: Base,
+    suffix: String,
+}
+
+/// Compiled `sidecars:` patterns, each `{name}` or `{original}` followed by a
+/// literal suffix matched case-insensitively, e.g. `{name}.xmp`. Any `{original}`
+/// JSON pattern also claims Takeout JSONs with truncated or numbered names.
+#[derive(Debug, Clone, Default)]
+pub struct SidecarPatterns {
+    patterns: Vec<Pattern>,
+}
+
+/// A sidecar found next to a media file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sidecar {
+    pub path: Utf8PathBuf,
+    base: Base,
+    /// The sidecar name after the media name, as spelled on disk
+    suffix: String,
+}
+
+impl SidecarPatterns {
+    pub fn load(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let (base, suffix) = if let Some(suffix) = pattern.strip_prefix("{name}") {
+                    (Base::Name, suffix)
+                } else if let Some(suffix) = pattern.strip_prefix("{original}") {
+                    (Base::Original, suffix)
+                } else {
+                    anyhow::bail!(
+                        "Sidecar pattern '{pattern}' must start with {{name}} or {{original}}"
+                    );
+                };
+                if suffix.is_empty() {
+                    anyhow::bail!("Sidecar pattern '{pattern}' needs a suffix");
+                }
+                Ok(Pattern {
+                    base,
+                    suffix: suffix.to_string(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Sidecars of the media file at `path` that exist on disk.
+    pub fn find(&self, path: &Utf8Path) -> Vec<Sidecar> {
+        self.find_with(path, |candidate| candidate.is_file())
+    }
+
+    /// Gives every sidecar of `files` a single owner, so that it is moved once:
+    /// IMG_0001.CR2 and IMG_0001.JPG both claim IMG_0001.xmp through `{name}.xmp`,
+    /// and the first path in sorted order keeps it. Sidecars that are in `files`
+    /// themselves, such as a `.THM` preview, never own sidecars.
+    pub fn assign(&self, files: &[Utf8PathBuf]) -> HashMap<Utf8PathBuf, Vec<Sidecar>> {
+        self.assign_with(files, |candidate| candidate.is_file())
+    }
+
+    fn assign_with(
+        &self,
+        files: &[Utf8PathBuf],
+        exists: impl Fn(&Utf8Path) -> bool,
+    ) -> HashMap<Utf8PathBuf, Vec<Sidecar>> {
+        let mut found: Vec<(&Utf8PathBuf, Vec<Sidecar>)> = files
+            .iter()
+            .map(|path| (path, self.find_with(path, &exists)))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(b.0));
+
+        let claimed: HashSet<&Utf8Path> = found
+            .iter()
+            .flat_map(|(_, sidecars)| sidecars.iter().map(|sidecar| sidecar.path.as_path()))
+            .collect();
+        let mut owned: HashSet<Utf8PathBuf> = HashSet::new();
+        let mut owners = HashMap::new();
+        for (path, sidecars) in &found {
+            if claimed.contains(path.as_path()) {
+                continue;
+            }
+            let sidecars: Vec<Sidecar> = sidecars
+                .iter()
+                .filter(|sidecar| owned.insert(sidecar.path.clone()))
+                .cloned()
+                .collect();
+            if !sidecars.is_empty() {
+                owners.insert((*path).clone(), sidecars);
+            }
+        }
+        owners
+    }
+
+    // Tries the suffix as configured, lowercase and uppercase, which covers the
+    // spellings cameras and editors use without listing the directory.
+    fn find_with(&self, path: &Utf8Path, exists: impl Fn(&Utf8Path) -> bool) -> Vec<Sidecar> {
+        let directory = path.parent().unwrap_or(Utf8Path::new(""));
+        let mut sidecars: Vec<Sidecar> = Vec::new();
+
+        for pattern in &self.patterns {
+            let base = match pattern.base {
+                Base::Name => path.file_stem(),
+                Base::Original | Base::Takeout => path.file_name(),
+            };
+            let Some(base) = base else {
+                continue;
+            };
+
+            let mut suffixes = vec![
+                pattern.suffix.clone(),
+                pattern.suffix.to_lowercase(),
+                pattern.suffix.to_uppercase(),
+            ];
+            suffixes.dedup();
+            let found = suffixes.into_iter().find_map(|suffix| {
+                let candidate = directory.join(format!("{base}{suffix}"));
+                (candidate != path && exists(&candidate)).then_some((candidate, suffix))
+            });
+
+            if let Some((candidate, suffix)) = found
+                && !sidecars.iter().any(|sidecar| sidecar.path == candidate)
+            {
+                sidecars.push(Sidecar {
+                    path: candidate,
+                    base: pattern.base,
+                    suffix,
+                });
+            }
+        }
+
+        if self.claims_takeout()
+            && let Some(file_name) = path.file_name()
+        {
+            let found = takeout::own_sidecar_names(file_name)
+                .into_iter()
+                .map(|name| directory.join(name))
+                .find(|candidate| candidate != path && exists(candidate));
+            if let Some(candidate) = found
+                && !sidecars.iter().any(|sidecar| sidecar.path == candidate)
+            {
+                sidecars.push(Sidecar {
+                    path: candidate,
+                    base: Base::Takeout,
+                    suffix: String::new(),
+                });
+            }
+        }
+
+        sidecars
+    }
+
+    fn claims_takeout(&self) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern.base == Base::Original && pattern.suffix.to_lowercase().ends_with(".json")
+        })
+    }
+}
+
+impl Sidecar {
+    /// The same sidecar after its media file went to `media_destination`.
+    pub fn relocated(&self, media_destination: &Utf8Path) -> Sidecar {
+        Sidecar {
+            path: self.destination(media_destination),
+            base: self.base,
+            suffix: self.suffix.clone(),
+        }
+    }
+
+    /// Where the sidecar goes when its media file goes to `media_destination`: the
+    /// same directory, renamed along with the media file.
+    pub fn destination(&self, media_destination: &Utf8Path) -> Utf8PathBuf {
+        let base = match self.base {
+            Base::Name => media_destination.file_stem(),
+            Base::Original | Base::Takeout => media_destination.file_name(),
+        }
+        .unwrap_or("");
+        let name = match self.base {
+            Base::Takeout => takeout::sidecar_name(base),
+            _ => format!("{base}{}", self.suffix),
+        };
+        match media_destination.parent() {
+            Some(parent) => parent.join(name),
+            None => Utf8PathBuf::from(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_and_destination() {
+        let patterns = SidecarPatterns::load(&default_patterns()).unwrap();
+        let on_disk = [
+            "/in/IMG_0001.XMP",
+            "/in/IMG_0001.JPG.supplemental-metadata.json",
+            "/in/IMG_0002.xmp",
+        ];
+        let exists = |candidate: &Utf8Path| on_disk.contains(&candidate.as_str());
+
+        let sidecars = patterns.find_with(Utf8Path::new("/in/IMG_0001.JPG"), exists);
+        let paths: Vec<&str> = sidecars.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/in/IMG_0001.XMP",
+                "/in/IMG_0001.JPG.supplemental-metadata.json"
+            ]
+        );
+
+        // Sidecars follow the media file's new name
+        let destination = Utf8Path::new("/out/2024/2024-07-12_IMG_0001.jpg");
+        assert_eq!(
+            sidecars[0].destination(destination),
+            "/out/2024/2024-07-12_IMG_0001.XMP"
+        );
+        assert_eq!(
+            sidecars[1].destination(destination),
+            "/out/2024/2024-07-12_IMG_0001.jpg.supplemental-metadata.json"
+        );
+    }
+
+    #[test]
+    fn test_truncated_takeout_names() {
+        let patterns = SidecarPatterns::load(&default_patterns()).unwrap();
+        let on_disk = [
+            "/in/PXL_20240712_103000123.NIGHT.jpg.supplemental-.json",
+            "/in/IMG_0002.jpg(1).json",
+            "/in/IMG_0003.jpg.json",
+        ];
+        let exists = |candidate: &Utf8Path| on_disk.contains(&candidate.as_str());
+        let paths = |path: &str| -> Vec<Utf8PathBuf> {
+            patterns
+                .find_with(Utf8Path::new(path), exists)
+                .into_iter()
+                .map(|sidecar| sidecar.path)
+                .collect()
+        };
+
+        let sidecars = patterns.find_with(
+            Utf8Path::new("/in/PXL_20240712_103000123.NIGHT.jpg"),
+            exists,
+        );
+        assert_eq!(
+            sidecars[0].path,
+            "/in/PXL_20240712_103000123.NIGHT.jpg.supplemental-.json"
+        );
+        assert_eq!(
+            sidecars[0].destination(Utf8Path::new(
+                "/out/2024-07-12_10-30-00_PXL_20240712_103000123.NIGHT.jpg"
+            )),
+            "/out/2024-07-12_10-30-00_PXL_20240712_103000123.NIG.json"
+        );
+        assert_eq!(
+            sidecars[0].destination(Utf8Path::new("/out/night.jpg")),
+            "/out/night.jpg.json"
+        );
+
+        assert_eq!(paths("/in/IMG_0002(1).jpg"), ["/in/IMG_0002.jpg(1).json"]);
+
+        // An edited copy does not take the original's sidecar
+        assert!(paths("/in/IMG_0003-edited.jpg").is_empty());
+        assert_eq!(paths("/in/IMG_0003.jpg"), ["/in/IMG_0003.jpg.json"]);
+
+        let patterns = SidecarPatterns::load(&["{name}.xmp".to_string()]).unwrap();
+        assert!(
+            patterns
+                .find_with(Utf8Path::new("/in/IMG_0002(1).jpg"), exists)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_assign_single_owner() {
+        let patterns = SidecarPatterns::load(&default_patterns()).unwrap();
+        let on_disk = ["/in/IMG_0001.xmp", "/in/IMG_0001.THM"];
+        let exists = |candidate: &Utf8Path| on_disk.contains(&candidate.as_str());
+        let files: Vec<Utf8PathBuf> = ["/in/IMG_0001.JPG", "/in/IMG_0001.THM", "/in/IMG_0001.CR2"]
+            .map(Utf8PathBuf::from)
+            .to_vec();
+
+        // Both media files claim the XMP; the CR2 comes first and keeps it
+        let owners = patterns.assign_with(&files, exists);
+        assert_eq!(owners.len(), 1);
+        let paths: Vec<&str> = owners[Utf8Path::new("/in/IMG_0001.CR2")]
+            .iter()
+            .map(|s| s.path.as_str())
+            .collect();
+        assert_eq!(paths, ["/in/IMG_0001.xmp", "/in/IMG_0001.THM"]);
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(SidecarPatterns::load(&["*.xmp".to_string()]).is_err());
+        assert!(SidecarPatterns::load(&["{name}".to_string()]).is_err());
+        assert!(SidecarPatterns::load(&[]).unwrap().is_empty());
+    }
+}
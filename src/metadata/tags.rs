@@ -0,0 +1,172 @@
+// Tags Module
+// ExifTool-compatible names and groups for EXIF tags. nom-exif names only the tags
+// it interprets, some differently from ExifTool, and reports the rest by code.
+
+use nom_exif::ExifTag;
+
+/// nom-exif names that ExifTool spells differently. Both names end up in `meta`,
+/// the nom-exif one for configurations written against it.
+const ALIASES: &[(&str, &str)] = &[
+    ("ISOSpeedRatings", "ISO"),
+    ("FlashPixVersion", "FlashpixVersion"),
+    ("WhiteBalanceMode", "WhiteBalance"),
+    ("FocalLengthIn35mmFilm", "FocalLengthIn35mmFormat"),
+];
+
+/// Tags nom-exif has no name for, as (code, ExifTool name, group). Standard EXIF
+/// 2.3+ tags first, then those written by Microsoft, Adobe and other vendors.
+const VENDOR_TAGS: &[(u16, &str, &str)] = &[
+    (0x00fe, "SubfileType", "IFD0"),
+    (0x0102, "BitsPerSample", "IFD0"),
+    (0x0103, "Compression", "IFD0"),
+    (0x0106, "PhotometricInterpretation", "IFD0"),
+    (0x0115, "SamplesPerPixel", "IFD0"),
+    (0x013b, "Artist", "IFD0"),
+    (0x0201, "ThumbnailOffset", "IFD1"),
+    (0x0202, "ThumbnailLength", "IFD1"),
+    (0x8831, "StandardOutputSensitivity", "ExifIFD"),
+    (0x8833, "ISOSpeed", "ExifIFD"),
+    (0x882a, "TimeZoneOffset", "ExifIFD"),
+    (0x9101, "ComponentsConfiguration", "ExifIFD"),
+    (0x9102, "CompressedBitsPerPixel", "ExifIFD"),
+    (0x9290, "SubSecTime", "ExifIFD"),
+    (0x9291, "SubSecTimeOriginal", "ExifIFD"),
+    (0x9400, "AmbientTemperature", "ExifIFD"),
+    (0x9401, "Humidity", "ExifIFD"),
+    (0x9402, "Pressure", "ExifIFD"),
+    (0x9403, "WaterDepth", "ExifIFD"),
+    (0x9404, "Acceleration", "ExifIFD"),
+    (0x9405, "CameraElevationAngle", "ExifIFD"),
+    (0xa005, "InteropOffset", "ExifIFD"),
+    (0xa420, "ImageUniqueID", "ExifIFD"),
+    (0xa430, "OwnerName", "ExifIFD"),
+    (0xa431, "SerialNumber", "ExifIFD"),
+    (0xa435, "LensSerialNumber", "ExifIFD"),
+    (0xa460, "CompositeImage", "ExifIFD"),
+    (0x001f, "GPSHPositioningError", "GPS"),
+    // Microsoft Windows Explorer
+    (0x4746, "Rating", "IFD0"),
+    (0x4749, "RatingPercent", "IFD0"),
+    (0x9c9b, "XPTitle", "IFD0"),
+    (0x9c9c, "XPComment", "IFD0"),
+    (0x9c9d, "XPAuthor", "IFD0"),
+    (0x9c9e, "XPKeywords", "IFD0"),
+    (0x9c9f, "XPSubject", "IFD0"),
+    (0xea1c, "Padding", "IFD0"),
+    (0xea1d, "OffsetSchema", "ExifIFD"),
+    // Adobe: embedded XMP, IPTC and Photoshop blocks, DNG
+    (0x02bc, "ApplicationNotes", "IFD0"),
+    (0x83bb, "IPTC-NAA", "IFD0"),
+    (0x8649, "PhotoshopSettings", "IFD0"),
+    (0x8773, "ICC_Profile", "IFD0"),
+    (0xc612, "DNGVersion", "IFD0"),
+    (0xc613, "DNGBackwardVersion", "IFD0"),
+    (0xc614, "UniqueCameraModel", "IFD0"),
+    (0xc621, "ColorMatrix1", "IFD0"),
+    (0xc62f, "CameraSerialNumber", "IFD0"),
+    (0xc630, "DNGLensInfo", "IFD0"),
+    // Epson PRINT Image Matching
+    (0xc4a5, "PrintIM", "IFD0"),
+];
+
+/// Tags of IFD0 with codes in the range otherwise used by the EXIF IFD.
+const IFD0_HIGH_CODES: &[u16] = &[0x8298, 0x8769, 0x8825];
+
+/// The ExifTool name and group of an EXIF entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagName {
+    pub name: String,
+    /// nom-exif's name where ExifTool uses another one
+    pub alias: Option<&'static str>,
+    /// ExifTool family 1 group: IFD0, ExifIFD, GPS, IFD1...
+    pub group: String,
+}
+
+impl TagName {
+    /// The group-qualified key, such as "GPS:GPSAltitude".
+    pub fn qualified(&self) -> String {
+        format!("{}:{}", self.group, self.name)
+    }
+
+    /// Whether the entry describes the main image rather than a thumbnail.
+    pub fn is_main_image(&self) -> bool {
+        matches!(self.group.as_str(), "IFD0" | "ExifIFD" | "GPS")
+    }
+}
+
+/// Names an entry of IFD `ifd` (0 for the main image and its sub-IFDs, 1 for the
+/// thumbnail) the way ExifTool does.
+pub fn name(tag: Option<ExifTag>, code: u16, ifd: usize) -> TagName {
+    let vendor = VENDOR_TAGS
+        .iter()
+        .find(|(vendor_code, ..)| *vendor_code == code);
+    let (name, alias) = match (tag, vendor) {
+        (Some(tag), _) => {
+            let nom_name = format!("{tag:?}");
+            match ALIASES.iter().find(|(nom, _)| *nom == nom_name) {
+                Some((nom, exiftool)) => (exiftool.to_string(), Some(*nom)),
+                None => (nom_name, None),
+            }
+        }
+        (None, Some((_, name, _))) => (name.to_string(), None),
+        (None, None) => (format!("Tag_{code}"), None),
+    };
+
+    let group = if ifd > 0 {
+        format!("IFD{ifd}")
+    } else if let Some((_, _, group)) = vendor {
+        group.to_string()
+    } else if name.starts_with("GPS") && code <= 0x1f {
+        "GPS".to_string()
+    } else if code < 0x8000 || IFD0_HIGH_CODES.contains(&code) {
+        "IFD0".to_string()
+    } else {
+        "ExifIFD".to_string()
+    };
+
+    TagName { name, alias, group }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exiftool_names() {
+        let iso = name(Some(ExifTag::ISOSpeedRatings), 0x8827, 0);
+        assert_eq!(iso.name, "ISO");
+        assert_eq!(iso.alias, Some("ISOSpeedRatings"));
+        assert_eq!(iso.qualified(), "ExifIFD:ISO");
+
+        let lens = name(Some(ExifTag::LensModel), 0xa434, 0);
+        assert_eq!((lens.name.as_str(), lens.alias), ("LensModel", None));
+        assert_eq!(
+            name(Some(ExifTag::CreateDate), 0x9004, 0).name,
+            "CreateDate"
+        );
+    }
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(
+            name(Some(ExifTag::GPSAltitude), 0x0006, 0).qualified(),
+            "GPS:GPSAltitude"
+        );
+        assert_eq!(name(Some(ExifTag::Make), 0x010f, 0).group, "IFD0");
+        assert_eq!(name(Some(ExifTag::Copyright), 0x8298, 0).group, "IFD0");
+        assert_eq!(name(Some(ExifTag::FNumber), 0x829d, 0).group, "ExifIFD");
+
+        let thumbnail = name(Some(ExifTag::ImageWidth), 0x0100, 1);
+        assert_eq!(thumbnail.qualified(), "IFD1:ImageWidth");
+        assert!(!thumbnail.is_main_image());
+    }
+
+    #[test]
+    fn test_vendor_tags() {
+        let serial = name(None, 0xa431, 0);
+        assert_eq!(serial.qualified(), "ExifIFD:SerialNumber");
+        assert_eq!(name(None, 0x9c9e, 0).qualified(), "IFD0:XPKeywords");
+        assert_eq!(name(None, 0x0201, 1).qualified(), "IFD1:ThumbnailOffset");
+        assert_eq!(name(None, 0xbeef, 0).qualified(), "ExifIFD:Tag_48879");
+    }
+}
@@ -0,0 +1,269 @@
+// Takeout Module
+// Reads the JSON sidecars Google Photos Takeout writes next to each exported file.
+// Takeout strips EXIF dates and GPS from many files, so these sidecars are often
+// the only record of when and where a photo was taken.
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fs;
+
+/// Takeout truncates sidecar names, without the ".json" extension, to this many
+/// characters.
+const MAX_NAME_CHARS: usize = 46;
+
+const SUPPLEMENTAL: &str = ".supplemental-metadata";
+
+/// Suffixes Google Photos adds to edited copies, which share the original's sidecar.
+const EDITED_SUFFIXES: &[&str] = &["-edited", "-bearbeitet", "-modifié", "-editado"];
+
+/// What a Takeout sidecar says about its media file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TakeoutData {
+    /// Path of the JSON sidecar the data came from
+    pub json: Utf8PathBuf,
+    pub title: String,
+    pub description: String,
+    pub people: Vec<String>,
+    pub url: String,
+    pub favorited: bool,
+    pub taken: Option<DateTime<Utc>>,
+    /// Latitude, longitude and altitude, when the location is known
+    pub position: Option<(f64, f64, f64)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sidecar {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    people: Vec<Person>,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    favorited: bool,
+    photo_taken_time: Option<Timestamp>,
+    geo_data: Option<GeoData>,
+    geo_data_exif: Option<GeoData>,
+}
+
+#[derive(Deserialize)]
+struct Person {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Timestamp {
+    /// Unix seconds, as a string
+    timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct GeoData {
+    latitude: f64,
+    longitude: f64,
+    #[serde(default)]
+    altitude: f64,
+}
+
+impl GeoData {
+    // Takeout writes zeros when it has no location
+    fn position(&self) -> Option<(f64, f64, f64)> {
+        (self.latitude != 0.0 || self.longitude != 0.0).then_some((
+            self.latitude,
+            self.longitude,
+            self.altitude,
+        ))
+    }
+}
+
+/// Finds and parses the Takeout sidecar of the media file at `path`.
+pub fn read_sidecar(path: &Utf8Path) -> Option<TakeoutData> {
+    let json = find_sidecar(path)?;
+    match parse(&json) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            eprintln!("⚠️  Failed to read Takeout sidecar {json}: {e}");
+            None
+        }
+    }
+}
+
+pub fn find_sidecar(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let directory = path.parent().unwrap_or(Utf8Path::new(""));
+    candidate_names(path.file_name()?)
+        .into_iter()
+        .map(|name| directory.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Sidecar names Takeout may have used for a media file, most specific first.
+///
+/// A duplicate `IMG_1(1).jpg` has its sidecar at `IMG_1.jpg(1).json`, an edited
+/// copy `IMG_1-edited.jpg` shares `IMG_1.jpg.json`, and every name is cut to 46
+/// characters before ".json" is appended.
+fn candidate_names(file_name: &str) -> Vec<String> {
+    let (stem, extension, counter) = split_name(file_name);
+
+    let mut stems = vec![stem];
+    let lowercase = stem.to_lowercase();
+    for suffix in EDITED_SUFFIXES {
+        if lowercase.ends_with(suffix) {
+            stems.push(&stem[..stem.len() - suffix.len()]);
+        }
+    }
+
+    let mut names = Vec::new();
+    for stem in stems {
+        for name in names_for(stem, extension, counter) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Sidecar names Takeout may have used for the media file itself, leaving out the
+/// sidecar an edited copy shares with its original.
+pub(crate) fn own_sidecar_names(file_name: &str) -> Vec<String> {
+    let (stem, extension, counter) = split_name(file_name);
+    names_for(stem, extension, counter)
+}
+
+/// The name Takeout gives the sidecar of `file_name`, which `find_sidecar` finds
+/// again.
+pub(crate) fn sidecar_name(file_name: &str) -> String {
+    let (stem, extension, counter) = split_name(file_name);
+    format!("{}{counter}.json", truncate(&format!("{stem}{extension}")))
+}
+
+// Splits a file name into stem, extension with its dot, and duplicate counter
+fn split_name(file_name: &str) -> (&str, &str, &str) {
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(dot) if dot > 0 => file_name.split_at(dot),
+        _ => (file_name, ""),
+    };
+
+    match stem.strip_suffix(')').and_then(|s| s.rsplit_once('(')) {
+        Some((base, digits))
+            if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            (base, extension, &stem[base.len()..])
+        }
+        _ => (stem, extension, ""),
+    }
+}
+
+fn names_for(stem: &str, extension: &str, counter: &str) -> Vec<String> {
+    let original = format!("{stem}{extension}");
+    let mut names = Vec::new();
+    for base in [
+        format!("{original}{SUPPLEMENTAL}"),
+        original,
+        stem.to_string(),
+    ] {
+        let name = format!("{}{counter}.json", truncate(&base));
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn truncate(name: &str) -> String {
+    name.chars().take(MAX_NAME_CHARS).collect()
+}
+
+fn parse(json: &Utf8Path) -> Result<TakeoutData> {
+    let content = fs::read_to_string(json)?;
+    let sidecar: Sidecar = serde_json::from_str(&content).context("Invalid Takeout JSON")?;
+    Ok(from_sidecar(json, sidecar))
+}
+
+fn from_sidecar(json: &Utf8Path, sidecar: Sidecar) -> TakeoutData {
+    let taken = sidecar
+        .photo_taken_time
+        .and_then(|time| time.timestamp.parse::<i64>().ok())
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+    let position = [sidecar.geo_data, sidecar.geo_data_exif]
+        .iter()
+        .flatten()
+        .find_map(GeoData::position);
+
+    TakeoutData {
+        json: json.to_path_buf(),
+        title: sidecar.title,
+        description: sidecar.description,
+        people: sidecar.people.into_iter().map(|p| p.name).collect(),
+        url: sidecar.url,
+        favorited: sidecar.favorited,
+        taken,
+        position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_names() {
+        assert_eq!(
+            candidate_names("IMG_0001.jpg"),
+            [
+                "IMG_0001.jpg.supplemental-metadata.json",
+                "IMG_0001.jpg.json",
+                "IMG_0001.json"
+            ]
+        );
+        assert_eq!(
+            candidate_names("IMG_0001(2).jpg")[..2],
+            [
+                "IMG_0001.jpg.supplemental-metadata(2).json",
+                "IMG_0001.jpg(2).json"
+            ]
+        );
+        assert!(candidate_names("IMG_0001-edited.jpg").contains(&"IMG_0001.jpg.json".to_string()));
+
+        let long = "PXL_20240712_103000123.NIGHT.PORTRAIT-01.COVER.jpg";
+        assert_eq!(
+            candidate_names(long),
+            ["PXL_20240712_103000123.NIGHT.PORTRAIT-01.COVER.json"]
+        );
+        assert_eq!(
+            candidate_names("IMG_20240712_103000123.jpg")[0],
+            "IMG_20240712_103000123.jpg.supplemental-metada.json"
+        );
+    }
+
+    #[test]
+    fn test_from_sidecar() {
+        let sidecar: Sidecar = serde_json::from_str(
+            r#"{
+                "title": "IMG_0001.jpg",
+                "description": "Sunset",
+                "photoTakenTime": {"timestamp": "1720780200", "formatted": "Jul 12, 2024"},
+                "geoData": {"latitude": 0.0, "longitude": 0.0, "altitude": 0.0},
+                "geoDataExif": {"latitude": 38.72, "longitude": -9.14, "altitude": 50.0},
+                "people": [{"name": "Ana"}, {"name": "Rui"}],
+                "favorited": true
+            }"#,
+        )
+        .unwrap();
+        let data = from_sidecar(Utf8Path::new("/in/IMG_0001.jpg.json"), sidecar);
+
+        assert_eq!(
+            data.taken.unwrap().to_rfc3339(),
+            "2024-07-12T10:30:00+00:00"
+        );
+        assert_eq!(data.position, Some((38.72, -9.14, 50.0)));
+        assert_eq!(data.people, ["Ana", "Rui"]);
+        assert_eq!(data.description, "Sunset");
+        assert!(data.favorited);
+    }
+}
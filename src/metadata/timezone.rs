@@ -0,0 +1,102 @@
+// Timezone Module
+// Offline IANA time zone lookup from coordinates, used to turn camera wall-clock
+// times into real instants and to express capture times in local time.
+
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+
+use super::context::TimeContext;
+use super::extractor::set_local_time;
+
+lazy_static::lazy_static! {
+    static ref FINDER: DefaultFinder = DefaultFinder::new();
+}
+
+/// Returns the IANA time zone name at the given coordinates.
+pub fn timezone_at(latitude: f64, longitude: f64) -> Option<String> {
+    let name = FINDER.get_tz_name(longitude, latitude);
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Expresses the capture time in the given time zone. Naive wall-clock times are
+/// taken as local times there; real instants are converted to its wall clock.
+pub fn localize_time(time: &mut TimeContext, timezone: &str) {
+    let (Some(timestamp), Ok(tz)) = (time.timestamp, timezone.parse::<Tz>()) else {
+        return;
+    };
+
+    if time.naive {
+        // Times inside a DST gap do not exist locally and stay naive
+        if let Some(local) = tz.from_local_datetime(&timestamp.naive_utc()).earliest() {
+            set_local_time(time, local);
+        }
+    } else {
+        set_local_time(time, timestamp.with_timezone(&tz));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn time_at(hour: u32, naive: bool) -> TimeContext {
+        let dt = Utc.with_ymd_and_hms(2024, 7, 12, hour, 0, 0).unwrap();
+        let mut time = TimeContext::default();
+        set_local_time(&mut time, dt);
+        time.naive = naive;
+        time
+    }
+
+    #[test]
+    fn test_timezone_at() {
+        assert_eq!(
+            timezone_at(40.4168, -3.7038).as_deref(),
+            Some("Europe/Madrid")
+        );
+        assert_eq!(
+            timezone_at(35.6762, 139.6503).as_deref(),
+            Some("Asia/Tokyo")
+        );
+    }
+
+    #[test]
+    fn test_localize_naive_time() {
+        let mut time = time_at(12, true);
+        localize_time(&mut time, "Europe/Madrid");
+
+        // Wall clock is kept, the instant moves to UTC
+        assert_eq!(time.hh, "12");
+        assert_eq!(time.timestamp.unwrap().format("%H").to_string(), "10");
+        assert!(!time.naive);
+
+        // Localizing twice is harmless
+        localize_time(&mut time, "Europe/Madrid");
+        assert_eq!(time.hh, "12");
+        assert_eq!(time.timestamp.unwrap().format("%H").to_string(), "10");
+    }
+
+    #[test]
+    fn test_localize_instant() {
+        let mut time = time_at(23, false);
+        localize_time(&mut time, "Asia/Tokyo");
+
+        assert_eq!(time.dd, "13");
+        assert_eq!(time.hh, "08");
+        assert_eq!(time.weekday, "Saturday");
+        assert_eq!(time.utc_offset_secs, 9 * 3600);
+    }
+
+    #[test]
+    fn test_localize_unknown_timezone() {
+        let mut time = time_at(12, true);
+        localize_time(&mut time, "");
+        assert!(time.naive);
+        assert_eq!(time.hh, "12");
+    }
+}
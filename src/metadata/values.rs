@@ -0,0 +1,450 @@
+// Values Module
+// Typed values exposed to rule conditions alongside the plain context fields.
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use nom_exif::EntryValue;
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::context::TimeContext;
+
+/// A point in time as seen on a wall clock, with the UTC offset when it is known.
+///
+/// Two datetimes with offsets compare as instants. When either one has no offset,
+/// e.g. `date("2024-07-01")` or a camera time without GPS, their wall clocks are
+/// compared instead, which is what "taken on July 1st" means to a person.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Datetime {
+    local: NaiveDateTime,
+    /// Seconds east of UTC
+    offset: Option<i32>,
+}
+
+impl Datetime {
+    /// The capture time of a file, if it has one.
+    pub fn from_time(time: &TimeContext) -> Option<Self> {
+        let timestamp = time.timestamp?;
+        if time.naive {
+            return Some(Self {
+                local: timestamp.naive_utc(),
+                offset: None,
+            });
+        }
+
+        let offset = FixedOffset::east_opt(time.utc_offset_secs)?;
+        Some(Self {
+            local: timestamp.with_timezone(&offset).naive_local(),
+            offset: Some(time.utc_offset_secs),
+        })
+    }
+
+    /// Parses "2024-07-01", "2024-07-01 18:30[:00]" (also with a "T" or EXIF-style
+    /// colons in the date) and RFC 3339 times with an offset.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+            return Some(dt.into());
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Some(Self::naive(date.and_time(NaiveTime::MIN)));
+        }
+
+        [
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%d %H:%M",
+            "%Y-%m-%dT%H:%M",
+            "%Y:%m:%d %H:%M:%S",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(Self::naive)
+    }
+
+    /// The current local time.
+    pub fn now() -> Self {
+        Local::now().fixed_offset().into()
+    }
+
+    pub fn naive(local: NaiveDateTime) -> Self {
+        Self {
+            local,
+            offset: None,
+        }
+    }
+
+    pub fn local(&self) -> NaiveDateTime {
+        self.local
+    }
+
+    /// Seconds east of UTC, when known.
+    pub fn offset(&self) -> Option<i32> {
+        self.offset
+    }
+
+    /// Seconds since the Unix epoch. Times without offset are taken as UTC.
+    pub fn timestamp(&self) -> i64 {
+        self.local.and_utc().timestamp() - i64::from(self.offset.unwrap_or(0))
+    }
+
+    /// Midnight of the same day, keeping the offset.
+    pub fn date(&self) -> Self {
+        Self {
+            local: self.local.date().and_time(NaiveTime::MIN),
+            offset: self.offset,
+        }
+    }
+
+    /// Whole days elapsed from this time until `now`.
+    pub fn days_until(&self, now: &Self) -> i64 {
+        now.elapsed_since(self).num_days()
+    }
+
+    /// Whether this time lies within `[start, end]`.
+    pub fn between(&self, start: &Self, end: &Self) -> bool {
+        self >= start && self <= end
+    }
+
+    fn elapsed_since(&self, earlier: &Self) -> Duration {
+        match (self.offset, earlier.offset) {
+            (Some(_), Some(_)) => Duration::seconds(self.timestamp() - earlier.timestamp()),
+            _ => self.local - earlier.local,
+        }
+    }
+}
+
+impl From<DateTime<FixedOffset>> for Datetime {
+    fn from(dt: DateTime<FixedOffset>) -> Self {
+        Self {
+            local: dt.naive_local(),
+            offset: Some(dt.offset().local_minus_utc()),
+        }
+    }
+}
+
+impl PartialEq for Datetime {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Datetime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.elapsed_since(other).cmp(&Duration::zero()))
+    }
+}
+
+impl fmt::Display for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.local.format("%Y-%m-%dT%H:%M:%S"))?;
+        match self.offset.and_then(FixedOffset::east_opt) {
+            Some(offset) => write!(f, "{offset}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An EXIF rational such as an exposure time of 1/125 s, kept exact.
+///
+/// It compares with plain numbers by its value, so `meta.ExposureTime <= 0.008`
+/// works, and displays the way photographers write it: "1/125", "2.8" or "30".
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Rational {
+    pub num: i64,
+    pub den: i64,
+}
+
+impl Rational {
+    pub fn new(num: i64, den: i64) -> Self {
+        Self { num, den }
+    }
+
+    /// The value as a float, NaN for a zero denominator.
+    pub fn value(&self) -> f64 {
+        if self.den == 0 {
+            f64::NAN
+        } else {
+            self.num as f64 / self.den as f64
+        }
+    }
+
+    /// Parses "1/125", "2.8" or "30".
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some((num, den)) = value.split_once('/') {
+            return Some(Self::new(
+                num.trim().parse().ok()?,
+                den.trim().parse().ok()?,
+            ));
+        }
+        if let Ok(num) = value.parse() {
+            return Some(Self::new(num, 1));
+        }
+        let float: f64 = value.parse().ok()?;
+        float.is_finite().then(|| Self::from(float))
+    }
+
+    fn reduced(self) -> Self {
+        let (mut a, mut b) = (self.num.unsigned_abs(), self.den.unsigned_abs());
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        if a <= 1 {
+            return self;
+        }
+        let sign = if self.den < 0 { -1 } else { 1 };
+        Self::new(sign * self.num / a as i64, sign * self.den / a as i64)
+    }
+}
+
+impl From<f64> for Rational {
+    fn from(value: f64) -> Self {
+        Self::new((value * 10_000.0).round() as i64, 10_000).reduced()
+    }
+}
+
+impl PartialEq for Rational {
+    fn eq(&self, other: &Self) -> bool {
+        self.value() == other.value()
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value().partial_cmp(&other.value())
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 0 {
+            return write!(f, "{}/{}", self.num, self.den);
+        }
+        let reduced = self.reduced();
+        if reduced.den == 1 {
+            write!(f, "{}", reduced.num)
+        } else if reduced.num.abs() == 1 {
+            // Fractions of a second, as on the camera's display
+            write!(f, "{}/{}", reduced.num, reduced.den)
+        } else {
+            let text = format!("{:.4}", reduced.value());
+            write!(f, "{}", text.trim_end_matches('0').trim_end_matches('.'))
+        }
+    }
+}
+
+/// Converts an EXIF value to the type rule conditions see: integers and floats,
+/// `Rational`s, `Datetime`s, strings, arrays for multi-value tags and blobs for
+/// binary data.
+pub fn exif_value(value: &EntryValue) -> Dynamic {
+    fn array<T: Copy + Into<i64>>(values: &[T]) -> Dynamic {
+        let items: rhai::Array = values
+            .iter()
+            .map(|v| Dynamic::from_int((*v).into()))
+            .collect();
+        Dynamic::from_array(items)
+    }
+
+    match value {
+        EntryValue::Text(text) => Dynamic::from(text.clone()),
+        EntryValue::URational(r) => Dynamic::from(Rational::new(r.0.into(), r.1.into())),
+        EntryValue::IRational(r) => Dynamic::from(Rational::new(r.0.into(), r.1.into())),
+        EntryValue::U8(v) => Dynamic::from_int((*v).into()),
+        EntryValue::U16(v) => Dynamic::from_int((*v).into()),
+        EntryValue::U32(v) => Dynamic::from_int((*v).into()),
+        EntryValue::U64(v) => Dynamic::from_int(*v as i64),
+        EntryValue::I8(v) => Dynamic::from_int((*v).into()),
+        EntryValue::I16(v) => Dynamic::from_int((*v).into()),
+        EntryValue::I32(v) => Dynamic::from_int((*v).into()),
+        EntryValue::I64(v) => Dynamic::from_int(*v),
+        EntryValue::F32(v) => Dynamic::from_float((*v).into()),
+        EntryValue::F64(v) => Dynamic::from_float(*v),
+        EntryValue::Time(dt) => Dynamic::from(Datetime::from(*dt)),
+        EntryValue::NaiveDateTime(dt) => Dynamic::from(Datetime::naive(*dt)),
+        EntryValue::Undefined(bytes) => {
+            // Versions such as ExifVersion "0232" are ASCII stored as UNDEFINED
+            match std::str::from_utf8(bytes) {
+                Ok(text) if !text.is_empty() && text.chars().all(|c| c.is_ascii_graphic()) => {
+                    Dynamic::from(text.to_string())
+                }
+                _ => Dynamic::from_blob(bytes.clone()),
+            }
+        }
+        EntryValue::URationalArray(values) => Dynamic::from_array(
+            values
+                .iter()
+                .map(|r| Dynamic::from(Rational::new(r.0.into(), r.1.into())))
+                .collect(),
+        ),
+        EntryValue::IRationalArray(values) => Dynamic::from_array(
+            values
+                .iter()
+                .map(|r| Dynamic::from(Rational::new(r.0.into(), r.1.into())))
+                .collect(),
+        ),
+        EntryValue::U8Array(values) => array(values),
+        EntryValue::U16Array(values) => array(values),
+        EntryValue::U32Array(values) => array(values),
+        _ => Dynamic::from(value.to_string()),
+    }
+}
+
+/// Renders a value of `meta` for templates: rationals as "1/125", dates as
+/// "2024-07-01T18:30:00+02:00", arrays comma-separated.
+pub fn display_value(value: &Dynamic) -> String {
+    if value.is_string() {
+        value.clone().into_string().unwrap_or_default()
+    } else if value.is_int() {
+        value.as_int().unwrap_or(0).to_string()
+    } else if value.is_float() {
+        let f = value.as_float().unwrap_or(0.0);
+        // Format floats nicely, removing unnecessary decimals
+        if f.fract() == 0.0 {
+            format!("{f:.0}")
+        } else {
+            f.to_string()
+        }
+    } else if value.is_bool() {
+        value.as_bool().unwrap_or(false).to_string()
+    } else if let Some(rational) = value.read_lock::<Rational>() {
+        rational.to_string()
+    } else if let Some(datetime) = value.read_lock::<Datetime>() {
+        datetime.to_string()
+    } else if value.is_array() {
+        let items = value.read_lock::<rhai::Array>().map(|items| {
+            items
+                .iter()
+                .map(display_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        items.unwrap_or_default()
+    } else {
+        // Fallback for other types
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Datetime {
+        Datetime::parse(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("2024-07-01").to_string(), "2024-07-01T00:00:00");
+        assert_eq!(parse("2024-07-01 18:30").to_string(), "2024-07-01T18:30:00");
+        assert_eq!(
+            parse("2024:07:01 18:30:15").to_string(),
+            "2024-07-01T18:30:15"
+        );
+        assert_eq!(
+            parse("2024-07-01T18:30:00+02:00").to_string(),
+            "2024-07-01T18:30:00+02:00"
+        );
+        assert_eq!(parse("2024-07-01T18:30:00Z").timestamp(), 1719858600);
+        assert!(Datetime::parse("July 1st").is_none());
+    }
+
+    #[test]
+    fn test_compare() {
+        // Instants compare as instants
+        assert!(parse("2024-07-01T18:30:00+02:00") == parse("2024-07-01T16:30:00Z"));
+        assert!(parse("2024-07-01T18:30:00+02:00") < parse("2024-07-01T17:00:00Z"));
+
+        // Without an offset on either side, wall clocks are compared
+        let evening = parse("2024-07-01T23:30:00-05:00");
+        assert!(evening > parse("2024-07-01"));
+        assert!(evening < parse("2024-07-02"));
+        assert!(evening.between(&parse("2024-07-01"), &parse("2024-07-01 23:59:59")));
+        assert!(evening.date() == parse("2024-07-01"));
+    }
+
+    #[test]
+    fn test_from_time() {
+        let mut time = TimeContext {
+            timestamp: Some(
+                DateTime::parse_from_rfc3339("2024-07-12T10:00:00Z")
+                    .unwrap()
+                    .to_utc(),
+            ),
+            utc_offset_secs: 7200,
+            ..Default::default()
+        };
+        let datetime = Datetime::from_time(&time).unwrap();
+        assert_eq!(datetime.to_string(), "2024-07-12T12:00:00+02:00");
+        assert_eq!(datetime.timestamp(), 1720778400);
+
+        time.naive = true;
+        let datetime = Datetime::from_time(&time).unwrap();
+        assert_eq!(datetime.to_string(), "2024-07-12T10:00:00");
+
+        assert!(Datetime::from_time(&TimeContext::default()).is_none());
+    }
+
+    #[test]
+    fn test_days_until() {
+        let start = parse("2024-07-01");
+        assert_eq!(start.days_until(&parse("2024-07-31 12:00")), 30);
+        assert_eq!(start.days_until(&parse("2024-06-30")), -1);
+    }
+
+    #[test]
+    fn test_rational_display() {
+        assert_eq!(Rational::new(1, 125).to_string(), "1/125");
+        assert_eq!(Rational::new(10, 1250).to_string(), "1/125");
+        assert_eq!(Rational::new(28, 10).to_string(), "2.8");
+        assert_eq!(Rational::new(30, 1).to_string(), "30");
+        assert_eq!(Rational::new(10, 3).to_string(), "3.3333");
+        assert_eq!(Rational::new(-1, 3).to_string(), "-1/3");
+        assert_eq!(Rational::new(1, 0).to_string(), "1/0");
+    }
+
+    #[test]
+    fn test_rational_parse() {
+        assert_eq!(Rational::parse("1/125"), Some(Rational::new(1, 125)));
+        assert_eq!(Rational::parse("2.8").unwrap().to_string(), "2.8");
+        assert_eq!(Rational::parse("30"), Some(Rational::new(30, 1)));
+        assert!(Rational::parse("fast").is_none());
+        assert!(Rational::new(1, 125) < Rational::new(1, 60));
+        assert!(Rational::new(2, 250) == Rational::new(1, 125));
+    }
+
+    #[test]
+    fn test_exif_value() {
+        use nom_exif::{IRational, URational};
+
+        let exposure = exif_value(&EntryValue::URational(URational::from((1, 125))));
+        assert_eq!(display_value(&exposure), "1/125");
+        assert_eq!(exposure.cast::<Rational>().value(), 0.008);
+
+        let bias = exif_value(&EntryValue::IRational(IRational::from((-2, 3))));
+        assert_eq!(bias.cast::<Rational>().num, -2);
+
+        let taken = DateTime::parse_from_rfc3339("2024-07-01T18:30:00+02:00").unwrap();
+        let taken = exif_value(&EntryValue::Time(taken));
+        assert_eq!(display_value(&taken), "2024-07-01T18:30:00+02:00");
+        assert!(taken.is::<Datetime>());
+
+        let lens = exif_value(&EntryValue::URationalArray(vec![
+            URational::from((24, 1)),
+            URational::from((70, 1)),
+            URational::from((28, 10)),
+            URational::from((28, 10)),
+        ]));
+        assert!(lens.is_array());
+        assert_eq!(display_value(&lens), "24, 70, 2.8, 2.8");
+
+        let bits = exif_value(&EntryValue::U16Array(vec![8, 8, 8]));
+        assert_eq!(bits.into_array().unwrap().len(), 3);
+
+        let version = exif_value(&EntryValue::Undefined(b"0232".to_vec()));
+        assert_eq!(display_value(&version), "0232");
+        assert!(exif_value(&EntryValue::Undefined(vec![0, 1, 2])).is_blob());
+    }
+}
@@ -0,0 +1,275 @@
+// XMP Module
+// Reads the ratings, labels, titles, keywords and region names that Lightroom,
+// darktable and digiKam store as XMP, either embedded in the file (JPEG, HEIC, TIFF
+// and TIFF-based RAW all carry the packet as plain XML) or in a `.xmp` sidecar.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use regex::Regex;
+use std::fs::File;
+use std::io::Read;
+
+use super::context::XmpContext;
+
+/// Embedded packets sit near the start of the file; only this much is searched.
+const MAX_SEARCH_BYTES: u64 = 16 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref PACKET: regex::bytes::Regex =
+        regex::bytes::Regex::new(r"(?s)<x:xmpmeta.*?</x:xmpmeta>").unwrap();
+    static ref LIST_ITEM: Regex = Regex::new(r"(?s)<rdf:li(?:\s[^>]*)?>(.*?)</rdf:li>").unwrap();
+    static ref RATING: Property = Property::new("xmp:Rating");
+    static ref LABEL: Property = Property::new("xmp:Label");
+    static ref TITLE: Property = Property::new("dc:title");
+    static ref SUBJECT: Property = Property::new("dc:subject");
+    static ref REGION_NAME: Property = Property::new("mwg-rs:Name");
+}
+
+/// An XMP property, written either as an attribute of `rdf:Description` or as an
+/// element.
+struct Property {
+    attribute: Regex,
+    element: Regex,
+}
+
+impl Property {
+    fn new(name: &str) -> Self {
+        let name = regex::escape(name);
+        Self {
+            attribute: Regex::new(&format!(r#"\s{name}\s*=\s*(?:"([^"]*)"|'([^']*)')"#)).unwrap(),
+            element: Regex::new(&format!(r"(?s)<{name}(?:\s[^>]*)?>(.*?)</{name}>")).unwrap(),
+        }
+    }
+
+    /// Every value of the property, with `rdf:Bag`/`Seq`/`Alt` items flattened.
+    fn values(&self, xml: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        for caps in self.attribute.captures_iter(xml) {
+            if let Some(value) = caps.get(1).or(caps.get(2)) {
+                values.push(decode(value.as_str()));
+            }
+        }
+        for caps in self.element.captures_iter(xml) {
+            let content = &caps[1];
+            if content.contains("<rdf:li") {
+                values.extend(LIST_ITEM.captures_iter(content).map(|li| decode(&li[1])));
+            } else {
+                values.push(decode(content));
+            }
+        }
+        values.retain(|value| !value.is_empty());
+        values
+    }
+
+    fn first(&self, xml: &str) -> Option<String> {
+        self.values(xml).into_iter().next()
+    }
+}
+
+fn decode(text: &str) -> String {
+    let text = text.trim();
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse::<u32>))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Parses the fields of interest from an XMP packet.
+pub fn parse(xml: &str) -> XmpContext {
+    XmpContext {
+        rating: RATING.first(xml).and_then(|rating| {
+            // Some writers store ratings as decimals, e.g. "4.0"
+            rating.parse::<f64>().ok().map(|rating| rating as i64)
+        }),
+        label: LABEL.first(xml).unwrap_or_default(),
+        title: TITLE.first(xml).unwrap_or_default(),
+        keywords: SUBJECT.values(xml),
+        regions: REGION_NAME.values(xml),
+        source: String::new(),
+    }
+}
+
+/// XMP of the media file at `path`. Sidecar values take precedence over embedded
+/// ones, as editors write their changes to the sidecar.
+pub fn read(path: &Utf8Path) -> XmpContext {
+    let embedded = read_embedded(path).map(|xml| parse(&xml));
+    let sidecar = sidecar_path(path)
+        .and_then(|sidecar| std::fs::read(sidecar.as_std_path()).ok())
+        .map(|data| parse(&String::from_utf8_lossy(&data)));
+
+    match (embedded, sidecar) {
+        (Some(embedded), Some(sidecar)) => XmpContext {
+            rating: sidecar.rating.or(embedded.rating),
+            label: or_else(sidecar.label, embedded.label),
+            title: or_else(sidecar.title, embedded.title),
+            keywords: or_else_list(sidecar.keywords, embedded.keywords),
+            regions: or_else_list(sidecar.regions, embedded.regions),
+            source: "sidecar".to_string(),
+        },
+        (None, Some(sidecar)) => XmpContext {
+            source: "sidecar".to_string(),
+            ..sidecar
+        },
+        (Some(embedded), None) => XmpContext {
+            source: "embedded".to_string(),
+            ..embedded
+        },
+        (None, None) => XmpContext::default(),
+    }
+}
+
+fn or_else(preferred: String, fallback: String) -> String {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+fn or_else_list(preferred: Vec<String>, fallback: Vec<String>) -> Vec<String> {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+fn read_embedded(path: &Utf8Path) -> Option<String> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xmp"))
+    {
+        return None;
+    }
+    let mut data = Vec::new();
+    File::open(path.as_std_path())
+        .ok()?
+        .take(MAX_SEARCH_BYTES)
+        .read_to_end(&mut data)
+        .ok()?;
+    PACKET
+        .find(&data)
+        .map(|packet| String::from_utf8_lossy(packet.as_bytes()).into_owned())
+}
+
+/// `IMG_0001.xmp` (Lightroom, digiKam) or `IMG_0001.CR3.xmp` (darktable).
+fn sidecar_path(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let name = path.file_name()?;
+    let stem = path.file_stem()?;
+    [stem, name]
+        .iter()
+        .flat_map(|base| [format!("{base}.xmp"), format!("{base}.XMP")])
+        .map(|sidecar| path.with_file_name(sidecar))
+        .find(|sidecar| sidecar.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIGHTROOM: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmp:Rating="4"
+    xmp:Label="Red">
+   <dc:title>
+    <rdf:Alt>
+     <rdf:li xml:lang="x-default">Sunset &amp; Sea</rdf:li>
+    </rdf:Alt>
+   </dc:title>
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>beach</rdf:li>
+     <rdf:li>Lisbon</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <mwg-rs:Regions rdf:parseType="Resource">
+    <mwg-rs:RegionList>
+     <rdf:Bag>
+      <rdf:li><rdf:Description mwg-rs:Name="Ana" mwg-rs:Type="Face"/></rdf:li>
+      <rdf:li><rdf:Description><mwg-rs:Name>Rui</mwg-rs:Name></rdf:Description></rdf:li>
+     </rdf:Bag>
+    </mwg-rs:RegionList>
+   </mwg-rs:Regions>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn test_parse() {
+        let xmp = parse(LIGHTROOM);
+        assert_eq!(xmp.rating, Some(4));
+        assert_eq!(xmp.label, "Red");
+        assert_eq!(xmp.title, "Sunset & Sea");
+        assert_eq!(xmp.keywords, ["beach", "Lisbon"]);
+        assert_eq!(xmp.regions, ["Ana", "Rui"]);
+    }
+
+    #[test]
+    fn test_parse_elements() {
+        // digiKam writes simple properties as elements
+        let xml = "<x:xmpmeta><rdf:Description><xmp:Rating>5</xmp:Rating>\
+                   <xmp:Label>Select</xmp:Label><dc:subject/></rdf:Description></x:xmpmeta>";
+        let xmp = parse(xml);
+        assert_eq!(xmp.rating, Some(5));
+        assert_eq!(xmp.label, "Select");
+        assert!(xmp.keywords.is_empty());
+        assert_eq!(parse("<x:xmpmeta xmp:Rating='-1'/>").rating, Some(-1));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode("Caf&#233; &lt;3 &#x263A; &bogus"),
+            "Café <3 ☺ &bogus"
+        );
+    }
+
+    #[test]
+    fn test_embedded_packet() {
+        let mut data = b"\xFF\xD8\xFF\xE1\x00\x10http://ns.adobe.com/xap/1.0/\0".to_vec();
+        data.extend(LIGHTROOM.as_bytes());
+        data.extend(b"\xFF\xD9");
+        let packet = PACKET.find(&data).unwrap();
+        assert!(packet.as_bytes().starts_with(b"<x:xmpmeta"));
+        assert!(packet.as_bytes().ends_with(b"</x:xmpmeta>"));
+    }
+}
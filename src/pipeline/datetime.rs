@@ -0,0 +1,86 @@
+// Datetime type and functions available to rule conditions.
+
+use chrono::{Datelike, Timelike};
+use rhai::{Dynamic, Engine, EvalAltResult, Map};
+
+use crate::metadata::Datetime;
+
+type FnResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn parse(value: &str) -> FnResult<Datetime> {
+    Datetime::parse(value).ok_or_else(|| format!("invalid date: '{value}'").into())
+}
+
+// time.between(...) and time.days_since() read the capture time from the time map
+fn capture_time(time: &Map) -> Option<Datetime> {
+    time.get("datetime")?.clone().try_cast::<Datetime>()
+}
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_type_with_name::<Datetime>("Datetime");
+    engine.register_fn("to_string", |dt: &mut Datetime| dt.to_string());
+    engine.register_fn("to_debug", |dt: &mut Datetime| dt.to_string());
+
+    // date("2024-07-01"), date(2024, 7, 1) and now()
+    engine.register_fn("date", |value: &str| parse(value));
+    engine.register_fn(
+        "date",
+        |year: i64, month: i64, day: i64| -> FnResult<Datetime> {
+            parse(&format!("{year:04}-{month:02}-{day:02}"))
+        },
+    );
+    engine.register_fn("now", Datetime::now);
+
+    // time.datetime.date(): midnight of the same day
+    engine.register_fn("date", |dt: &mut Datetime| dt.date());
+
+    engine.register_get("timestamp", |dt: &mut Datetime| dt.timestamp());
+    engine.register_get("year", |dt: &mut Datetime| i64::from(dt.local().year()));
+    engine.register_get("month", |dt: &mut Datetime| i64::from(dt.local().month()));
+    engine.register_get("day", |dt: &mut Datetime| i64::from(dt.local().day()));
+    engine.register_get("hour", |dt: &mut Datetime| i64::from(dt.local().hour()));
+    engine.register_get("minute", |dt: &mut Datetime| i64::from(dt.local().minute()));
+
+    engine.register_fn("==", |a: Datetime, b: Datetime| a == b);
+    engine.register_fn("!=", |a: Datetime, b: Datetime| a != b);
+    engine.register_fn("<", |a: Datetime, b: Datetime| a < b);
+    engine.register_fn("<=", |a: Datetime, b: Datetime| a <= b);
+    engine.register_fn(">", |a: Datetime, b: Datetime| a > b);
+    engine.register_fn(">=", |a: Datetime, b: Datetime| a >= b);
+
+    // Bounds may be given as datetimes or as date strings
+    engine.register_fn(
+        "between",
+        |dt: Datetime, start: Dynamic, end: Dynamic| -> FnResult<bool> {
+            Ok(dt.between(&bound(start)?, &bound(end)?))
+        },
+    );
+    engine.register_fn(
+        "between",
+        |time: Map, start: Dynamic, end: Dynamic| -> FnResult<bool> {
+            Ok(match capture_time(&time) {
+                Some(dt) => dt.between(&bound(start)?, &bound(end)?),
+                None => false,
+            })
+        },
+    );
+
+    // Whole days elapsed until now; () when the file has no capture time
+    engine.register_fn("days_since", |dt: Datetime| dt.days_until(&Datetime::now()));
+    engine.register_fn("days_since", |time: Map| -> Dynamic {
+        match capture_time(&time) {
+            Some(dt) => Dynamic::from_int(dt.days_until(&Datetime::now())),
+            None => Dynamic::UNIT,
+        }
+    });
+}
+
+fn bound(value: Dynamic) -> FnResult<Datetime> {
+    if value.is_string() {
+        return parse(&value.into_string()?);
+    }
+    let type_name = value.type_name();
+    value
+        .try_cast::<Datetime>()
+        .ok_or_else(|| format!("expected a date, got {type_name}").into())
+}
@@ -0,0 +1,137 @@
+// Location-aware functions available to rule conditions.
+
+use rhai::{Dynamic, Engine, EvalAltResult};
+use std::sync::{Arc, Mutex};
+
+use crate::metadata::Places;
+use crate::metadata::geo::{haversine_km, initial_bearing_deg};
+
+/// Coordinates of the file whose conditions are being evaluated, if it has any.
+pub(crate) type Position = Arc<Mutex<Option<(f64, f64)>>>;
+
+type FnResult<T> = Result<T, Box<EvalAltResult>>;
+
+// Coordinates may be written as integers in conditions, e.g. within_bbox(40, -4, 41, -3)
+fn degrees(value: &Dynamic) -> FnResult<f64> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .map_err(|type_name| format!("expected a coordinate, got {type_name}").into())
+}
+
+fn in_bbox(lat: f64, lon: f64, south: f64, west: f64, north: f64, east: f64) -> bool {
+    let within_lon = if west <= east {
+        (west..=east).contains(&lon)
+    } else {
+        // The box crosses the antimeridian
+        lon >= west || lon <= east
+    };
+    (south..=north).contains(&lat) && within_lon
+}
+
+pub(crate) fn register(engine: &mut Engine, places: Arc<Places>, position: Position) {
+    // in_place("Home"): whether the current file lies inside a configured place
+    let (in_place_places, in_place_position) = (places.clone(), position.clone());
+    engine.register_fn("in_place", move |name: &str| -> bool {
+        match *in_place_position.lock().unwrap() {
+            Some((lat, lon)) => in_place_places.contains(name, lat, lon),
+            None => false,
+        }
+    });
+
+    // distance_km(lat, lon, lat2, lon2): great-circle distance between two coordinates
+    engine.register_fn(
+        "distance_km",
+        |lat: Dynamic, lon: Dynamic, lat2: Dynamic, lon2: Dynamic| -> FnResult<f64> {
+            Ok(haversine_km(
+                degrees(&lat)?,
+                degrees(&lon)?,
+                degrees(&lat2)?,
+                degrees(&lon2)?,
+            ))
+        },
+    );
+
+    // distance_from("Home"): kilometers from the current file to the center of a
+    // configured place, or () when the file has no coordinates or the place is unknown
+    let (from_places, from_position) = (places, position.clone());
+    engine.register_fn("distance_from", move |name: &str| -> Dynamic {
+        let current = *from_position.lock().unwrap();
+        match (current, from_places.get(name)) {
+            (Some((lat, lon)), Some(place)) => {
+                let (center_lat, center_lon) = place.center();
+                Dynamic::from_float(haversine_km(lat, lon, center_lat, center_lon))
+            }
+            _ => Dynamic::UNIT,
+        }
+    });
+
+    // within_bbox(lat, lon, south, west, north, east): whether a coordinate lies in
+    // a bounding box; west > east describes a box crossing the antimeridian
+    engine.register_fn(
+        "within_bbox",
+        |lat: Dynamic,
+         lon: Dynamic,
+         south: Dynamic,
+         west: Dynamic,
+         north: Dynamic,
+         east: Dynamic|
+         -> FnResult<bool> {
+            Ok(in_bbox(
+                degrees(&lat)?,
+                degrees(&lon)?,
+                degrees(&south)?,
+                degrees(&west)?,
+                degrees(&north)?,
+                degrees(&east)?,
+            ))
+        },
+    );
+
+    // within_bbox(south, west, north, east): the same for the current file
+    engine.register_fn(
+        "within_bbox",
+        move |south: Dynamic, west: Dynamic, north: Dynamic, east: Dynamic| -> FnResult<bool> {
+            let Some((lat, lon)) = *position.lock().unwrap() else {
+                return Ok(false);
+            };
+            Ok(in_bbox(
+                lat,
+                lon,
+                degrees(&south)?,
+                degrees(&west)?,
+                degrees(&north)?,
+                degrees(&east)?,
+            ))
+        },
+    );
+
+    // bearing(lat, lon, lat2, lon2): initial compass bearing in degrees from the
+    // first coordinate towards the second
+    engine.register_fn(
+        "bearing",
+        |lat: Dynamic, lon: Dynamic, lat2: Dynamic, lon2: Dynamic| -> FnResult<f64> {
+            Ok(initial_bearing_deg(
+                degrees(&lat)?,
+                degrees(&lon)?,
+                degrees(&lat2)?,
+                degrees(&lon2)?,
+            ))
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_bbox() {
+        assert!(in_bbox(40.4, -3.7, 40.0, -4.0, 41.0, -3.0));
+        assert!(!in_bbox(42.0, -3.7, 40.0, -4.0, 41.0, -3.0));
+        // Fiji straddles the antimeridian
+        assert!(in_bbox(-17.0, 179.5, -20.0, 177.0, -15.0, -179.0));
+        assert!(in_bbox(-17.0, -179.5, -20.0, 177.0, -15.0, -179.0));
+        assert!(!in_bbox(-17.0, 170.0, -20.0, 177.0, -15.0, -179.0));
+    }
+}
@@ -1,8 +1,20 @@
+mod datetime;
+mod geo;
+mod phash;
+mod rational;
+
+use crate::batch::{DuplicateConfig, EventConfig, GroupConfig, TripConfig};
 use crate::metadata::context::MediaContext;
+use crate::metadata::sidecar;
+use crate::metadata::{
+    CameraConfig, ClockOffsetConfig, Datetime, ExifToolConfig, GeocoderConfig, MediaTypeConfig,
+    PlaceConfig, Places,
+};
 use crate::template::apply_template;
 use anyhow::Result;
 use rhai::{Dynamic, Engine, Scope};
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pipeline {
@@ -12,12 +24,62 @@ pub struct Pipeline {
     /// Maximum time difference in hours to search for location history (default: 48)
     #[serde(default = "default_location_history_max_hours")]
     pub location_history_max_hours: u64,
+    /// Maximum time difference in minutes to borrow GPS from a geotagged file in
+    /// the same run (disabled when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub neighbor_location_max_minutes: Option<u64>,
+    /// Reverse geocoding settings
+    #[serde(default)]
+    pub geocoder: GeocoderConfig,
+    /// Named geofences matched against file coordinates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub places: Vec<PlaceConfig>,
+    /// Clustering of the files of a run into events (disabled when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<EventConfig>,
+    /// Detection of trips away from a configured home place (disabled when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trips: Option<TripConfig>,
+    /// Per-camera clock corrections applied to EXIF capture times
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clock_offsets: Vec<ClockOffsetConfig>,
+    /// Grouping of RAW+JPEG pairs and Live Photos into assets (disabled when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<GroupConfig>,
+    /// Grouping of identical and near-duplicate images (disabled when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates: Option<DuplicateConfig>,
+    /// Extension and MIME type overrides for media type classification
+    #[serde(default)]
+    pub media_types: MediaTypeConfig,
+    /// Additions to the built-in camera make and model table
+    #[serde(default)]
+    pub cameras: CameraConfig,
+    /// External exiftool for files the native parsers cannot fully read (disabled
+    /// when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exiftool: Option<ExifToolConfig>,
+    /// Let location names entered in IPTC replace reverse-geocoded ones
+    #[serde(default)]
+    pub iptc_location: bool,
+    /// Pass every file to the rules, not only images, videos and the types listed
+    /// in `media_types.include`
+    #[serde(default)]
+    pub include_non_media: bool,
+    /// Patterns of sidecar files that follow their media file's action, such as
+    /// "{name}.xmp"
+    #[serde(default = "default_sidecars")]
+    pub sidecars: Vec<String>,
 }
 
 fn default_location_history_max_hours() -> u64 {
     48
 }
 
+fn default_sidecars() -> Vec<String> {
+    sidecar::default_patterns()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ruleset {
     pub name: String,
@@ -105,21 +167,40 @@ impl ActionSpec {
 
 pub struct RuleEngine {
     engine: Engine,
+    position: geo::Position,
 }
 
 impl RuleEngine {
     pub fn new() -> Result<Self> {
+        Self::with_places(Arc::new(Places::default()))
+    }
+
+    pub fn with_places(places: Arc<Places>) -> Result<Self> {
         let mut engine = Engine::new();
 
         // Configure for expression-only evaluation
         engine.set_max_expr_depths(64, 64);
 
-        Ok(Self { engine })
+        let position = Arc::new(Mutex::new(None));
+        geo::register(&mut engine, places, position.clone());
+        datetime::register(&mut engine);
+        phash::register(&mut engine);
+        rational::register(&mut engine);
+
+        Ok(Self { engine, position })
     }
 
     pub fn evaluate_condition(&self, condition: &str, context: &MediaContext) -> Result<bool> {
         let mut scope = Scope::new();
 
+        // Expose the file's coordinates to location-aware functions
+        let space = &context.space;
+        *self.position.lock().unwrap() = if space.lat == 0.0 && space.lon == 0.0 {
+            None
+        } else {
+            Some((space.lat, space.lon))
+        };
+
         // Create time object map
         let mut time_map = rhai::Map::new();
         let time = &context.time;
@@ -131,25 +212,47 @@ impl RuleEngine {
         time_map.insert("hh".into(), Dynamic::from(time.hh.clone()));
         time_map.insert("min".into(), Dynamic::from(time.min.clone()));
         time_map.insert("ss".into(), Dynamic::from(time.ss.clone()));
+        if let Some(timestamp) = time.timestamp {
+            time_map.insert("timestamp".into(), Dynamic::from(timestamp.timestamp()));
+        }
+        if let Some(datetime) = Datetime::from_time(time) {
+            time_map.insert("datetime".into(), Dynamic::from(datetime));
+        }
+        if let Some(original) = time.original {
+            time_map.insert("original".into(), Dynamic::from(original));
+        }
+        time_map.insert("source".into(), Dynamic::from(time.source.clone()));
         scope.push("time", time_map);
 
         // Create space object map
         let mut space_map = rhai::Map::new();
-        let space = &context.space;
         space_map.insert("country".into(), Dynamic::from(space.country.clone()));
         space_map.insert(
             "country_code".into(),
             Dynamic::from(space.country_code.clone()),
         );
         space_map.insert("state".into(), Dynamic::from(space.state.clone()));
+        space_map.insert("county".into(), Dynamic::from(space.county.clone()));
         space_map.insert("city".into(), Dynamic::from(space.city.clone()));
         space_map.insert("district".into(), Dynamic::from(space.district.clone()));
         space_map.insert("road".into(), Dynamic::from(space.road.clone()));
         space_map.insert("lat".into(), Dynamic::from(space.lat));
         space_map.insert("lon".into(), Dynamic::from(space.lon));
+        space_map.insert("place_name".into(), Dynamic::from(space.place_name.clone()));
+        space_map.insert(
+            "place_address".into(),
+            Dynamic::from(space.place_address.clone()),
+        );
+        space_map.insert("place_id".into(), Dynamic::from(space.place_id.clone()));
+        space_map.insert("source".into(), Dynamic::from(space.source.clone()));
+        space_map.insert("place".into(), Dynamic::from(space.place.clone()));
+        space_map.insert("timezone".into(), Dynamic::from(space.timezone.clone()));
         if let Some(altitude) = space.altitude {
             space_map.insert("altitude".into(), Dynamic::from(altitude));
         }
+        if let Some(distance) = space.place_distance_km {
+            space_map.insert("place_distance_km".into(), Dynamic::from(distance));
+        }
         scope.push("space", space_map);
 
         // Create source object map
@@ -160,6 +263,7 @@ impl RuleEngine {
         source_map.insert("extension".into(), Dynamic::from(source.extension.clone()));
         source_map.insert("original".into(), Dynamic::from(source.original.clone()));
         source_map.insert("size".into(), Dynamic::from(source.size as i64));
+        source_map.insert("mime".into(), Dynamic::from(source.mime.clone()));
         scope.push("source", source_map);
 
         // Create special object map
@@ -168,10 +272,133 @@ impl RuleEngine {
         special_map.insert("md5".into(), Dynamic::from(special.md5.clone()));
         special_map.insert("md5_short".into(), Dynamic::from(special.md5_short.clone()));
         special_map.insert("count".into(), Dynamic::from(special.count as i64));
+        special_map.insert("phash".into(), Dynamic::from(special.phash.clone()));
+        special_map.insert("dup_group".into(), Dynamic::from(special.dup_group.clone()));
+        special_map.insert("is_best".into(), Dynamic::from(special.is_best));
         scope.push("special", special_map);
 
+        // Create event object map
+        let mut event_map = rhai::Map::new();
+        let event = &context.event;
+        event_map.insert("id".into(), Dynamic::from(event.id as i64));
+        event_map.insert("name".into(), Dynamic::from(event.name.clone()));
+        event_map.insert("size".into(), Dynamic::from(event.size as i64));
+        if let Some(start) = event.start {
+            event_map.insert("start".into(), Dynamic::from(start));
+        }
+        if let Some(end) = event.end {
+            event_map.insert("end".into(), Dynamic::from(end));
+        }
+        scope.push("event", event_map);
+
+        // Create trip object map
+        let mut trip_map = rhai::Map::new();
+        let trip = &context.trip;
+        trip_map.insert("id".into(), Dynamic::from(trip.id as i64));
+        trip_map.insert("name".into(), Dynamic::from(trip.name.clone()));
+        trip_map.insert("day".into(), Dynamic::from(trip.day as i64));
+        if let Some(start) = trip.start {
+            trip_map.insert("start".into(), Dynamic::from(start));
+        }
+        if let Some(end) = trip.end {
+            trip_map.insert("end".into(), Dynamic::from(end));
+        }
+        scope.push("trip", trip_map);
+
+        // Create group object map
+        let mut group_map = rhai::Map::new();
+        let group = &context.group;
+        group_map.insert("id".into(), Dynamic::from(group.id as i64));
+        group_map.insert("role".into(), Dynamic::from(group.role.clone()));
+        group_map.insert("size".into(), Dynamic::from(group.size as i64));
+        scope.push("group", group_map);
+
+        // Create takeout object map
+        let mut takeout_map = rhai::Map::new();
+        let takeout = &context.takeout;
+        takeout_map.insert("json".into(), Dynamic::from(takeout.json.clone()));
+        takeout_map.insert("title".into(), Dynamic::from(takeout.title.clone()));
+        takeout_map.insert(
+            "description".into(),
+            Dynamic::from(takeout.description.clone()),
+        );
+        let people: rhai::Array = takeout.people.iter().cloned().map(Dynamic::from).collect();
+        takeout_map.insert("people".into(), Dynamic::from(people));
+        takeout_map.insert("url".into(), Dynamic::from(takeout.url.clone()));
+        takeout_map.insert("favorited".into(), Dynamic::from(takeout.favorited));
+        if let Some(taken) = takeout.taken {
+            takeout_map.insert("taken".into(), Dynamic::from(taken));
+        }
+        scope.push("takeout", takeout_map);
+
+        // Create xmp object map
+        let mut xmp_map = rhai::Map::new();
+        let xmp = &context.xmp;
+        if let Some(rating) = xmp.rating {
+            xmp_map.insert("rating".into(), Dynamic::from(rating));
+        }
+        xmp_map.insert("label".into(), Dynamic::from(xmp.label.clone()));
+        xmp_map.insert("title".into(), Dynamic::from(xmp.title.clone()));
+        let keywords: rhai::Array = xmp.keywords.iter().cloned().map(Dynamic::from).collect();
+        xmp_map.insert("keywords".into(), Dynamic::from(keywords));
+        let regions: rhai::Array = xmp.regions.iter().cloned().map(Dynamic::from).collect();
+        xmp_map.insert("regions".into(), Dynamic::from(regions));
+        xmp_map.insert("source".into(), Dynamic::from(xmp.source.clone()));
+        scope.push("xmp", xmp_map);
+
+        // Create iptc object map
+        let mut iptc_map = rhai::Map::new();
+        let iptc = &context.iptc;
+        for (key, value) in [
+            ("title", &iptc.title),
+            ("headline", &iptc.headline),
+            ("caption", &iptc.caption),
+            ("credit", &iptc.credit),
+            ("source", &iptc.source),
+            ("copyright", &iptc.copyright),
+            ("city", &iptc.city),
+            ("sublocation", &iptc.sublocation),
+            ("state", &iptc.state),
+            ("country", &iptc.country),
+            ("country_code", &iptc.country_code),
+        ] {
+            iptc_map.insert(key.into(), Dynamic::from(value.clone()));
+        }
+        let keywords: rhai::Array = iptc.keywords.iter().cloned().map(Dynamic::from).collect();
+        iptc_map.insert("keywords".into(), Dynamic::from(keywords));
+        let byline: rhai::Array = iptc.byline.iter().cloned().map(Dynamic::from).collect();
+        iptc_map.insert("byline".into(), Dynamic::from(byline));
+        scope.push("iptc", iptc_map);
+
+        // Create camera object map
+        let mut camera_map = rhai::Map::new();
+        let camera = &context.camera;
+        camera_map.insert("make".into(), Dynamic::from(camera.make.clone()));
+        camera_map.insert("model".into(), Dynamic::from(camera.model.clone()));
+        camera_map.insert("serial".into(), Dynamic::from(camera.serial.clone()));
+        camera_map.insert("lens".into(), Dynamic::from(camera.lens.clone()));
+        camera_map.insert("kind".into(), Dynamic::from(camera.kind.clone()));
+        scope.push("camera", camera_map);
+
+        // Create image object map; sizes are missing when unknown
+        let mut image_map = rhai::Map::new();
+        let image = &context.image;
+        if image.width > 0 {
+            image_map.insert("width".into(), Dynamic::from(image.width));
+            image_map.insert("height".into(), Dynamic::from(image.height));
+            image_map.insert("megapixels".into(), Dynamic::from(image.megapixels));
+            image_map.insert("aspect_ratio".into(), Dynamic::from(image.aspect_ratio));
+        }
+        image_map.insert(
+            "orientation_class".into(),
+            Dynamic::from(image.orientation_class.clone()),
+        );
+        image_map.insert("is_panorama".into(), Dynamic::from(image.is_panorama));
+        scope.push("image", image_map);
+
         // Add type variable
         scope.push("type", context.r#type.clone());
+        scope.push("subtype", context.subtype.clone());
 
         // Add meta as a Rhai object map
         let mut meta_map = rhai::Map::new();
@@ -196,13 +423,19 @@ impl RuleEngine {
         context: &MediaContext,
     ) -> Result<Option<(String, ActionSpec)>> {
         if self.evaluate_condition(&rule.condition, context)? {
-            // Apply template to get the destination path
-            let destination = apply_template(&rule.template, context)?;
-            Ok(Some((destination.to_string(), rule.action.clone())))
+            self.apply_rule(rule, context).map(Some)
         } else {
             Ok(None)
         }
     }
+
+    /// Resolves the destination of a rule without evaluating its condition, for
+    /// files that follow the rule matched by another file.
+    pub fn apply_rule(&self, rule: &Rule, context: &MediaContext) -> Result<(String, ActionSpec)> {
+        // Apply template to get the destination path
+        let destination = apply_template(&rule.template, context)?;
+        Ok((destination.to_string(), rule.action.clone()))
+    }
 }
 
 #[cfg(test)]
@@ -225,12 +458,25 @@ mod tests {
                 month_name: "December".to_string(),
                 weekday: "Monday".to_string(),
                 timestamp: None,
+                naive: false,
+                utc_offset_secs: 0,
+                original: None,
+                source: String::new(),
             },
             r#type: defaultctx.r#type.clone(),
             meta: defaultctx.meta.clone(),
             source: defaultctx.source.clone(),
             space: defaultctx.space.clone(),
             special: defaultctx.special.clone(),
+            event: defaultctx.event.clone(),
+            trip: defaultctx.trip.clone(),
+            group: defaultctx.group.clone(),
+            takeout: defaultctx.takeout.clone(),
+            xmp: defaultctx.xmp.clone(),
+            iptc: defaultctx.iptc.clone(),
+            camera: defaultctx.camera.clone(),
+            image: defaultctx.image.clone(),
+            subtype: defaultctx.subtype.clone(),
         };
 
         assert!(
@@ -265,6 +511,10 @@ mod tests {
             month_name: "July".to_string(),
             weekday: "Thursday".to_string(),
             timestamp: None,
+            naive: false,
+            utc_offset_secs: 0,
+            original: None,
+            source: String::new(),
         };
 
         let mut context = MediaContext {
@@ -274,6 +524,15 @@ mod tests {
             space: ctxdefault.space.clone(),
             meta: Default::default(),
             special: ctxdefault.special.clone(),
+            event: ctxdefault.event.clone(),
+            trip: ctxdefault.trip.clone(),
+            group: ctxdefault.group.clone(),
+            takeout: ctxdefault.takeout.clone(),
+            xmp: ctxdefault.xmp.clone(),
+            iptc: ctxdefault.iptc.clone(),
+            camera: ctxdefault.camera.clone(),
+            image: ctxdefault.image.clone(),
+            subtype: ctxdefault.subtype.clone(),
         };
 
         // Add metadata values
@@ -304,6 +563,10 @@ mod tests {
             month_name: "December".to_string(),
             weekday: "Monday".to_string(),
             timestamp: None,
+            naive: false,
+            utc_offset_secs: 0,
+            original: None,
+            source: String::new(),
         };
 
         context.source = crate::metadata::context::SourceContext {
@@ -312,6 +575,7 @@ mod tests {
             extension: "jpg".to_string(),
             original: "photo.jpg".to_string(),
             size: 1024,
+            mime: "image/jpeg".to_string(),
         };
 
         context.r#type = "image".to_string();
@@ -348,6 +612,15 @@ mod tests {
             space: defaultctx.space.clone(),
             meta: Default::default(),
             special: defaultctx.special.clone(),
+            event: defaultctx.event.clone(),
+            trip: defaultctx.trip.clone(),
+            group: defaultctx.group.clone(),
+            takeout: defaultctx.takeout.clone(),
+            xmp: defaultctx.xmp.clone(),
+            iptc: defaultctx.iptc.clone(),
+            camera: defaultctx.camera.clone(),
+            image: defaultctx.image.clone(),
+            subtype: defaultctx.subtype.clone(),
         };
 
         context
@@ -369,4 +642,302 @@ mod tests {
         let result = engine.process_rule(&rule, &context).unwrap();
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_in_place_condition() {
+        let places: Vec<PlaceConfig> =
+            serde_yaml::from_str("- name: Home\n  lat: 40.4168\n  lon: -3.7038\n  radius_m: 150\n")
+                .unwrap();
+        let engine = RuleEngine::with_places(Arc::new(Places::load(&places).unwrap())).unwrap();
+
+        let mut context = MediaContext::default();
+        assert!(
+            !engine
+                .evaluate_condition("in_place(\"Home\")", &context)
+                .unwrap()
+        );
+
+        context.space.lat = 40.4170;
+        context.space.lon = -3.7040;
+        assert!(
+            engine
+                .evaluate_condition("in_place(\"Home\")", &context)
+                .unwrap()
+        );
+        assert!(
+            !engine
+                .evaluate_condition("in_place(\"Office\")", &context)
+                .unwrap()
+        );
+
+        context.space.lat = 41.3874;
+        context.space.lon = 2.1686;
+        assert!(
+            !engine
+                .evaluate_condition("in_place(\"Home\")", &context)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_geo_functions() {
+        let places: Vec<PlaceConfig> =
+            serde_yaml::from_str("- name: Home\n  lat: 40.4168\n  lon: -3.7038\n  radius_m: 150\n")
+                .unwrap();
+        let engine = RuleEngine::with_places(Arc::new(Places::load(&places).unwrap())).unwrap();
+        let mut context = MediaContext::default();
+
+        // Without coordinates the distance is unknown and never matches
+        assert!(
+            !engine
+                .evaluate_condition("distance_from(\"Home\") > 100", &context)
+                .unwrap()
+        );
+        assert!(
+            engine
+                .evaluate_condition("distance_from(\"Home\") == ()", &context)
+                .unwrap()
+        );
+
+        // Barcelona is about 505 km from Madrid, heading east-northeast
+        context.space.lat = 41.3874;
+        context.space.lon = 2.1686;
+        for condition in [
+            "distance_from(\"Home\") > 100",
+            "distance_km(space.lat, space.lon, 40.4168, -3.7038) > 500",
+            "within_bbox(41, 2, 42, 3)",
+            "within_bbox(space.lat, space.lon, 41.0, 2.0, 42.0, 3.0)",
+            "bearing(40.4168, -3.7038, space.lat, space.lon) < 90",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+        assert!(
+            !engine
+                .evaluate_condition("within_bbox(40, -4, 41, -3)", &context)
+                .unwrap()
+        );
+        assert!(
+            engine
+                .evaluate_condition("distance_km(\"a\", 0, 0, 0) > 0", &context)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_datetime_conditions() {
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+
+        // Files without a capture time never match date ranges
+        assert!(
+            !engine
+                .evaluate_condition("time.between(\"2024-07-01\", \"2024-07-15\")", &context)
+                .unwrap()
+        );
+        assert!(
+            !engine
+                .evaluate_condition("time.datetime >= date(\"2024-07-01\")", &context)
+                .unwrap()
+        );
+
+        // 2024-07-12 12:00 in Madrid
+        context.time.timestamp = Some("2024-07-12T10:00:00Z".parse().unwrap());
+        context.time.utc_offset_secs = 7200;
+        for condition in [
+            "time.timestamp == 1720778400",
+            "time.datetime >= date(\"2024-07-01\") && time.datetime < date(2024, 7, 15)",
+            "time.between(\"2024-07-01\", \"2024-07-15\")",
+            "time.datetime.between(date(\"2024-07-12 11:00\"), date(\"2024-07-12 13:00\"))",
+            "time.datetime.date() == date(\"2024-07-12\")",
+            "time.datetime.hour == 12",
+            "time.days_since() > 30",
+            "time.datetime < now()",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+        assert!(
+            !engine
+                .evaluate_condition("time.between(\"2024-07-13\", \"2024-07-15\")", &context)
+                .unwrap()
+        );
+        assert!(
+            engine
+                .evaluate_condition("date(\"July 1st\") < now()", &context)
+                .is_err()
+        );
+
+        // The camera's own time, before a clock offset moved it an hour on
+        context.time.original = Datetime::parse("2024-07-12T11:00:00+02:00");
+        assert!(
+            engine
+                .evaluate_condition("time.original < time.datetime", &context)
+                .unwrap()
+        );
+        let path = apply_template("{time.original}", &context).unwrap();
+        assert_eq!(path.as_str(), "2024-07-12T11:00:00+02:00");
+    }
+
+    #[test]
+    fn test_phash_distance_condition() {
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+        context.special.phash = "f0f0f0f0f0f0f0f0".to_string();
+
+        assert!(
+            engine
+                .evaluate_condition(
+                    "phash_distance(special.phash, \"f0f0f0f0f0f0f0f3\") == 2",
+                    &context
+                )
+                .unwrap()
+        );
+        assert!(
+            engine
+                .evaluate_condition("phash_distance(special.phash, \"\") < 10", &context)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_xmp_conditions() {
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+        context.xmp.rating = Some(4);
+        context.xmp.keywords = vec!["beach".to_string(), "Lisbon".to_string()];
+
+        for condition in [
+            "xmp.rating >= 4",
+            "\"beach\" in xmp.keywords",
+            "xmp.keywords.len() == 2",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+
+        // Files without a rating never match a rating threshold
+        context.xmp.rating = None;
+        assert!(
+            !engine
+                .evaluate_condition("xmp.rating >= 4", &context)
+                .unwrap()
+        );
+        assert!(
+            engine
+                .evaluate_condition("xmp.rating == ()", &context)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_typed_meta_conditions() {
+        use crate::metadata::values::Rational;
+
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+        let meta = &mut context.meta;
+        meta.insert(
+            "ExposureTime".to_string(),
+            Dynamic::from(Rational::new(1, 125)),
+        );
+        meta.insert("FNumber".to_string(), Dynamic::from(Rational::new(28, 10)));
+        meta.insert(
+            "DateTimeOriginal".to_string(),
+            Dynamic::from(Datetime::parse("2024-07-01T18:30:00+02:00").unwrap()),
+        );
+        let bits: rhai::Array = vec![Dynamic::from_int(8); 3];
+        meta.insert("BitsPerSample".to_string(), Dynamic::from_array(bits));
+        meta.insert(
+            "GPS:GPSAltitude".to_string(),
+            Dynamic::from(Rational::new(1234, 10)),
+        );
+
+        for condition in [
+            "meta.ExposureTime <= 0.008",
+            "meta.ExposureTime < rational(\"1/60\")",
+            "meta.ExposureTime.den == 125",
+            "meta.FNumber < 4 && 2 < meta.FNumber",
+            "meta.FNumber == 2.8",
+            "meta.DateTimeOriginal >= date(\"2024-07-01\")",
+            "meta.BitsPerSample.len() == 3 && meta.BitsPerSample[0] == 8",
+            "meta.ExposureTime * 1000 == 8.0",
+            "1 / meta.ExposureTime > 100",
+            "meta.FNumber + 0.5 > 3.2 && meta.FNumber - 1 < 2",
+            "meta.FNumber * meta.FNumber < 8",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+
+        let path = apply_template("{meta.ExposureTime}s f{meta.FNumber}", &context).unwrap();
+        assert_eq!(path.as_str(), "1/125s f2.8");
+
+        // Group-qualified keys work in templates too
+        let path = apply_template("{meta.GPS:GPSAltitude}m {meta.IFD1:ImageWidth}", &context);
+        assert_eq!(
+            path.unwrap().as_str(),
+            "123.4m {unknown:meta.IFD1:ImageWidth}"
+        );
+    }
+
+    #[test]
+    fn test_image_conditions() {
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+        assert!(
+            !engine
+                .evaluate_condition("image.megapixels > 10", &context)
+                .unwrap()
+        );
+
+        context.image.width = 3024;
+        context.image.height = 4032;
+        context.image.megapixels = 12.19;
+        context.image.aspect_ratio = 0.75;
+        context.image.orientation_class = "portrait".to_string();
+        for condition in [
+            "image.megapixels > 10",
+            "image.orientation_class == \"portrait\" && !image.is_panorama",
+            "image.height > image.width && image.aspect_ratio < 1.0",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+
+        let path = apply_template(
+            "{image.width}x{image.height} {image.megapixels}MP",
+            &context,
+        );
+        assert_eq!(path.unwrap().as_str(), "3024x4032 12.2MP");
+    }
+
+    #[test]
+    fn test_iptc_conditions() {
+        let engine = RuleEngine::new().unwrap();
+        let mut context = MediaContext::default();
+        context.iptc.keywords = vec!["storm".to_string(), "coast".to_string()];
+        context.iptc.city = "Nazaré".to_string();
+
+        for condition in [
+            "\"storm\" in iptc.keywords",
+            "iptc.city == \"Nazaré\"",
+            "iptc.byline.len() == 0",
+        ] {
+            assert!(
+                engine.evaluate_condition(condition, &context).unwrap(),
+                "{condition}"
+            );
+        }
+    }
 }
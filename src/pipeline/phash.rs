@@ -0,0 +1,17 @@
+// Perceptual hash functions available to rule conditions.
+
+use rhai::{Engine, EvalAltResult};
+
+use crate::metadata::phash;
+
+pub(crate) fn register(engine: &mut Engine) {
+    // phash_distance(special.phash, "f0e1d2c3b4a59687"): differing bits, 0 to 64
+    engine.register_fn(
+        "phash_distance",
+        |a: &str, b: &str| -> Result<i64, Box<EvalAltResult>> {
+            phash::phash_distance(a, b)
+                .map(i64::from)
+                .ok_or_else(|| format!("invalid perceptual hash: '{a}' or '{b}'").into())
+        },
+    );
+}
@@ -0,0 +1,73 @@
+// Rational type for EXIF values such as ExposureTime and FNumber.
+
+use rhai::{Engine, EvalAltResult, FLOAT, INT};
+
+use crate::metadata::values::Rational;
+
+type FnResult<T> = Result<T, Box<EvalAltResult>>;
+
+// Plain numbers compare with the rational's value
+macro_rules! register_comparisons {
+    ($engine:expr, $other:ty) => {
+        $engine.register_fn("==", |a: Rational, b: $other| a.value() == b as f64);
+        $engine.register_fn("!=", |a: Rational, b: $other| a.value() != b as f64);
+        $engine.register_fn("<", |a: Rational, b: $other| a.value() < b as f64);
+        $engine.register_fn("<=", |a: Rational, b: $other| a.value() <= b as f64);
+        $engine.register_fn(">", |a: Rational, b: $other| a.value() > b as f64);
+        $engine.register_fn(">=", |a: Rational, b: $other| a.value() >= b as f64);
+        $engine.register_fn("==", |a: $other, b: Rational| a as f64 == b.value());
+        $engine.register_fn("!=", |a: $other, b: Rational| a as f64 != b.value());
+        $engine.register_fn("<", |a: $other, b: Rational| (a as f64) < b.value());
+        $engine.register_fn("<=", |a: $other, b: Rational| a as f64 <= b.value());
+        $engine.register_fn(">", |a: $other, b: Rational| a as f64 > b.value());
+        $engine.register_fn(">=", |a: $other, b: Rational| a as f64 >= b.value());
+    };
+}
+
+// Arithmetic with plain numbers gives a float: meta.FocalLength * 1.5
+macro_rules! register_arithmetic {
+    ($engine:expr, $other:ty) => {
+        $engine.register_fn("+", |a: Rational, b: $other| a.value() + b as f64);
+        $engine.register_fn("-", |a: Rational, b: $other| a.value() - b as f64);
+        $engine.register_fn("*", |a: Rational, b: $other| a.value() * b as f64);
+        $engine.register_fn("/", |a: Rational, b: $other| a.value() / b as f64);
+        $engine.register_fn("+", |a: $other, b: Rational| a as f64 + b.value());
+        $engine.register_fn("-", |a: $other, b: Rational| a as f64 - b.value());
+        $engine.register_fn("*", |a: $other, b: Rational| a as f64 * b.value());
+        $engine.register_fn("/", |a: $other, b: Rational| a as f64 / b.value());
+    };
+}
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_type_with_name::<Rational>("Rational");
+    engine.register_fn("to_string", |r: &mut Rational| r.to_string());
+    engine.register_fn("to_debug", |r: &mut Rational| r.to_string());
+    engine.register_fn("to_float", |r: &mut Rational| r.value());
+
+    // rational("1/125") and rational(1, 125)
+    engine.register_fn("rational", |value: &str| -> FnResult<Rational> {
+        Rational::parse(value).ok_or_else(|| format!("invalid rational: '{value}'").into())
+    });
+    engine.register_fn("rational", Rational::new);
+
+    engine.register_get("num", |r: &mut Rational| r.num);
+    engine.register_get("den", |r: &mut Rational| r.den);
+    engine.register_get("value", |r: &mut Rational| r.value());
+
+    // meta.ExposureTime <= 0.008, meta.FNumber < 4, meta.ExposureTime < rational("1/60")
+    engine.register_fn("==", |a: Rational, b: Rational| a == b);
+    engine.register_fn("!=", |a: Rational, b: Rational| a != b);
+    engine.register_fn("<", |a: Rational, b: Rational| a < b);
+    engine.register_fn("<=", |a: Rational, b: Rational| a <= b);
+    engine.register_fn(">", |a: Rational, b: Rational| a > b);
+    engine.register_fn(">=", |a: Rational, b: Rational| a >= b);
+    register_comparisons!(engine, INT);
+    register_comparisons!(engine, FLOAT);
+
+    engine.register_fn("+", |a: Rational, b: Rational| a.value() + b.value());
+    engine.register_fn("-", |a: Rational, b: Rational| a.value() - b.value());
+    engine.register_fn("*", |a: Rational, b: Rational| a.value() * b.value());
+    engine.register_fn("/", |a: Rational, b: Rational| a.value() / b.value());
+    register_arithmetic!(engine, INT);
+    register_arithmetic!(engine, FLOAT);
+}
@@ -1,12 +1,12 @@
 use anyhow::Result;
 use camino::Utf8PathBuf;
 use regex::Regex;
-use rhai::Dynamic;
 
 use crate::metadata::MediaContext;
+use crate::metadata::values::display_value;
 
 lazy_static::lazy_static! {
-    static ref TEMPLATE_VAR: Regex = Regex::new(r"\{([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)*)\}").unwrap();
+    static ref TEMPLATE_VAR: Regex = Regex::new(r"\{([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_:]*)*)\}").unwrap();
 }
 
 pub fn apply_template(template: &str, context: &MediaContext) -> Result<Utf8PathBuf> {
@@ -31,17 +31,28 @@ fn resolve_variable(var_name: &str, context: &MediaContext) -> Option<String> {
             "ss" => Some(context.time.ss.clone()),
             "month_name" => Some(context.time.month_name.clone()),
             "weekday" => Some(context.time.weekday.clone()),
+            "timestamp" => context.time.timestamp.map(|ts| ts.timestamp().to_string()),
+            "source" => Some(context.time.source.clone()),
+            "original" => context.time.original.map(|dt| dt.to_string()),
             _ => None,
         },
         ["space", field] => match *field {
             "country" => Some(context.space.country.clone()),
             "country_code" => Some(context.space.country_code.clone()),
             "state" => Some(context.space.state.clone()),
+            "county" => Some(context.space.county.clone()),
             "city" => Some(context.space.city.clone()),
             "district" => Some(context.space.district.clone()),
             "road" => Some(context.space.road.clone()),
             "lat" => Some(context.space.lat.to_string()),
             "lon" => Some(context.space.lon.to_string()),
+            "place_name" => Some(context.space.place_name.clone()),
+            "place_address" => Some(context.space.place_address.clone()),
+            "place_id" => Some(context.space.place_id.clone()),
+            "source" => Some(context.space.source.clone()),
+            "place" => Some(context.space.place.clone()),
+            "timezone" => Some(context.space.timezone.clone()),
+            "place_distance_km" => context.space.place_distance_km.map(|d| format!("{d:.1}")),
             _ => None,
         },
         ["source", field] => match *field {
@@ -50,37 +61,117 @@ fn resolve_variable(var_name: &str, context: &MediaContext) -> Option<String> {
             "extension" => Some(context.source.extension.clone()),
             "original" => Some(context.source.original.clone()),
             "size" => Some(context.source.size.to_string()),
+            "mime" => Some(context.source.mime.clone()),
             _ => None,
         },
         ["special", field] => match *field {
             "md5" => Some(context.special.md5.clone()),
             "md5_short" => Some(context.special.md5_short.clone()),
             "count" => Some(context.special.count.to_string()),
+            "phash" => Some(context.special.phash.clone()),
+            "dup_group" => Some(context.special.dup_group.clone()),
+            "is_best" => Some(context.special.is_best.to_string()),
             _ => None,
         },
+        ["event", field] => match *field {
+            "id" => Some(context.event.id.to_string()),
+            "name" => Some(context.event.name.clone()),
+            "start" => context
+                .event
+                .start
+                .map(|dt| dt.local().format("%Y-%m-%d").to_string()),
+            "end" => context
+                .event
+                .end
+                .map(|dt| dt.local().format("%Y-%m-%d").to_string()),
+            "size" => Some(context.event.size.to_string()),
+            _ => None,
+        },
+        ["trip", field] => match *field {
+            "id" => Some(context.trip.id.to_string()),
+            "name" => Some(context.trip.name.clone()),
+            "start" => context
+                .trip
+                .start
+                .map(|dt| dt.local().format("%Y-%m-%d").to_string()),
+            "end" => context
+                .trip
+                .end
+                .map(|dt| dt.local().format("%Y-%m-%d").to_string()),
+            "day" => Some(context.trip.day.to_string()),
+            _ => None,
+        },
+        ["group", field] => match *field {
+            "id" => Some(context.group.id.to_string()),
+            "role" => Some(context.group.role.clone()),
+            "size" => Some(context.group.size.to_string()),
+            _ => None,
+        },
+        ["takeout", field] => match *field {
+            "json" => Some(context.takeout.json.clone()),
+            "title" => Some(context.takeout.title.clone()),
+            "description" => Some(context.takeout.description.clone()),
+            "people" => Some(context.takeout.people.join(", ")),
+            "url" => Some(context.takeout.url.clone()),
+            "favorited" => Some(context.takeout.favorited.to_string()),
+            "taken" => context
+                .takeout
+                .taken
+                .map(|dt| dt.local().format("%Y-%m-%d").to_string()),
+            _ => None,
+        },
+        ["xmp", field] => match *field {
+            "rating" => context.xmp.rating.map(|rating| rating.to_string()),
+            "label" => Some(context.xmp.label.clone()),
+            "title" => Some(context.xmp.title.clone()),
+            "keywords" => Some(context.xmp.keywords.join(", ")),
+            "regions" => Some(context.xmp.regions.join(", ")),
+            "source" => Some(context.xmp.source.clone()),
+            _ => None,
+        },
+        ["iptc", field] => {
+            let iptc = &context.iptc;
+            match *field {
+                "title" => Some(iptc.title.clone()),
+                "headline" => Some(iptc.headline.clone()),
+                "caption" => Some(iptc.caption.clone()),
+                "keywords" => Some(iptc.keywords.join(", ")),
+                "byline" => Some(iptc.byline.join(", ")),
+                "credit" => Some(iptc.credit.clone()),
+                "source" => Some(iptc.source.clone()),
+                "copyright" => Some(iptc.copyright.clone()),
+                "city" => Some(iptc.city.clone()),
+                "sublocation" => Some(iptc.sublocation.clone()),
+                "state" => Some(iptc.state.clone()),
+                "country" => Some(iptc.country.clone()),
+                "country_code" => Some(iptc.country_code.clone()),
+                _ => None,
+            }
+        }
+        ["camera", field] => match *field {
+            "make" => Some(context.camera.make.clone()),
+            "model" => Some(context.camera.model.clone()),
+            "serial" => Some(context.camera.serial.clone()),
+            "lens" => Some(context.camera.lens.clone()),
+            "kind" => Some(context.camera.kind.clone()),
+            _ => None,
+        },
+        ["image", field] => {
+            let image = &context.image;
+            let known = image.width > 0;
+            match *field {
+                "width" => known.then(|| image.width.to_string()),
+                "height" => known.then(|| image.height.to_string()),
+                "megapixels" => known.then(|| format!("{:.1}", image.megapixels)),
+                "aspect_ratio" => known.then(|| format!("{:.2}", image.aspect_ratio)),
+                "orientation_class" => Some(image.orientation_class.clone()),
+                "is_panorama" => Some(image.is_panorama.to_string()),
+                _ => None,
+            }
+        }
         ["type"] => Some(context.r#type.clone()),
-        ["meta", tag] => context.meta.get(*tag).map(dynamic_to_string),
+        ["subtype"] => Some(context.subtype.clone()),
+        ["meta", tag] => context.meta.get(*tag).map(display_value),
         _ => None,
     }
 }
-
-fn dynamic_to_string(value: &Dynamic) -> String {
-    if value.is_string() {
-        value.clone().into_string().unwrap_or_default()
-    } else if value.is_int() {
-        value.as_int().unwrap_or(0).to_string()
-    } else if value.is_float() {
-        let f = value.as_float().unwrap_or(0.0);
-        // Format floats nicely, removing unnecessary decimals
-        if f.fract() == 0.0 {
-            format!("{f:.0}")
-        } else {
-            f.to_string()
-        }
-    } else if value.is_bool() {
-        value.as_bool().unwrap_or(false).to_string()
-    } else {
-        // Fallback for other types
-        value.to_string()
-    }
-}
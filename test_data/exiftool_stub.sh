@@ -0,0 +1,24 @@
+#!/bin/sh
+# Stand-in for `exiftool -stay_open True -@ -` in the exiftool tests. It answers
+# every -execute with a fixed JSON record for the file named last, numbering the
+# requests so the tests can tell that one process served them all.
+[ "$1" = "-stay_open" ] || exit 2
+
+requests=0
+file=
+while IFS= read -r line; do
+    case "$line" in
+        -execute)
+            requests=$((requests + 1))
+            case "$file" in
+                *missing*) ;;
+                *) printf '[{"SourceFile": "%s", "File:FileName": "%s", "EXIF:ISO": 200, "EXIF:DateTimeOriginal": "2024:07:01 18:30:00+02:00", "Stub:Request": %d}]\n' \
+                    "$file" "$(basename "$file")" "$requests" ;;
+            esac
+            echo '{ready}'
+            ;;
+        False) exit 0 ;;
+        -*) ;;
+        *) file=$line ;;
+    esac
+done